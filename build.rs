@@ -45,10 +45,12 @@ mod inner {
     use std::path::Path;
 
     pub fn main() {
-        expand!("src/config.in.rs",    "config.rs");
-        expand!("src/trello.in.rs",    "trello.rs");
-        expand!("src/travis_ci.in.rs", "travis_ci.rs");
-        expand!("src/appveyor.in.rs",  "appveyor.rs");
+        expand!("src/config.in.rs",        "config.rs");
+        expand!("src/trello.in.rs",        "trello.rs");
+        expand!("src/travis_ci.in.rs",     "travis_ci.rs");
+        expand!("src/appveyor.in.rs",      "appveyor.rs");
+        expand!("src/spool.in.rs",         "spool.rs");
+        expand!("src/trello_client.in.rs", "trello_client.rs");
     }
 }
 
@@ -27,20 +27,17 @@ use std::collections::BTreeMap;
 use std::io;
 use std::io::Write;
 
-use std::io::Read;
-
 extern crate term;
 
 extern crate hyper;
-use hyper::Client;
-use hyper::client::Body;
-use hyper::client::IntoUrl;
 use hyper::header::Headers;
 
 use serde_json::Value;
 
+use ci;
 use ci::CITrait;
 use config;
+use error::{self, TrelloBSTError};
 use utils;
 
 
@@ -100,12 +97,6 @@ pub struct GithubResponse {
     organizations: Vec<GroupInfo>
 }
 
-pub struct AppVeyorEncryptedVars {
-    pub trello_api_token: String,
-    pub list_id:          String,
-    pub build_pass_id:    String,
-    pub build_fail_id:    String
-}
 
 ////////////////////////////////////////////////////////////
 //                         Impls                          //
@@ -120,7 +111,7 @@ impl GithubResponse {
         }
     }
 
-    pub fn from_api_call(&mut self, config: &mut config::TrelloBSTConfig) -> Result<(), &'static str> {
+    pub fn from_api_call(&mut self, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
 
         //Do API call.
         let     api_call      = format!("https://ci.appveyor.com/api/repositories/gitHub");
@@ -135,31 +126,31 @@ impl GithubResponse {
         //Parse raw array.
         let data: Value = match serde_json::from_str(&response_body){
             Ok(data) => data,
-            Err(_)   => {return Err("Error parsing the JSON data");}
+            Err(err) => {return Err(TrelloBSTError::parse("the AppVeyor repositories", err));}
         };
 
         //Get group Info.
-        let group_info_array: Vec<Value> = try!(data.as_array().ok_or("Error: The JSON response from GithubResponse is not an array.")).clone();
+        let group_info_array: Vec<Value> = try!(data.as_array().ok_or(TrelloBSTError::parse("the AppVeyor repositories", "The JSON response from GithubResponse is not an array."))).clone();
 
         for group in group_info_array {
 
-            let group_info = try!(group.as_object().ok_or("Error: Expected an array of JSON objects in GithubResponse.")).clone();
+            let group_info = try!(group.as_object().ok_or(TrelloBSTError::parse("the AppVeyor repositories", "Expected an array of JSON objects in GithubResponse."))).clone();
 
-            let group_type_value = try!(group_info.get("groupType").ok_or("Error: Could not find the \"groupType\" field in a GithubResponse object."));
-            let group_type       = try!(group_type_value.as_str().ok_or("Error: Failed to parse the value of \"groupType\" in the GithubResponse object.")).to_string();
+            let group_type_value = try!(group_info.get("groupType").ok_or(TrelloBSTError::parse("the AppVeyor repositories", "Could not find the \"groupType\" field in a GithubResponse object.")));
+            let group_type       = try!(group_type_value.as_str().ok_or(TrelloBSTError::parse("the AppVeyor repositories", "Failed to parse the value of \"groupType\" in the GithubResponse object."))).to_string();
 
             if group_type == "user" {
                 match serde_json::from_value(group) {
                     Ok(parsed_group) => self.users.push(parsed_group),
-                    Err(_)           => return Err("Error while parsing a group info object in GithubResponse")
+                    Err(err)         => return Err(TrelloBSTError::parse("the AppVeyor repositories", err))
                 }
             } else if group_type == "organization" {
                 match serde_json::from_value(group) {
                     Ok(parsed_group) => self.organizations.push(parsed_group),
-                    Err(_)           => return Err("Error while parsing a group info object in GithubResponse")
+                    Err(err)         => return Err(TrelloBSTError::parse("the AppVeyor repositories", err))
                 }
             } else {
-                return Err("Error: Invalid group type found in the \"groupType\" field in a GithubResponse object.")
+                return Err(TrelloBSTError::parse("the AppVeyor repositories", "Invalid group type found in the \"groupType\" field in a GithubResponse object."))
             }
         }
 
@@ -179,7 +170,7 @@ impl CITrait for AppVeyor {
 
     fn get_name(&mut self) -> String {return "AppVeyor".to_string();}
 
-    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), String> {
+    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
 
         //API key
         if config.get("appveyor_api_token").is_empty() {
@@ -194,7 +185,7 @@ impl CITrait for AppVeyor {
         Ok(())
     }
 
-    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), String> {
+    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), TrelloBSTError> {
 
         //Select Repo
         let mut repo_tag = String::new();
@@ -219,10 +210,12 @@ impl CITrait for AppVeyor {
         //Generate File
         let mut file_data = include_str!("templates/appveyor").to_string();;
 
-        file_data = file_data.replace("<TRELLO_API_TOKEN>",         &encrypted_vars.trello_api_token[..]);
-        file_data = file_data.replace("<TRELLO_API_LIST_ID>",       &encrypted_vars.list_id[..]);
-        file_data = file_data.replace("<TRELLO_API_BUILD_PASS_ID>", &encrypted_vars.build_pass_id[..]);
-        file_data = file_data.replace("<TRELLO_API_BUILD_FAIL_ID>", &encrypted_vars.build_fail_id[..]);
+        let mut secure_env_vars = String::new();
+        for &(ref name, ref encrypted_var) in &encrypted_vars {
+            secure_env_vars.push_str(&format!("  {}:\n    secure: {}\n", name, encrypted_var));
+        }
+
+        file_data = file_data.replace("<TRELLO_SECURE_ENV_VARS>\n", &secure_env_vars[..]);
 
         Ok((self.get_filename(), file_data))
     }
@@ -230,10 +223,8 @@ impl CITrait for AppVeyor {
 
 impl AppVeyor {
 
-    pub fn appveyor_encrypt_var(&mut self, config: &mut config::TrelloBSTConfig, var: &str) -> Result<String, String> {
+    pub fn appveyor_encrypt_var(&mut self, config: &mut config::TrelloBSTConfig, value: &str) -> Result<String, TrelloBSTError> {
 
-        let     http_client   = Client::new();
-        let mut response_body = String::new();
         let     api_call      = format!("https://ci.appveyor.com/api/account/encrypt");
         let mut header        = Headers::new();
         let     auth          = format!("Bearer {}", config.get("appveyor_api_token"));
@@ -241,46 +232,31 @@ impl AppVeyor {
         header.set_raw("Authorization",  vec![auth.into_bytes()]);
         header.set_raw("Content-Type",   vec![b"application/json;charset=utf-8".to_vec()]);
 
-        let api_call_url = match api_call.into_url() {
-            Ok(url) => url,
-            Err(_)  => {return Err("Error while parsing API call url.".to_string());}
-        };
-
         let mut body: String = "{\"plainValue\":\"".to_string();
-        body.push_str(&config.get(var)[..]);
+        body.push_str(value);
         body.push_str("\"}");
 
-        let body_len = body.len().clone();
-        let mut response = match http_client.post(api_call_url)
-            .headers(header)
-            .body(Body::BufBody(&body.into_bytes()[..], body_len))
-            .send() {
-            Ok(res) => res,
-            Err(_)  => {return Err("Error calling the API.".to_string());}
-        };
-
-        match response.read_to_string(&mut response_body){
-            Ok(_)  => (),
-            Err(_) => {return Err("Error converting the API response to a string.".to_string());}
-        }
+        let response_body = try!(utils::rest_api_call(utils::Method::Post, &api_call, header, Option::Some(body.as_bytes())));
 
         if response_body.contains("{\"message\":") {
-            return Err("Error encrypting variable.".to_string())
+            return Err(TrelloBSTError::Api {request: format!("POST {}", api_call), message: error::api_message(&response_body)}.context("Error encrypting variable"));
         }
 
         Ok(response_body)
     }
 
-    pub fn encrypt_vars(&mut self, config: &mut config::TrelloBSTConfig) -> Result<AppVeyorEncryptedVars, String> {
-        Ok(AppVeyorEncryptedVars{
-            trello_api_token: try!(self.appveyor_encrypt_var(config, "trello_api_token")),
-            list_id:          try!(self.appveyor_encrypt_var(config, "trello_list_id")),
-            build_pass_id:    try!(self.appveyor_encrypt_var(config, "trello_label_pass_id")),
-            build_fail_id:    try!(self.appveyor_encrypt_var(config, "trello_label_fail_id"))
-        })
+    pub fn encrypt_vars(&mut self, config: &mut config::TrelloBSTConfig) -> Result<Vec<(String, String)>, TrelloBSTError> {
+
+        let mut encrypted_vars = Vec::new();
+        for (name, value) in ci::trello_env_vars(config) {
+            let encrypted_var = try!(self.appveyor_encrypt_var(config, &value));
+            encrypted_vars.push((name, encrypted_var));
+        }
+
+        Ok(encrypted_vars)
     }
 
-    pub fn repo_selection(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, repo_tag: &mut String) -> Result<(), String> {
+    pub fn repo_selection(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, repo_tag: &mut String) -> Result<(), TrelloBSTError> {
 
         let mut status      = utils::StatusPrint::from_str(term, "Acquiring the repo list from AppVeyor.");
         let mut groups_info = GithubResponse::new();
@@ -288,7 +264,7 @@ impl AppVeyor {
             Ok(())   => status.success(term),
             Err(err) => {
                 status.error(term);
-                return Err(err.to_string())
+                return Err(err)
             }
         }
 
@@ -331,13 +307,14 @@ impl AppVeyor {
         }
 
         //Get selected repo.
-        let repo = try!(repos.get(&option).ok_or("Error: Faied to acquire the repo information for the selected option.")).clone();
+        if option == 0 {
+            return Err(TrelloBSTError::UserAbort);
+        }
+        let repo = try!(repos.get(&option).ok_or(TrelloBSTError::Validation("Error: Invalid repository selection.".to_string()))).clone();
 
         //Link repo.
         //NOTE: This is pretty hacky...
         status                = utils::StatusPrint::from_str(term, "Adding the repository to AppVeyor.");
-        let     http_client   = Client::new();
-        let mut response_body = String::new();
         let     api_call      = format!("https://ci.appveyor.com/api/projects");
         let mut header        = Headers::new();
         let     auth          = format!("Bearer {}", config.get("appveyor_api_token"));
@@ -345,43 +322,18 @@ impl AppVeyor {
         header.set_raw("Authorization",  vec![auth.into_bytes()]);
         header.set_raw("Content-Type",   vec![b"application/json".to_vec()]);
 
-        let api_call_url = match api_call.into_url() {
-            Ok(url) => url,
-            Err(_)  => {
-                status.error(term);
-                return Err("Error while parsing API call url.".to_string())
-            }
-        };
-
         let mut body: String = "{\"repositoryProvider\":\"gitHub\", \"repositoryName\":\"".to_string();
         body.push_str(&repo.full_name[..]);
         body.push_str("\"}");
 
-        let     body_len = body.len().clone();
-        let mut response = match http_client.post(api_call_url)
-            .headers(header)
-            .body(Body::BufBody(&body.into_bytes()[..], body_len))
-            .send() {
-            Ok(res) => res,
-            Err(_)  => {
-                status.error(term);
-                return Err("Error calling the API.".to_string())
-            }
-        };
-
-        match response.read_to_string(&mut response_body){
-            Ok(_)  => (),
-            Err(_) => {
+        match utils::rest_api_call(utils::Method::Post, &api_call, header, Option::Some(body.as_bytes())) {
+            Ok(_)    => status.success(term),
+            Err(err) => {
                 status.error(term);
-                return Err("Error converting the API response to a string.".to_string())
+                return Err(err.context(format!("Error while adding {} to AppVeyor", repo.full_name)));
             }
         }
 
-        if response_body.contains("{\"message\":") {
-            status.error(term);
-        }
-
-        status.success(term);
         *repo_tag = repo.full_name;
         Ok(())
     }
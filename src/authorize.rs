@@ -0,0 +1,345 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+extern crate url;
+use self::url::form_urlencoded;
+
+use error::TrelloBSTError;
+use trello_client;
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//Trello puts the token in the url fragment, which browsers don't send, so the callback page forwards it to the
+//token path next to it, ex: from /<nonce>/callback to /<nonce>/token.
+const CALLBACK_PAGE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head><title>TrelloBST</title></head>
+<body>
+<p id="message">Sending the token to TrelloBST...</p>
+<script>
+var match = /[#&]token=([^&]+)/.exec(window.location.hash);
+if (match) {
+    window.location.replace("token?token=" + encodeURIComponent(match[1]));
+} else {
+    document.getElementById("message").textContent = "Trello did not give a token, go back to TrelloBST to paste it instead.";
+}
+</script>
+</body>
+</html>
+"#;
+
+const DONE_PAGE: &'static str = "<!DOCTYPE html>\n<html><body><p>TrelloBST received the token, you can close this window.</p></body></html>\n";
+
+//Token lifetimes and scopes accepted by Trello's authorize page.
+pub const EXPIRATIONS: [&'static str; 3] = ["1day", "30days", "never"];
+pub const SCOPES:      [&'static str; 3] = ["read", "write", "account"];
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//Lifetime and scopes of the token the setup asks Trello for.
+//NOTE: Pushing build statuses needs the "write" scope, parse refuses scopes without it.
+#[derive(Clone)]
+pub struct TokenRequest {
+    pub expiration: String,
+    pub scopes:     Vec<String>
+}
+
+//Short-lived HTTP listener on 127.0.0.1 receiving the token from Trello's authorize page.
+//NOTE: Any local process or web page can reach the listener, requests must have the random nonce of the return url.
+pub struct TokenListener {
+    listener: TcpListener,
+    port:     u16,
+    nonce:    String
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl TokenRequest {
+
+    //The token TrelloBST always asked for, it doesn't expire.
+    pub fn default() -> TokenRequest {
+        TokenRequest {
+            expiration: "never".to_string(),
+            scopes:     vec!["read".to_string(), "write".to_string()]
+        }
+    }
+
+    //ex: ("30days", "read,write")
+    pub fn parse(expiration: &str, scopes: &str) -> Result<TokenRequest, TrelloBSTError> {
+
+        if !EXPIRATIONS.contains(&expiration) {
+            return Err(TrelloBSTError::Validation(format!("Error: Invalid token expiration \"{}\", expected one of: {}.", expiration, EXPIRATIONS.join(", "))));
+        }
+
+        let mut token_scopes: Vec<String> = Vec::new();
+        for scope in scopes.split(',').map(|scope| scope.trim()).filter(|scope| !scope.is_empty()) {
+            if !SCOPES.contains(&scope) {
+                return Err(TrelloBSTError::Validation(format!("Error: Invalid token scope \"{}\", expected a comma separated list of: {}.", scope, SCOPES.join(", "))));
+            }
+            if !token_scopes.iter().any(|token_scope| token_scope == scope) {
+                token_scopes.push(scope.to_string());
+            }
+        }
+        if !token_scopes.iter().any(|scope| scope == "write") {
+            return Err(TrelloBSTError::Validation("Error: The token needs the write scope, build statuses can't be pushed without it.".to_string()));
+        }
+
+        Ok(TokenRequest {
+            expiration: expiration.to_string(),
+            scopes:     token_scopes
+        })
+    }
+
+    //ex: "read,write"
+    pub fn scope(&self) -> String {
+        self.scopes.join(",")
+    }
+}
+
+impl TokenListener {
+
+    //Listens on a port picked by the OS.
+    pub fn bind() -> Result<TokenListener, TrelloBSTError> {
+
+        let listener = try!(TcpListener::bind("127.0.0.1:0").map_err(|err| local_error("Error: Failed to listen on 127.0.0.1", err)));
+        let port     = try!(listener.local_addr().map_err(|err| local_error("Error: Failed to get the listening port", err))).port();
+
+        //Non blocking so that wait_for_token can give up.
+        try!(listener.set_nonblocking(true).map_err(|err| local_error("Error: Failed to set up the listener", err)));
+
+        Ok(TokenListener {
+            listener: listener,
+            port:     port,
+            nonce:    nonce()
+        })
+    }
+
+    //ex: "http://127.0.0.1:51234/3f9a0c.../callback"
+    pub fn return_url(&self) -> String {
+        format!("http://127.0.0.1:{}/{}/callback", self.port, self.nonce)
+    }
+
+    //Serves the callback page until the token comes back or until the timeout.
+    pub fn wait_for_token(&self, timeout_secs: u64) -> Result<String, TrelloBSTError> {
+
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+
+        while Instant::now() < deadline {
+            match self.listener.accept() {
+                Ok((mut stream, _)) => {
+                    //NOTE: A broken request from the browser isn't fatal, it will retry or the timeout will end the wait.
+                    if let Ok(Some(token)) = handle_request(&mut stream, &self.nonce) {
+                        return Ok(token);
+                    }
+                }
+                Err(_) => thread::sleep(Duration::from_millis(100))
+            }
+        }
+
+        Err(TrelloBSTError::Transport {
+            request: format!("http://127.0.0.1:{}", self.port),
+            cause:   format!("No token was received from the browser within {} seconds.", timeout_secs)
+        })
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Url of Trello's authorize page, with a return url Trello redirects to with the token instead of showing it.
+pub fn authorize_url(api_key: &str, token_request: &TokenRequest, return_url: Option<&str>) -> String {
+
+    let mut url = format!("https://trello.com/1/authorize?response_type=token&key={}&scope={}&expiration={}&name=TrelloBST",
+                          api_key, trello_client::encode(&token_request.scope()), trello_client::encode(&token_request.expiration));
+
+    if let Some(return_url) = return_url {
+        url.push_str(&format!("&callback_method=fragment&return_url={}", trello_client::encode(return_url)));
+    }
+
+    url
+}
+
+//Opens the url in the default browser, false if it could not be opened.
+pub fn open_browser(url: &str) -> bool {
+
+    let status = if cfg!(target_os = "windows") {
+        //NOTE: "cmd /C start" would split the url at its "&".
+        Command::new("rundll32").arg("url.dll,FileProtocolHandler").arg(url).status()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(url).status()
+    } else {
+        Command::new("xdg-open").arg(url).status()
+    };
+
+    match status {
+        Ok(status) => status.success(),
+        Err(_)     => false
+    }
+}
+
+//Answers one request, returns the token once the callback page sends it.
+//NOTE: Paths without the nonce are not found, whatever sent them.
+fn handle_request(stream: &mut TcpStream, nonce: &str) -> Result<Option<String>, TrelloBSTError> {
+
+    try!(stream.set_nonblocking(false).map_err(|err| local_error("Error: Failed to read the browser's request", err)));
+    try!(stream.set_read_timeout(Option::Some(Duration::from_secs(5))).map_err(|err| local_error("Error: Failed to read the browser's request", err)));
+
+    //Only the request line is needed, ex: "GET /token?token=... HTTP/1.1"
+    let mut buffer  = [0; 8192];
+    let     read    = try!(stream.read(&mut buffer).map_err(|err| local_error("Error: Failed to read the browser's request", err)));
+    let     request = String::from_utf8_lossy(&buffer[..read]).to_string();
+    let     target  = request.lines().next().unwrap_or("").split_whitespace().nth(1).unwrap_or("").to_string();
+
+    let (path, query) = match target.find('?') {
+        Some(index) => (&target[..index], &target[index + 1..]),
+        None        => (&target[..], "")
+    };
+
+    let callback_path = format!("/{}/callback", nonce);
+    let token_path    = format!("/{}/token", nonce);
+
+    match path {
+        _ if path == callback_path => {
+            try!(respond(stream, "200 OK", CALLBACK_PAGE));
+            Ok(Option::None)
+        }
+        _ if path == token_path => {
+            let token = form_urlencoded::parse(query.as_bytes())
+                        .find(|&(ref name, _)| *name == "token")
+                        .map(|(_, value)| value.trim().to_string())
+                        .unwrap_or(String::new());
+
+            //Trello tokens are alphanumeric, anything else didn't come from the callback page.
+            if token.is_empty() || !token.chars().all(|c| c.is_alphanumeric()) {
+                try!(respond(stream, "400 Bad Request", "Invalid token.\n"));
+                return Ok(Option::None);
+            }
+
+            try!(respond(stream, "200 OK", DONE_PAGE));
+            Ok(Option::Some(token))
+        }
+        _ => {
+            try!(respond(stream, "404 Not Found", "Not found.\n"));
+            Ok(Option::None)
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> Result<(), TrelloBSTError> {
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    stream.write_all(response.as_bytes()).map_err(|err| local_error("Error: Failed to answer the browser", err))
+}
+
+//128 random bits as hex, the hashers' keys come from the OS's random number generator.
+fn nonce() -> String {
+    let mut nonce = String::new();
+    for part in 0..2u8 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(part);
+        nonce.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    nonce
+}
+
+fn local_error<T: fmt::Display>(context: &str, err: T) -> TrelloBSTError {
+    TrelloBSTError::Transport {request: "127.0.0.1".to_string(), cause: err.to_string()}.context(context)
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    //Sends a request line to handle_request, returns its result and the response's status line.
+    fn request(target: &str, nonce: &str) -> (Option<String>, String) {
+        let listener   = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target).as_bytes()).unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let token           = handle_request(&mut stream, nonce).unwrap();
+        drop(stream);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        (token, response.lines().next().unwrap_or("").to_string())
+    }
+
+    #[test]
+    fn token_with_nonce_is_accepted() {
+        assert_eq!(request("/abc123/token?token=0123abcd", "abc123"), (Some("0123abcd".to_string()), "HTTP/1.1 200 OK".to_string()));
+        assert_eq!(request("/abc123/callback", "abc123").1, "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn token_without_nonce_is_rejected() {
+        assert_eq!(request("/token?token=0123abcd", "abc123"), (None, "HTTP/1.1 404 Not Found".to_string()));
+        assert_eq!(request("/other/token?token=0123abcd", "abc123"), (None, "HTTP/1.1 404 Not Found".to_string()));
+        assert_eq!(request("/callback", "abc123").1, "HTTP/1.1 404 Not Found");
+    }
+
+    #[test]
+    fn token_request_needs_write() {
+        assert_eq!(TokenRequest::parse("30days", "read, write,read").unwrap().scope(), "read,write");
+        assert!(TokenRequest::parse("30days", "read").is_err());
+        assert!(TokenRequest::parse("30days", "").is_err());
+        assert!(TokenRequest::parse("1week", "read,write").is_err());
+    }
+
+    #[test]
+    fn nonces_are_random() {
+        let first = nonce();
+        assert_eq!(first.len(), 32);
+        assert!(first != nonce());
+    }
+}
@@ -0,0 +1,160 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+use error::TrelloBSTError;
+use trello_client::TrelloClient;
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//Branch label colours, green and red are left to the build statuses.
+const BRANCH_LABEL_COLORS: [&'static str; 8] = ["yellow", "orange", "purple", "blue", "sky", "lime", "pink", "black"];
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the id of the label named after the branch on the list's board, the label is created if missing.
+//Board and label ids are cached in ~/.TrelloBST.labels so repeated builds don't query the board.
+pub fn branch_label_id(api_key: &str, trello_api_token: &str, list_id: &str, branch: &str) -> Result<String, TrelloBSTError> {
+
+    let     client = TrelloClient::new(api_key, trello_api_token);
+    let mut cache  = load_cache();
+
+    //Board of the list
+    let board_key = format!("list:{}", list_id);
+    let board_id  = match cache.get(&board_key).cloned() {
+        Some(board_id) => board_id,
+        None           => {
+            let board_id = try!(client.list(list_id)).id_board;
+            cache.insert(board_key, board_id.clone());
+            board_id
+        }
+    };
+
+    //Branch label
+    let label_key = format!("label:{}:{}", board_id, branch);
+    if let Some(label_id) = cache.get(&label_key).cloned() {
+        return Ok(label_id);
+    }
+
+    let label_id = match try!(client.board_labels(&board_id)).into_iter().find(|label| label.name == branch) {
+        Some(label) => label.id,
+        None        => try!(client.create_label(&board_id, branch, label_color(branch))).id
+    };
+
+    cache.insert(label_key, label_id.clone());
+    save_cache(&cache);
+
+    Ok(label_id)
+}
+
+//Drops the cached board and label of a branch, ex: once the label was deleted from the board.
+pub fn forget_branch_label(list_id: &str, branch: &str) {
+
+    let mut cache = load_cache();
+    if let Some(board_id) = cache.remove(&format!("list:{}", list_id)) {
+        cache.remove(&format!("label:{}:{}", board_id, branch));
+    }
+    save_cache(&cache);
+}
+
+//Trello rejects cards with an unknown label id, a cached label may have been deleted since.
+pub fn is_stale_label_error(err: &TrelloBSTError) -> bool {
+    match *err.root() {
+        TrelloBSTError::HttpStatus {status, ..} => status == 400 || status == 404,
+        _                                       => false
+    }
+}
+
+//The same branch always gets the same colour.
+pub fn label_color(branch: &str) -> &'static str {
+
+    //FNV-1a
+    let mut hash: u32 = 2166136261;
+    for byte in branch.as_bytes() {
+        hash ^= *byte as u32;
+        hash  = hash.wrapping_mul(16777619);
+    }
+
+    BRANCH_LABEL_COLORS[hash as usize % BRANCH_LABEL_COLORS.len()]
+}
+
+fn cache_path() -> PathBuf {
+    match env::home_dir() {
+        Some(mut home_dir) => {
+            home_dir.push(".TrelloBST.labels");
+            home_dir
+        }
+        None => PathBuf::from("./.TrelloBST.labels")
+    }
+}
+
+//NOTE: The cache is only an optimisation, a missing or broken cache file is treated as empty.
+fn load_cache() -> BTreeMap<String, String> {
+
+    let mut cache     = BTreeMap::new();
+    let mut file_data = String::new();
+    match File::open(cache_path()).and_then(|mut file| file.read_to_string(&mut file_data)) {
+        Ok(_)  => (),
+        Err(_) => return cache
+    }
+
+    if let Ok(Value::Object(object)) = serde_json::from_str::<Value>(&file_data) {
+        for (key, val) in object {
+            if let Value::String(val) = val {
+                cache.insert(key, val);
+            }
+        }
+    }
+
+    cache
+}
+
+fn save_cache(cache: &BTreeMap<String, String>) {
+
+    let mut json_map: BTreeMap<String, Value> = BTreeMap::new();
+    for (key, val) in cache {
+        json_map.insert(key.clone(), Value::String(val.clone()));
+    }
+
+    if let Ok(json_string) = serde_json::to_string(&Value::Object(json_map)) {
+        if let Ok(mut file) = File::create(cache_path()) {
+            let _ = file.write_all(json_string.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::process::Command;
+
+use ci_env::CIEnvironment;
+use error::TrelloBSTError;
+use push::PushConfig;
+use status::BuildStatus;
+use trello_client::TrelloClient;
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//Starts the description line holding the branch and commit of a card pushed with --list-changes.
+//ex: "TrelloBST-Commit: master 0123456789abcdef0123456789abcdef01234567"
+pub const COMMIT_MARKER: &'static str = "TrelloBST-Commit:";
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+pub struct Commit {
+    pub author:    String,
+    pub short_sha: String,
+    pub subject:   String
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the branch and the commit being built from the CI's env vars, else from git.
+pub fn build_commit() -> Option<(String, String)> {
+
+    let (mut branch, mut sha) = match CIEnvironment::detect() {
+        Some(ci_env) => (ci_env.branch, ci_env.commit),
+        None         => (String::new(), String::new())
+    };
+
+    if branch.is_empty() {
+        branch = git_output(&["rev-parse", "--abbrev-ref", "HEAD"]);
+    }
+    if sha.is_empty() {
+        sha = git_output(&["rev-parse", "HEAD"]);
+    }
+
+    if branch.is_empty() || branch == "HEAD" || !is_sha(&sha) {
+        return Option::None;
+    }
+    Option::Some((branch, sha.to_lowercase()))
+}
+
+//The line added at the top of the card description, see COMMIT_MARKER.
+pub fn marker(branch: &str, sha: &str) -> String {
+    format!("{} {} {}", COMMIT_MARKER, branch, sha)
+}
+
+//Finds the commit of the most recent passing card of the branch, None if there is no such card.
+//NOTE: Only cards pushed with --list-changes have the commit marker.
+pub fn last_passing_commit(api_key: &String, push_data: &PushConfig, branch: &str) -> Result<Option<String>, TrelloBSTError> {
+
+    let pass_label = push_data.label_id(BuildStatus::Passed);
+    let cards      = try!(TrelloClient::new(api_key, &push_data.trello_api_token).list_cards(&push_data.list_id(BuildStatus::Passed)));
+
+    //NOTE: Cards are always pushed at the top of the list, so the first match is the most recent one.
+    for card in &cards {
+        if let Some(ref pass_label) = pass_label {
+            if !card.id_labels.contains(pass_label) {
+                continue;
+            }
+        }
+        match find_marker(&card.desc) {
+            Some((ref card_branch, ref sha)) if card_branch == branch => return Ok(Option::Some(sha.clone())),
+            _                                                         => ()
+        }
+    }
+
+    Ok(Option::None)
+}
+
+//Gets the commits between the given commit and HEAD from "git log".
+pub fn commits_since(sha: &str) -> Result<Vec<Commit>, TrelloBSTError> {
+
+    let output = match Command::new("git").arg("log").arg("--format=%h%x09%an%x09%s").arg(format!("{}..HEAD", sha)).output() {
+        Ok(output) => output,
+        Err(_)     => return Err(TrelloBSTError::Validation("Error: Failed to run \"git log\".".to_string()))
+    };
+
+    if !output.status.success() {
+        return Err(TrelloBSTError::Validation("Error: \"git log\" failed, the commit of the last passing build may not be in this checkout (shallow clone?).".to_string()));
+    }
+
+    let mut commits = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.splitn(3, '\t').collect();
+        if fields.len() == 3 {
+            commits.push(Commit {
+                short_sha: fields[0].to_string(),
+                author:    fields[1].to_string(),
+                subject:   fields[2].to_string()
+            });
+        }
+    }
+
+    Ok(commits)
+}
+
+//Renders the commits as a Markdown section for the card description.
+pub fn render(sha: &str, commits: &Vec<Commit>) -> String {
+
+    let short_sha: String = sha.chars().take(7).collect();
+    let mut section = format!("**Changes since the last passing build ({})**\n", short_sha);
+
+    if commits.is_empty() {
+        section.push_str("\nNo new commits.");
+        return section;
+    }
+
+    for commit in commits {
+        section.push_str(&format!("\n* `{}` {} - *{}*", commit.short_sha, commit.subject, commit.author));
+    }
+
+    section
+}
+
+//Gets the branch and commit of the commit marker line of a description.
+pub fn find_marker(desc: &str) -> Option<(String, String)> {
+
+    for line in desc.lines() {
+        let line = line.trim();
+        if !line.starts_with(COMMIT_MARKER) {
+            continue;
+        }
+
+        let fields: Vec<&str> = line[COMMIT_MARKER.len()..].split_whitespace().collect();
+        if fields.len() == 2 && is_sha(fields[1]) {
+            return Option::Some((fields[0].to_string(), fields[1].to_lowercase()));
+        }
+    }
+
+    Option::None
+}
+
+//Full commit SHA, 40 hex digits.
+fn is_sha(sha: &str) -> bool {
+    sha.len() == 40 && sha.chars().all(|c| c.is_digit(16))
+}
+
+fn git_output(args: &[&str]) -> String {
+    match Command::new("git").args(args).output() {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        _                                         => String::new()
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const SHA: &'static str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn find_marker_reads_the_marker_line() {
+        let desc = format!("{}\n[Logs]\\: https://ci.example.com/1\n", marker("feature]x", SHA));
+        assert_eq!(find_marker(&desc), Some(("feature]x".to_string(), SHA.to_string())));
+    }
+
+    #[test]
+    fn find_marker_ignores_free_text() {
+        assert_eq!(find_marker(&format!("[Commit]\\: https://github.com/a/b/commit/{}", SHA)), None);
+        assert_eq!(find_marker(&format!("Log:\n{}\n", SHA)), None);
+        assert_eq!(find_marker("TrelloBST-Commit: master 1234"), None);
+    }
+}
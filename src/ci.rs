@@ -29,6 +29,9 @@ use std::cell::RefCell;
 extern crate term;
 
 use config;
+use custom_fields::BuildField;
+use error::TrelloBSTError;
+use status::{BuildStatus, ListStage};
 use utils;
 
 
@@ -40,8 +43,8 @@ use utils;
 pub trait CITrait {
     fn get_filename(&mut self) -> String;
     fn get_name(&mut self) -> String;
-    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), String>;
-    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), String>;
+    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError>;
+    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), TrelloBSTError>;
 }
 
 
@@ -65,7 +68,7 @@ impl CI {
         self.ci_map.insert(ci.get_name().to_string(), RefCell::new(ci));
     }
 
-    pub fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), String> {
+    pub fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), TrelloBSTError> {
 
         //Select CI
         let mut ci_select = utils::MenuBuilder::new("Which Continuous Integration provider do you want a configuration for?".to_string());
@@ -88,3 +91,44 @@ impl CI {
         }
     }
 }
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the environment variables used by "TrelloBST push" as (name, value) pairs, unset list and label ids are skipped.
+pub fn trello_env_vars(config: &mut config::TrelloBSTConfig) -> Vec<(String, String)> {
+
+    let mut env_vars = Vec::new();
+
+    env_vars.push(("TRELLO_API_TOKEN".to_string(), config.get("trello_api_token")));
+
+    let list_id = config.get("trello_list_id");
+    if !list_id.is_empty() {
+        env_vars.push(("TRELLO_API_LIST_ID".to_string(), list_id));
+    }
+
+    for stage in ListStage::all() {
+        let list_id = config.get(&stage.config_key());
+        if !list_id.is_empty() {
+            env_vars.push((stage.env_var(), list_id));
+        }
+    }
+
+    for status in BuildStatus::all() {
+        let label_id = config.get(&status.config_key());
+        if !label_id.is_empty() {
+            env_vars.push((status.env_var(), label_id));
+        }
+    }
+
+    for field in BuildField::all() {
+        let field_id = config.get(&field.config_key());
+        if !field_id.is_empty() {
+            env_vars.push((field.env_var(), field_id));
+        }
+    }
+
+    env_vars
+}
@@ -0,0 +1,308 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::env;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//Build information gathered from the environment variables set by the CI provider.
+#[derive(Clone)]
+pub struct CIEnvironment {
+    pub provider:     String,
+    pub branch:       String,
+    pub build_number: String,
+    pub os:           String,
+    pub compiler:     String,
+    pub repo_slug:    String,
+    pub commit:       String,
+    pub author_email: String,
+    pub log_url:      String,
+    pub commit_url:   String,
+    //Unix time the build started at, if the CI gives it.
+    pub started_at:   Option<u64>
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl CIEnvironment {
+
+    //Detects the CI provider from its environment variables, None if we're not running on a known CI.
+    pub fn detect() -> Option<CIEnvironment> {
+
+        if is_set("TRAVIS") {
+            let repo_slug = var("TRAVIS_REPO_SLUG");
+            let commit    = var("TRAVIS_COMMIT");
+            let mut log_url = var("TRAVIS_JOB_WEB_URL");
+            if log_url.is_empty() {
+                log_url = format!("https://travis-ci.org/{}/jobs/{}", repo_slug, var("TRAVIS_JOB_ID"));
+            }
+            return Option::Some(CIEnvironment {
+                provider:     "Travis-CI".to_string(),
+                started_at:   Option::None,
+                branch:       first_of(&["TRAVIS_PULL_REQUEST_BRANCH", "TRAVIS_BRANCH"]),
+                build_number: var("TRAVIS_BUILD_NUMBER"),
+                os:           var("TRAVIS_OS_NAME"),
+                compiler:     first_of(&["TRAVIS_COMPILER", "COMPILER", "CC"]),
+                commit_url:   github_commit_url("https://github.com", &repo_slug, &commit),
+                repo_slug:    repo_slug,
+                commit:       commit,
+                author_email: String::new(),
+                log_url:      log_url
+            });
+        }
+
+        if is_set("APPVEYOR") {
+            let repo_slug = var("APPVEYOR_REPO_NAME");
+            let commit    = var("APPVEYOR_REPO_COMMIT");
+            let image     = var("APPVEYOR_BUILD_WORKER_IMAGE").to_lowercase();
+            let os        = if image.contains("ubuntu") {"linux"} else if image.contains("macos") {"osx"} else {"windows"};
+            let commit_url = if var("APPVEYOR_REPO_PROVIDER").to_lowercase() == "github" {
+                github_commit_url("https://github.com", &repo_slug, &commit)
+            } else {
+                String::new()
+            };
+            return Option::Some(CIEnvironment {
+                provider:     "AppVeyor".to_string(),
+                started_at:   Option::None,
+                branch:       first_of(&["APPVEYOR_PULL_REQUEST_HEAD_REPO_BRANCH", "APPVEYOR_REPO_BRANCH"]),
+                build_number: var("APPVEYOR_BUILD_NUMBER"),
+                os:           os.to_string(),
+                compiler:     first_of(&["COMPILER", "CC"]),
+                log_url:      format!("https://ci.appveyor.com/project/{}/{}/build/{}/job/{}", var("APPVEYOR_ACCOUNT_NAME"), var("APPVEYOR_PROJECT_SLUG"), var("APPVEYOR_BUILD_VERSION"), var("APPVEYOR_JOB_ID")),
+                commit_url:   commit_url,
+                repo_slug:    repo_slug,
+                commit:       commit,
+                author_email: var("APPVEYOR_REPO_COMMIT_AUTHOR_EMAIL")
+            });
+        }
+
+        if is_set("GITHUB_ACTIONS") {
+            let server    = if is_set("GITHUB_SERVER_URL") {var("GITHUB_SERVER_URL")} else {"https://github.com".to_string()};
+            let repo_slug = var("GITHUB_REPOSITORY");
+            let commit    = var("GITHUB_SHA");
+            let mut branch = first_of(&["GITHUB_HEAD_REF", "GITHUB_REF_NAME"]);
+            if branch.is_empty() {
+                branch = var("GITHUB_REF").replace("refs/heads/", "");
+            }
+            return Option::Some(CIEnvironment {
+                provider:     "GitHub Actions".to_string(),
+                started_at:   Option::None,
+                branch:       branch,
+                build_number: var("GITHUB_RUN_NUMBER"),
+                os:           var("RUNNER_OS").to_lowercase(),
+                compiler:     first_of(&["COMPILER", "CC"]),
+                log_url:      format!("{}/{}/actions/runs/{}", server, repo_slug, var("GITHUB_RUN_ID")),
+                commit_url:   github_commit_url(&server, &repo_slug, &commit),
+                repo_slug:    repo_slug,
+                commit:       commit,
+                author_email: String::new()
+            });
+        }
+
+        if is_set("GITLAB_CI") {
+            let commit = var("CI_COMMIT_SHA");
+            return Option::Some(CIEnvironment {
+                provider:     "GitLab CI".to_string(),
+                started_at:   parse_utc_time(&var("CI_JOB_STARTED_AT")),
+                branch:       first_of(&["CI_MERGE_REQUEST_SOURCE_BRANCH_NAME", "CI_COMMIT_REF_NAME"]),
+                build_number: first_of(&["CI_PIPELINE_IID", "CI_PIPELINE_ID"]),
+                os:           var("CI_RUNNER_EXECUTABLE_ARCH").split('/').next().unwrap_or("").to_string(),
+                compiler:     first_of(&["COMPILER", "CC"]),
+                repo_slug:    var("CI_PROJECT_PATH"),
+                log_url:      var("CI_JOB_URL"),
+                commit_url:   format!("{}/-/commit/{}", var("CI_PROJECT_URL"), commit),
+                commit:       commit,
+                author_email: email_from_author(&var("CI_COMMIT_AUTHOR"))
+            });
+        }
+
+        if is_set("CIRCLECI") {
+            let repo_slug = format!("{}/{}", var("CIRCLE_PROJECT_USERNAME"), var("CIRCLE_PROJECT_REPONAME"));
+            let commit    = var("CIRCLE_SHA1");
+            return Option::Some(CIEnvironment {
+                provider:     "CircleCI".to_string(),
+                started_at:   Option::None,
+                branch:       var("CIRCLE_BRANCH"),
+                build_number: var("CIRCLE_BUILD_NUM"),
+                os:           env::consts::OS.to_string(),
+                compiler:     first_of(&["COMPILER", "CC"]),
+                log_url:      var("CIRCLE_BUILD_URL"),
+                commit_url:   github_commit_url("https://github.com", &repo_slug, &commit),
+                repo_slug:    repo_slug,
+                commit:       commit,
+                author_email: String::new()
+            });
+        }
+
+        if is_set("JENKINS_URL") {
+            return Option::Some(CIEnvironment {
+                provider:     "Jenkins".to_string(),
+                started_at:   Option::None,
+                branch:       first_of(&["BRANCH_NAME", "GIT_BRANCH"]).replace("origin/", ""),
+                build_number: var("BUILD_NUMBER"),
+                os:           env::consts::OS.to_string(),
+                compiler:     first_of(&["COMPILER", "CC"]),
+                repo_slug:    var("JOB_NAME"),
+                commit:       var("GIT_COMMIT"),
+                author_email: first_of(&["GIT_AUTHOR_EMAIL", "GIT_COMMITTER_EMAIL"]),
+                log_url:      format!("{}console", var("BUILD_URL")),
+                commit_url:   String::new()
+            });
+        }
+
+        Option::None
+    }
+
+    //ex: "[master][Travis-CI][linux][gcc]: #12", the build status is added when the card is pushed.
+    //NOTE: The tags are in the order used by update and prune keys (branch, ci, os, compiler).
+    pub fn title(&self) -> String {
+
+        let mut title = format!("[{}][{}][{}]", self.branch, self.provider, self.os);
+        if !self.compiler.is_empty() {
+            title.push_str(&format!("[{}]", self.compiler));
+        }
+
+        format!("{}: #{}", title, self.build_number)
+    }
+
+    //Links to the build log and to the commit.
+    pub fn description(&self) -> String {
+
+        let mut desc = String::new();
+
+        if !self.log_url.is_empty() {
+            desc.push_str(&format!("[Logs]\\: {}\n", self.log_url));
+        }
+
+        if !self.commit_url.is_empty() {
+            desc.push_str(&format!("[Commit]\\: {}\n", self.commit_url));
+        } else if !self.commit.is_empty() {
+            desc.push_str(&format!("[Commit]\\: {}\n", self.commit));
+        }
+
+        desc.trim_right().to_string()
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+fn var(name: &str) -> String {
+    env::var(name).unwrap_or(String::new())
+}
+
+fn is_set(name: &str) -> bool {
+    !var(name).is_empty()
+}
+
+//Gets the value of the first variable set.
+fn first_of(names: &[&str]) -> String {
+    for name in names {
+        let value = var(name);
+        if !value.is_empty() {
+            return value;
+        }
+    }
+    String::new()
+}
+
+//ex: "Name <name@example.com>" gives "name@example.com".
+fn email_from_author(author: &str) -> String {
+    match (author.find('<'), author.rfind('>')) {
+        (Some(start), Some(end)) if start < end => author[start + 1..end].to_string(),
+        _                                        => String::new()
+    }
+}
+
+//Parses a UTC time in the ISO 8601 format used by CIs, ex: "2016-10-18T05:36:28Z" or "2016-10-18T05:36:28.123+00:00".
+fn parse_utc_time(time: &str) -> Option<u64> {
+
+    let time = time.trim();
+    if time.len() < 20 || !time.bytes().all(|byte| byte < 128) || !(time.ends_with('Z') || time.ends_with("+00:00")) {
+        return Option::None;
+    }
+
+    let field = |start: usize, end: usize| time[start..end].parse::<i64>().ok();
+    let (year, month, day) = match (field(0, 4), field(5, 7), field(8, 10)) {
+        (Some(year), Some(month), Some(day)) if month >= 1 && month <= 12 && day >= 1 && day <= 31 => (year, month, day),
+        _                                                                                          => return Option::None
+    };
+    let (hours, minutes, seconds) = match (field(11, 13), field(14, 16), field(17, 19)) {
+        (Some(hours), Some(minutes), Some(seconds)) => (hours, minutes, seconds),
+        _                                           => return Option::None
+    };
+
+    //Days since 1970-01-01 of the proleptic Gregorian calendar, with years starting in March.
+    let year_from_march = if month <= 2 {year - 1} else {year};
+    let era             = year_from_march / 400;
+    let year_of_era     = year_from_march - era * 400;
+    let day_of_year     = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era      = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days            = era * 146097 + day_of_era - 719468;
+
+    let timestamp = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+    if timestamp < 0 {Option::None} else {Option::Some(timestamp as u64)}
+}
+
+fn github_commit_url(server: &str, repo_slug: &String, commit: &String) -> String {
+    if repo_slug.is_empty() || commit.is_empty() {
+        return String::new();
+    }
+    format!("{}/{}/commit/{}", server, repo_slug, commit)
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_utc_time_formats() {
+        assert_eq!(parse_utc_time("1970-01-01T00:00:00Z"),          Some(0));
+        assert_eq!(parse_utc_time("2016-10-18T05:36:28Z"),          Some(1476768988));
+        assert_eq!(parse_utc_time("2000-02-29T00:00:00.123+00:00"), Some(951782400));
+    }
+
+    #[test]
+    fn parse_utc_time_rejects_other_times() {
+        assert_eq!(parse_utc_time(""),                          None);
+        assert_eq!(parse_utc_time("2016-10-18T05:36:28+02:00"), None);
+        assert_eq!(parse_utc_time("2016-13-18T05:36:28Z"),      None);
+        assert_eq!(parse_utc_time("yesterday at noon, UTC Z"),  None);
+    }
+}
@@ -25,7 +25,6 @@
 
 
 use std::collections::BTreeMap;
-use std::error::Error;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::PathBuf;
@@ -34,6 +33,8 @@ extern crate term;
 
 use serde_json::Value;
 
+use error::TrelloBSTError;
+
 
 ////////////////////////////////////////////////////////////
 //                         Macros                         //
@@ -66,28 +67,30 @@ impl TrelloBSTConfig {
         }
     }
 
-    pub fn load(&mut self, config_mode: Option<PathBuf>) -> Result<(), &'static str> {
+    pub fn load(&mut self, config_mode: Option<PathBuf>) -> Result<(), TrelloBSTError> {
 
         self.config_mode = config_mode;
 
         //Parse if we're using a config file, silently skip if were not
         if self.config_mode.is_some() {
 
+            let config_path = self.config_path();
+
             //Load file
             let mut file = match File::open(self.clone().config_mode.unwrap().as_path()) {
                 Ok(file) => file,
-                Err(_)   =>{
+                Err(err) =>{
                     self.config_mode = Option::None;
-                    return Err("Error: Failed to open the configuration file for parsing, TrelloBST will continue without saving inputted values into the configuration file.");
+                    return Err(TrelloBSTError::config_io(&config_path, err).context("Error: Failed to open the configuration file for parsing, TrelloBST will continue without saving inputted values into the configuration file"));
                 }
             };
 
             //Get config file metadata.
             let metadata = match file.metadata() {
                 Ok(metadata)  => metadata,
-                Err(_)        => {
+                Err(err)      => {
                     self.config_mode = Option::None;
-                    return Err("Error: Failed to gather metadata of the configuration file, TrelloBST will continue without saving inputted values into the configuration file.")
+                    return Err(TrelloBSTError::config_io(&config_path, err).context("Error: Failed to gather metadata of the configuration file, TrelloBST will continue without saving inputted values into the configuration file"))
                 }
             };
 
@@ -100,10 +103,10 @@ impl TrelloBSTConfig {
                 //Read file
                 let mut file_data: String = String::with_capacity(file_length + 1);
                 match file.read_to_string(&mut file_data) {
-                    Ok(_)  => (),
-                    Err(_) => {
+                    Ok(_)    => (),
+                    Err(err) => {
                         self.config_mode = Option::None;
-                        return Err("Error: Failed to read the configuration file, TrelloBST will continue without saving inputted values into the configuration file.")
+                        return Err(TrelloBSTError::config_io(&config_path, err).context("Error: Failed to read the configuration file, TrelloBST will continue without saving inputted values into the configuration file"))
                     }
                 }
 
@@ -111,16 +114,16 @@ impl TrelloBSTConfig {
                 //Parse
                 let json_data: Value = match serde_json::from_str(&file_data){
                     Ok(json_data) => json_data,
-                    Err(_)        => {
+                    Err(err)      => {
                         self.config_mode = Option::None;
-                        return Err("Error: Failed to parse the JSON data in the configuration file, TrelloBST will continue without saving inputted values into the configuration file.")
+                        return Err(TrelloBSTError::parse(&format!("the configuration file \"{}\"", config_path), err).context("Error: Failed to parse the configuration file, TrelloBST will continue without saving inputted values into the configuration file"))
                     }
                 };
 
 
                 //Extract data
                 //Get JSON object
-                let json_object = match json_data.as_object().ok_or("Error: JSON data in the configuration file does not describe a JSON object, TrelloBST will continue without saving inputted values into the configuration file.") {
+                let json_object = match json_data.as_object().ok_or(TrelloBSTError::parse(&format!("the configuration file \"{}\"", config_path), "The JSON data does not describe a JSON object.").context("Error: Failed to parse the configuration file, TrelloBST will continue without saving inputted values into the configuration file")) {
                     Ok(object) => object.clone(),
                     Err(err)   => {
                         self.config_mode = Option::None;
@@ -143,10 +146,12 @@ impl TrelloBSTConfig {
 
 
     //Save config
-    pub fn save(&mut self) -> Result<(), String> {
+    pub fn save(&mut self) -> Result<(), TrelloBSTError> {
 
         if self.config_mode.is_some() {
 
+            let config_path = self.config_path();
+
             let mut json_map: BTreeMap<String, Value> = BTreeMap::new();
 
             for (key, val) in &self.key_val_map {
@@ -158,7 +163,7 @@ impl TrelloBSTConfig {
             let json_map_string = match serde_json::to_string(&value) {
                 Ok(map)  => map,
                 Err(err) => {
-                    return Err(err.description().to_string());
+                    return Err(TrelloBSTError::parse("the configuration", err));
                 }
             };
 
@@ -168,16 +173,16 @@ impl TrelloBSTConfig {
                 Ok(_file) => {
                     file = _file;
                     match file.write_all(json_map_string.as_bytes()) {
-                        Ok(()) => (),
-                        Err(_) => {
+                        Ok(())   => (),
+                        Err(err) => {
                             self.config_mode = Option::None;
-                            return Err("Error: Failed to write data to the configuration file, TrelloBST will continue without saving inputted values into the configuration file.".to_string());
+                            return Err(TrelloBSTError::config_io(&config_path, err).context("Error: Failed to write data to the configuration file, TrelloBST will continue without saving inputted values into the configuration file"));
                         }
                     }
                 }
-                Err(_) => {
+                Err(err) => {
                     self.config_mode = Option::None;
-                    return Err("Error: Failed to open the configuration file for saving, TrelloBST will continue without saving inputted values into the configuration file.".to_string());
+                    return Err(TrelloBSTError::config_io(&config_path, err).context("Error: Failed to open the configuration file for saving, TrelloBST will continue without saving inputted values into the configuration file"));
                 }
             }
         }
@@ -200,4 +205,13 @@ impl TrelloBSTConfig {
             return String::new();
         }
     }
+
+
+    //Path of the configuration file for error messages, "" if we're not using one
+    fn config_path(&self) -> String {
+        match self.config_mode {
+            Some(ref config_path) => config_path.to_string_lossy().to_string(),
+            None                  => String::new()
+        }
+    }
 }
@@ -0,0 +1,332 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::BTreeMap;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+use ci_env::CIEnvironment;
+use error::TrelloBSTError;
+use push::PushConfig;
+use trello_client::TrelloClient;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//Build metadata written into the board's custom fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildField {
+    BuildNumber,
+    Commit,
+    Duration,
+    Provider
+}
+
+pub struct FieldInfo {
+    pub id:         String,
+    pub name:       String,
+    pub field_type: String,
+    pub options:    Vec<(String, String)>
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl BuildField {
+
+    pub fn all() -> Vec<BuildField> {
+        vec![BuildField::BuildNumber,
+             BuildField::Commit,
+             BuildField::Duration,
+             BuildField::Provider]
+    }
+
+    //Default name of the custom field on the board.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BuildField::BuildNumber => "Build #",
+            BuildField::Commit      => "Commit",
+            BuildField::Duration    => "Duration",
+            BuildField::Provider    => "Provider"
+        }
+    }
+
+    fn short_name(&self) -> &'static str {
+        match *self {
+            BuildField::BuildNumber => "build_number",
+            BuildField::Commit      => "commit",
+            BuildField::Duration    => "duration",
+            BuildField::Provider    => "provider"
+        }
+    }
+
+    //Trello custom field type, "list" is a dropdown.
+    pub fn field_type(&self) -> &'static str {
+        match *self {
+            BuildField::BuildNumber => "number",
+            BuildField::Commit      => "text",
+            BuildField::Duration    => "number",
+            BuildField::Provider    => "list"
+        }
+    }
+
+    pub fn config_key(&self) -> String {
+        format!("trello_field_{}_id", self.short_name())
+    }
+
+    pub fn env_var(&self) -> String {
+        format!("TRELLO_API_FIELD_{}_ID", self.short_name().to_uppercase())
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the custom fields of a board.
+pub fn board_fields(api_key: &str, trello_api_token: &str, board_id: &str) -> Result<Vec<FieldInfo>, TrelloBSTError> {
+
+    let data = try!(TrelloClient::new(api_key, trello_api_token).board_custom_fields(board_id));
+
+    let mut fields = Vec::new();
+    for field in try!(data.as_array().ok_or(TrelloBSTError::parse("the custom fields", "The response does not describe an array."))) {
+        fields.push(try!(parse_field(field)));
+    }
+
+    Ok(fields)
+}
+
+//Creates a custom field on a board, shown on the front of the cards.
+pub fn create_field(api_key: &str, trello_api_token: &str, board_id: &str, name: &str, field: BuildField) -> Result<FieldInfo, TrelloBSTError> {
+
+    let mut body: BTreeMap<String, Value> = BTreeMap::new();
+    body.insert("idModel".to_string(),           Value::String(board_id.to_string()));
+    body.insert("modelType".to_string(),         Value::String("board".to_string()));
+    body.insert("name".to_string(),              Value::String(name.to_string()));
+    body.insert("type".to_string(),              Value::String(field.field_type().to_string()));
+    body.insert("pos".to_string(),               Value::String("bottom".to_string()));
+    body.insert("display_cardFront".to_string(), Value::Bool(true));
+
+    let data = try!(TrelloClient::new(api_key, trello_api_token).create_custom_field(&try!(to_json(body))));
+    parse_field(&data)
+}
+
+//Gets the value of each build field, the ones that are not known are left out.
+pub fn field_values(push_data: &PushConfig, ci_env: &Option<CIEnvironment>) -> BTreeMap<BuildField, String> {
+
+    let mut values = BTreeMap::new();
+
+    if let Some(ref ci_env) = *ci_env {
+        if ci_env.build_number.parse::<u64>().is_ok() {
+            values.insert(BuildField::BuildNumber, ci_env.build_number.clone());
+        }
+        if !ci_env.commit.is_empty() {
+            values.insert(BuildField::Commit, ci_env.commit.clone());
+        }
+        values.insert(BuildField::Provider, ci_env.provider.clone());
+    }
+
+    if let Some(duration_secs) = push_data.duration_secs {
+        values.insert(BuildField::Duration, duration_secs.to_string());
+    }
+
+    values
+}
+
+//Fills the custom fields of a card with the build metadata.
+pub fn fill_fields(api_key: &String, push_data: &PushConfig, card_id: &String) -> Result<(), TrelloBSTError> {
+
+    let client = TrelloClient::new(api_key, &push_data.trello_api_token);
+    for (field, value) in field_values(push_data, &CIEnvironment::detect()) {
+
+        let field_id = match push_data.field_ids.get(&field) {
+            Some(field_id) => field_id,
+            None           => continue
+        };
+
+        let mut body: BTreeMap<String, Value> = BTreeMap::new();
+        if field.field_type() == "list" {
+            let option_id = try!(find_or_create_option(&client, field_id, &value));
+            body.insert("idValue".to_string(), Value::String(option_id));
+        } else {
+            let mut field_value: BTreeMap<String, Value> = BTreeMap::new();
+            field_value.insert(field.field_type().to_string(), Value::String(value));
+            body.insert("value".to_string(), Value::Object(field_value));
+        }
+
+        try!(client.set_card_custom_field(card_id, field_id, &try!(to_json(body))));
+    }
+
+    Ok(())
+}
+
+//Gets the id of a dropdown option, the option is added if the dropdown doesn't have it yet.
+fn find_or_create_option(client: &TrelloClient, field_id: &str, text: &str) -> Result<String, TrelloBSTError> {
+
+    let data = try!(client.custom_field(field_id));
+
+    for &(ref option_id, ref option_text) in &try!(parse_field(&data)).options {
+        if option_text == text {
+            return Ok(option_id.clone());
+        }
+    }
+
+    let mut option_value: BTreeMap<String, Value> = BTreeMap::new();
+    option_value.insert("text".to_string(), Value::String(text.to_string()));
+
+    let mut body: BTreeMap<String, Value> = BTreeMap::new();
+    body.insert("value".to_string(), Value::Object(option_value));
+    body.insert("pos".to_string(),   Value::String("bottom".to_string()));
+
+    let option = try!(client.add_custom_field_option(field_id, &try!(to_json(body))));
+    match option.lookup("id").and_then(|id| id.as_str()) {
+        Some(option_id) => Ok(option_id.to_string()),
+        None            => Err(TrelloBSTError::parse("the new custom field option", "The option has no id."))
+    }
+}
+
+fn parse_field(data: &Value) -> Result<FieldInfo, TrelloBSTError> {
+
+    let object = try!(data.as_object().ok_or(TrelloBSTError::parse("the custom fields", "A custom field does not describe an object.")));
+
+    let get_string = |key: &str| -> Result<String, TrelloBSTError> {
+        let value = try!(object.get(key).ok_or(TrelloBSTError::parse("the custom fields", "A custom field is missing a field.")));
+        Ok(try!(value.as_str().ok_or(TrelloBSTError::parse("the custom fields", "A custom field has a field that is not a string."))).to_string())
+    };
+
+    let mut options = Vec::new();
+    if let Some(options_value) = object.get("options") {
+        for option in try!(options_value.as_array().ok_or(TrelloBSTError::parse("the custom fields", "The options of a custom field do not describe an array."))) {
+            let option_id   = option.lookup("id").and_then(|id| id.as_str());
+            let option_text = option.lookup("value.text").and_then(|text| text.as_str());
+            match (option_id, option_text) {
+                (Some(option_id), Some(option_text)) => options.push((option_id.to_string(), option_text.to_string())),
+                _                                    => return Err(TrelloBSTError::parse("the custom fields", "A custom field option is missing its id or text."))
+            }
+        }
+    }
+
+    Ok(FieldInfo {
+        id:         try!(get_string("id")),
+        name:       try!(get_string("name")),
+        field_type: try!(get_string("type")),
+        options:    options
+    })
+}
+
+fn to_json(object: BTreeMap<String, Value>) -> Result<String, TrelloBSTError> {
+    match serde_json::to_string(&Value::Object(object)) {
+        Ok(json) => Ok(json),
+        Err(err) => Err(TrelloBSTError::parse("the request body", err))
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use config;
+
+    use super::*;
+
+    fn push_config(duration_secs: Option<u64>) -> PushConfig {
+        let mut push_config = PushConfig::fill("Build #1".to_string(), "desc".to_string(), "pass".to_string(), "fail".to_string(), "list".to_string(),
+                                               "token".to_string(), String::new(), Vec::new(), &mut config::TrelloBSTConfig::new()).unwrap();
+        push_config.duration_secs = duration_secs;
+        push_config
+    }
+
+    fn ci_env(build_number: &str, commit: &str) -> Option<CIEnvironment> {
+        Option::Some(CIEnvironment {
+            provider:     "Travis-CI".to_string(),
+            branch:       "master".to_string(),
+            build_number: build_number.to_string(),
+            os:           "linux".to_string(),
+            compiler:     String::new(),
+            repo_slug:    "user/repo".to_string(),
+            commit:       commit.to_string(),
+            author_email: String::new(),
+            log_url:      String::new(),
+            commit_url:   String::new(),
+            started_at:   Option::None
+        })
+    }
+
+    fn field(json: &str) -> Result<FieldInfo, TrelloBSTError> {
+        parse_field(&serde_json::from_str::<Value>(json).unwrap())
+    }
+
+    #[test]
+    fn field_values_from_the_ci_and_the_duration() {
+        let values = field_values(&push_config(Some(95)), &ci_env("12", "0123456"));
+        assert_eq!(values.get(&BuildField::BuildNumber), Some(&"12".to_string()));
+        assert_eq!(values.get(&BuildField::Commit),      Some(&"0123456".to_string()));
+        assert_eq!(values.get(&BuildField::Provider),    Some(&"Travis-CI".to_string()));
+        assert_eq!(values.get(&BuildField::Duration),    Some(&"95".to_string()));
+    }
+
+    #[test]
+    fn field_values_skip_what_is_not_known() {
+        //AppVeyor build versions such as "1.0.12" aren't numbers
+        let values = field_values(&push_config(None), &ci_env("1.0.12", ""));
+        assert_eq!(values.keys().cloned().collect::<Vec<BuildField>>(), vec![BuildField::Provider]);
+
+        let values = field_values(&push_config(Some(3)), &Option::None);
+        assert_eq!(values.keys().cloned().collect::<Vec<BuildField>>(), vec![BuildField::Duration]);
+    }
+
+    #[test]
+    fn parse_field_with_options() {
+        let field = field("{\"id\": \"f1\", \"name\": \"Provider\", \"type\": \"list\", \
+                            \"options\": [{\"id\": \"o1\", \"value\": {\"text\": \"Travis-CI\"}}, {\"id\": \"o2\", \"value\": {\"text\": \"AppVeyor\"}}]}").unwrap();
+        assert_eq!(field.id, "f1");
+        assert_eq!(field.name, "Provider");
+        assert_eq!(field.field_type, "list");
+        assert_eq!(field.options, vec![("o1".to_string(), "Travis-CI".to_string()), ("o2".to_string(), "AppVeyor".to_string())]);
+    }
+
+    #[test]
+    fn parse_field_without_options() {
+        assert!(field("{\"id\": \"f1\", \"name\": \"Build #\", \"type\": \"number\"}").unwrap().options.is_empty());
+    }
+
+    #[test]
+    fn parse_field_rejects_incomplete_fields() {
+        assert!(field("{\"name\": \"Build #\", \"type\": \"number\"}").is_err());
+        assert!(field("{\"id\": \"f1\", \"name\": \"Provider\", \"type\": \"list\", \"options\": [{\"id\": \"o1\"}]}").is_err());
+        assert!(field("{\"id\": \"f1\", \"name\": \"Provider\", \"type\": \"list\", \"options\": [{\"value\": {\"text\": \"AppVeyor\"}}]}").is_err());
+        assert!(field("[]").is_err());
+    }
+}
@@ -0,0 +1,416 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+extern crate hyper;
+use self::hyper::header::Headers;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+use authorize;
+use config;
+use custom_fields::BuildField;
+use error::TrelloBSTError;
+use status::{BuildStatus, ListStage};
+use trello_client::{self, TrelloClient};
+use utils;
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//Keys written by older versions of the setup, with the keys push and the CI files read instead.
+const STALE_KEYS: [(&'static str, &'static str); 2] = [("trello_build_pass_id", "trello_label_pass_id"),
+                                                       ("trello_build_fail_id", "trello_label_fail_id")];
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error
+}
+
+//Result of a check, fix is what to do about it and is empty for passed checks.
+pub struct Finding {
+    pub severity: Severity,
+    pub message:  String,
+    pub fix:      String
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl Finding {
+
+    fn ok(message: String) -> Finding {
+        Finding {severity: Severity::Ok, message: message, fix: String::new()}
+    }
+
+    fn warning(message: String, fix: String) -> Finding {
+        Finding {severity: Severity::Warning, message: message, fix: fix}
+    }
+
+    fn error(message: String, fix: String) -> Finding {
+        Finding {severity: Severity::Error, message: message, fix: fix}
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Checks the configuration against the Trello, Travis CI and AppVeyor APIs.
+//NOTE: The board's lists, labels and fields are only checked once the token and the board are known to work.
+pub fn diagnose(api_key: &str, config: &mut config::TrelloBSTConfig) -> Vec<Finding> {
+
+    let mut findings = Vec::new();
+
+    check_stale_keys(&mut findings, config);
+
+    if check_token(&mut findings, api_key, config) && check_board(&mut findings, api_key, config) {
+        check_lists(&mut findings, api_key, config);
+        check_labels(&mut findings, api_key, config);
+        check_fields(&mut findings, api_key, config);
+    }
+
+    check_travis_token(&mut findings, config);
+    check_appveyor_token(&mut findings, config);
+
+    findings
+}
+
+fn check_stale_keys(findings: &mut Vec<Finding>, config: &mut config::TrelloBSTConfig) {
+    for &(stale_key, key) in &STALE_KEYS {
+        let stale_value = config.get(stale_key);
+        if stale_value.is_empty() {
+            continue;
+        }
+
+        if config.get(key).is_empty() {
+            findings.push(Finding::error(format!("\"{}\" is set but push and the CI files read the label id from \"{}\", which is empty.", stale_key, key),
+                                         format!("Rename \"{}\" to \"{}\" in the configuration file.", stale_key, key)));
+        } else if config.get(key) != stale_value {
+            findings.push(Finding::warning(format!("\"{}\" is no longer read and differs from \"{}\".", stale_key, key),
+                                           format!("Remove \"{}\" from the configuration file, or move its value to \"{}\" if that is the label you want.", stale_key, key)));
+        } else {
+            findings.push(Finding::warning(format!("\"{}\" is no longer read.", stale_key),
+                                           format!("Remove \"{}\" from the configuration file.", stale_key)));
+        }
+    }
+}
+
+//Checks that a token is configured, without calling the API.
+fn check_token_is_set(findings: &mut Vec<Finding>, config: &mut config::TrelloBSTConfig) -> bool {
+
+    if !config.get("trello_api_token").is_empty() {
+        return true;
+    }
+
+    findings.push(Finding::error("No trello api token is configured.".to_string(),
+                                 "Run TrelloBST or \"TrelloBST setup --token <token>\" to set one up.".to_string()));
+    false
+}
+
+fn check_token(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig) -> bool {
+
+    if !check_token_is_set(findings, config) {
+        return false;
+    }
+
+    let token = config.get("trello_api_token");
+    match TrelloClient::new(api_key, &token).me() {
+        Ok(member) => {
+            findings.push(Finding::ok(format!("The trello api token is valid (logged in as {}).", member.username)));
+            true
+        }
+        Err(err) => {
+            findings.push(Finding::error(format!("The trello api token was refused: {}", err),
+                                         format!("Generate a new token at {} and set it as \"trello_api_token\".", authorize::authorize_url(api_key, &authorize::TokenRequest::default(), Option::None))));
+            false
+        }
+    }
+}
+
+fn check_board(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig) -> bool {
+
+    let board_id = config.get("trello_board_id");
+    if board_id.is_empty() {
+        findings.push(Finding::error("No board is configured.".to_string(),
+                                     "Run TrelloBST or \"TrelloBST setup\" to select a board.".to_string()));
+        return false;
+    }
+
+    match TrelloClient::new(api_key, &config.get("trello_api_token")).board(&board_id) {
+        Ok(board) => {
+            if board.closed {
+                findings.push(Finding::warning(format!("The board \"{}\" is closed.", board.name),
+                                               "Reopen the board on Trello or run the setup with another board.".to_string()));
+            } else {
+                findings.push(Finding::ok(format!("The board \"{}\" exists.", board.name)));
+            }
+            true
+        }
+        Err(err) => {
+            findings.push(Finding::error(format!("The board {} could not be found: {}", board_id, err),
+                                         "Run TrelloBST or \"TrelloBST setup\" to select an existing board.".to_string()));
+            false
+        }
+    }
+}
+
+//Either the single list or every build stage list must be set, returns false when no list is set.
+fn check_list_keys(findings: &mut Vec<Finding>, config: &mut config::TrelloBSTConfig) -> bool {
+
+    let list_id   = config.get("trello_list_id");
+    let stage_ids: Vec<String> = ListStage::all().into_iter().map(|stage| config.get(&stage.config_key())).collect();
+    let set_count = stage_ids.iter().filter(|stage_id| !stage_id.is_empty()).count();
+
+    if list_id.is_empty() && set_count == 0 {
+        findings.push(Finding::error("No list is configured.".to_string(),
+                                     "Run the setup to select the list the build statuses are pushed to.".to_string()));
+        return false;
+    }
+
+    if set_count != 0 && set_count != stage_ids.len() {
+        findings.push(Finding::error("Only some of the build stage lists are configured.".to_string(),
+                                     format!("Run the setup again, or set all of {} in the configuration file.", ListStage::all().iter().map(|stage| stage.config_key()).collect::<Vec<String>>().join(", "))));
+    }
+    true
+}
+
+fn check_lists(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig) {
+
+    if !check_list_keys(findings, config) {
+        return;
+    }
+
+    let list_id = config.get("trello_list_id");
+    if !list_id.is_empty() {
+        check_board_item(findings, api_key, config, "list", "trello_list_id", &format!("lists/{}", trello_client::encode(&list_id)), "name,closed,idBoard");
+    }
+
+    let stage_ids: Vec<(ListStage, String)> = ListStage::all().into_iter().map(|stage| (stage, config.get(&stage.config_key()))).collect();
+    for &(stage, ref stage_id) in &stage_ids {
+        if !stage_id.is_empty() {
+            check_board_item(findings, api_key, config, "list", &stage.config_key(), &format!("lists/{}", trello_client::encode(stage_id)), "name,closed,idBoard");
+        }
+    }
+}
+
+fn check_labels(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig) {
+    for build_status in BuildStatus::all() {
+        let label_id = config.get(&build_status.config_key());
+        if label_id.is_empty() {
+            if build_status.is_required() {
+                findings.push(Finding::error(format!("No label is configured for the build {} status.", build_status.name()),
+                                             format!("Run the setup to select the label, or set \"{}\" in the configuration file.", build_status.config_key())));
+            }
+            continue;
+        }
+        check_board_item(findings, api_key, config, "label", &build_status.config_key(), &format!("labels/{}", trello_client::encode(&label_id)), "name,color,idBoard");
+    }
+}
+
+fn check_fields(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig) {
+    for field in BuildField::all() {
+        let field_id = config.get(&field.config_key());
+        if !field_id.is_empty() {
+            check_board_item(findings, api_key, config, "custom field", &field.config_key(), &format!("customFields/{}", trello_client::encode(&field_id)), "");
+        }
+    }
+}
+
+//Checks that the list, label or custom field exists and is on the configured board.
+//NOTE: Custom fields don't take a list of fields, an empty one gets them all.
+fn check_board_item(findings: &mut Vec<Finding>, api_key: &str, config: &mut config::TrelloBSTConfig, kind: &str, config_key: &str, api_path: &str, fields: &str) {
+
+    let board_id = config.get("trello_board_id");
+    let client   = TrelloClient::new(api_key, &config.get("trello_api_token"));
+    let params   = if fields.is_empty() {vec![]} else {vec![("fields", fields)]};
+
+    let item = match client.get_json(&format!("the {}", kind), api_path, &params) {
+        Ok(item) => item,
+        Err(err) => {
+            findings.push(Finding::error(format!("The {} \"{}\" could not be found: {}", kind, config_key, err),
+                                         format!("Run the setup again to select an existing {}, or fix \"{}\" in the configuration file.", kind, config_key)));
+            return;
+        }
+    };
+
+    //Custom fields belong to their board through idModel.
+    let item_board_id = match item.lookup("idBoard").or(item.lookup("idModel")).and_then(|id| id.as_str()) {
+        Some(item_board_id) => item_board_id.to_string(),
+        None                => String::new()
+    };
+
+    let name = string_field(&item, "name");
+    if item_board_id != board_id {
+        findings.push(Finding::error(format!("The {} \"{}\" ({}) is on another board than \"trello_board_id\".", kind, name, config_key),
+                                     format!("Run the setup again to select a {} of board {}, or fix \"{}\" in the configuration file.", kind, board_id, config_key)));
+    } else if item.lookup("closed").and_then(|closed| closed.as_bool()) == Some(true) {
+        findings.push(Finding::warning(format!("The {} \"{}\" ({}) is archived.", kind, name, config_key),
+                                       format!("Unarchive it on Trello or run the setup again to select another {}.", kind)));
+    } else {
+        findings.push(Finding::ok(format!("The {} \"{}\" ({}) is on the board.", kind, name, config_key)));
+    }
+}
+
+fn check_travis_token(findings: &mut Vec<Finding>, config: &mut config::TrelloBSTConfig) {
+
+    let token = config.get("travis_access_token");
+    if token.is_empty() {
+        return;
+    }
+
+    let mut header = Headers::new();
+    header.set_raw("User-Agent",    vec![b"Travis_TrelloBST/1.0.0".to_vec()]);
+    header.set_raw("Accept",        vec![b"application/vnd.travis-ci.2+json".to_vec()]);
+    header.set_raw("Authorization", vec![format!("token {}", token).into_bytes()]);
+    header.set_raw("Host",          vec![b"api.travis-ci.org".to_vec()]);
+
+    match get_object(&"https://api.travis-ci.org/users".to_string(), header) {
+        Ok(_)    => findings.push(Finding::ok("The Travis CI access token is valid.".to_string())),
+        Err(err) => findings.push(Finding::error(format!("The Travis CI access token was refused: {}", err),
+                                                 "Remove \"travis_access_token\" from the configuration file and run TrelloBST to log in to Travis CI again.".to_string()))
+    }
+}
+
+fn check_appveyor_token(findings: &mut Vec<Finding>, config: &mut config::TrelloBSTConfig) {
+
+    let token = config.get("appveyor_api_token");
+    if token.is_empty() {
+        return;
+    }
+
+    let mut header = Headers::new();
+    header.set_raw("Authorization", vec![format!("Bearer {}", token).into_bytes()]);
+    header.set_raw("Content-Type",  vec![b"application/json".to_vec()]);
+
+    match utils::rest_api_call_get_with_header(&"https://ci.appveyor.com/api/projects".to_string(), header) {
+        Ok(_)    => findings.push(Finding::ok("The AppVeyor api token is valid.".to_string())),
+        Err(err) => findings.push(Finding::error(format!("The AppVeyor api token was refused: {}", err),
+                                                 "Get a new token from https://ci.appveyor.com/api-token and set it as \"appveyor_api_token\" in the configuration file.".to_string()))
+    }
+}
+
+fn get_object(api_call: &String, header: Headers) -> Result<Value, TrelloBSTError> {
+
+    let response_body = try!(utils::rest_api_call_get_with_header(api_call, header));
+
+    match serde_json::from_str::<Value>(&response_body) {
+        Ok(data) => Ok(data),
+        Err(err) => Err(TrelloBSTError::parse("the API response", err))
+    }
+}
+
+fn string_field(data: &Value, field: &str) -> String {
+    data.lookup(field).and_then(|value| value.as_str()).unwrap_or("").to_string()
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use config;
+
+    use super::*;
+
+    fn config(values: &[(&str, &str)]) -> config::TrelloBSTConfig {
+        let mut config = config::TrelloBSTConfig::new();
+        for &(key, value) in values {
+            config.set(key, value);
+        }
+        config
+    }
+
+    fn severities(findings: &Vec<Finding>) -> Vec<Severity> {
+        findings.iter().map(|finding| finding.severity).collect()
+    }
+
+    #[test]
+    fn stale_keys_without_the_new_key_are_errors() {
+        let mut findings = Vec::new();
+        check_stale_keys(&mut findings, &mut config(&[("trello_build_pass_id", "l1")]));
+        assert_eq!(severities(&findings), vec![Severity::Error]);
+        assert!(findings[0].fix.contains("trello_label_pass_id"));
+    }
+
+    #[test]
+    fn stale_keys_next_to_the_new_key_are_warnings() {
+        let mut findings = Vec::new();
+        check_stale_keys(&mut findings, &mut config(&[("trello_build_pass_id", "l1"), ("trello_label_pass_id", "l1"),
+                                                      ("trello_build_fail_id", "l2"), ("trello_label_fail_id", "l3")]));
+        assert_eq!(severities(&findings), vec![Severity::Warning, Severity::Warning]);
+        assert!(findings[1].message.contains("differs"));
+
+        findings.clear();
+        check_stale_keys(&mut findings, &mut config(&[("trello_label_pass_id", "l1")]));
+        assert!(findings.is_empty());
+    }
+
+    #[test]
+    fn partially_configured_stage_lists_are_errors() {
+        let mut findings = Vec::new();
+        assert!(check_list_keys(&mut findings, &mut config(&[("trello_list_running_id", "l1"), ("trello_list_passed_id", "l2")])));
+        assert_eq!(severities(&findings), vec![Severity::Error]);
+        assert!(findings[0].fix.contains("trello_list_failed_id"));
+
+        findings.clear();
+        assert!(check_list_keys(&mut findings, &mut config(&[("trello_list_running_id", "l1"), ("trello_list_passed_id", "l2"), ("trello_list_failed_id", "l3")])));
+        assert!(check_list_keys(&mut findings, &mut config(&[("trello_list_id", "l1")])));
+        assert!(findings.is_empty());
+    }
+
+    #[test]
+    fn missing_lists_are_errors() {
+        let mut findings = Vec::new();
+        assert!(!check_list_keys(&mut findings, &mut config(&[])));
+        assert_eq!(severities(&findings), vec![Severity::Error]);
+    }
+
+    #[test]
+    fn missing_token_is_an_error() {
+        let mut findings = Vec::new();
+        assert!(!check_token_is_set(&mut findings, &mut config(&[])));
+        assert_eq!(severities(&findings), vec![Severity::Error]);
+
+        findings.clear();
+        assert!(check_token_is_set(&mut findings, &mut config(&[("trello_api_token", "token")])));
+        assert!(findings.is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::error;
+use std::fmt;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+
+////////////////////////////////////////////////////////////
+//                         Enums                          //
+////////////////////////////////////////////////////////////
+
+//Every error TrelloBST can run into, main prints the context chain and exits with the category's code.
+//NOTE: Requests are kept as "METHOD url" with the token redacted, see redact_url.
+#[derive(Debug)]
+pub enum TrelloBSTError {
+    //The API could not be reached or its response could not be read.
+    Transport  {request: String, cause: String},
+    //The API answered with an error status code, message is the error returned by the API if any.
+    HttpStatus {request: String, status: u16, message: String},
+    //The API refused the request with an error message (ex: "invalid token").
+    Api        {request: String, message: String},
+    //A response, report or file could not be parsed.
+    Parse      {what: String, cause: String},
+    //A local file (configuration, state, spool or members mapping) could not be read or written.
+    ConfigIo   {path: String, cause: String},
+    //A file given to the push (attachment or test report) could not be read.
+    FileIo     {path: String, cause: String},
+    //The user quit the setup.
+    UserAbort,
+    //Invalid option, environment variable or configuration value.
+    Validation (String),
+    //What was being done when the cause happened.
+    Context    {context: String, cause: Box<TrelloBSTError>}
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Traits                         //
+////////////////////////////////////////////////////////////
+
+//Adds a context to the error of a result, ex: try!(config.save().context("Error while saving the configuration file"))
+pub trait ResultContext<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, TrelloBSTError>;
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl TrelloBSTError {
+
+    pub fn parse<T: fmt::Display>(what: &str, cause: T) -> TrelloBSTError {
+        TrelloBSTError::Parse {what: what.to_string(), cause: cause.to_string()}
+    }
+
+    pub fn config_io<T: fmt::Display>(path: &str, cause: T) -> TrelloBSTError {
+        TrelloBSTError::ConfigIo {path: path.to_string(), cause: cause.to_string()}
+    }
+
+    pub fn file_io<T: fmt::Display>(path: &str, cause: T) -> TrelloBSTError {
+        TrelloBSTError::FileIo {path: path.to_string(), cause: cause.to_string()}
+    }
+
+    //Wraps the error with what was being done, ex: "Error while pushing the card to Trello".
+    pub fn context<T: Into<String>>(self, context: T) -> TrelloBSTError {
+        TrelloBSTError::Context {context: context.into(), cause: Box::new(self)}
+    }
+
+    //The error at the end of the context chain.
+    pub fn root(&self) -> &TrelloBSTError {
+        match *self {
+            TrelloBSTError::Context {ref cause, ..} => cause.root(),
+            _                                       => self
+        }
+    }
+
+    //Messages from the outermost context to the root error.
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain = vec![self.message()];
+        if let TrelloBSTError::Context {ref cause, ..} = *self {
+            chain.extend(cause.chain());
+        }
+        chain
+    }
+
+    //Exit code of the root error's category, these don't change between versions so scripts can rely on them.
+    //1 is left to failed checks and build commands, 2 and up are the categories.
+    pub fn exit_code(&self) -> i32 {
+        match *self.root() {
+            TrelloBSTError::Validation(_)      => 2,
+            TrelloBSTError::Transport {..}     => 3,
+            TrelloBSTError::HttpStatus {..}    => 4,
+            TrelloBSTError::Api {..}           => 5,
+            TrelloBSTError::Parse {..}         => 6,
+            TrelloBSTError::ConfigIo {..}      => 7,
+            TrelloBSTError::UserAbort          => 8,
+            TrelloBSTError::FileIo {..}        => 9,
+            TrelloBSTError::Context {..}       => unreachable!()
+        }
+    }
+
+    //Message of this error alone, without its causes.
+    fn message(&self) -> String {
+        match *self {
+            TrelloBSTError::Transport {ref request, ref cause} => {
+                format!("Error calling the API ({}): {}", request, cause)
+            }
+            TrelloBSTError::HttpStatus {ref request, status, ref message} => {
+                let mut text = format!("Error: {} (HTTP {}, {})", status_description(status), status, request);
+                if !message.is_empty() {
+                    text.push_str(&format!(": {}", message));
+                }
+                text
+            }
+            TrelloBSTError::Api {ref request, ref message} => {
+                format!("Error, the API refused the request ({}): {}", request, message)
+            }
+            TrelloBSTError::Parse {ref what, ref cause} => {
+                format!("Error parsing {}: {}", what, cause)
+            }
+            TrelloBSTError::ConfigIo {ref path, ref cause} => {
+                format!("Error accessing \"{}\": {}", path, cause)
+            }
+            TrelloBSTError::FileIo {ref path, ref cause} => {
+                format!("Error reading \"{}\": {}", path, cause)
+            }
+            TrelloBSTError::UserAbort => {
+                "Aborted by the user.".to_string()
+            }
+            TrelloBSTError::Validation(ref message) => {
+                message.clone()
+            }
+            TrelloBSTError::Context {ref context, ..} => {
+                context.clone()
+            }
+        }
+    }
+}
+
+//The whole chain on a single line, main prints one line per error instead.
+impl fmt::Display for TrelloBSTError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.chain().join(": "))
+    }
+}
+
+impl error::Error for TrelloBSTError {
+
+    fn description(&self) -> &str {
+        match *self {
+            TrelloBSTError::Transport {..}  => "transport error",
+            TrelloBSTError::HttpStatus {..} => "http status error",
+            TrelloBSTError::Api {..}        => "api error",
+            TrelloBSTError::Parse {..}      => "parse error",
+            TrelloBSTError::ConfigIo {..}   => "configuration io error",
+            TrelloBSTError::FileIo {..}     => "file io error",
+            TrelloBSTError::UserAbort       => "user abort",
+            TrelloBSTError::Validation(_)   => "validation error",
+            TrelloBSTError::Context {..}    => "error context"
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            TrelloBSTError::Context {ref cause, ..} => Option::Some(&**cause),
+            _                                       => Option::None
+        }
+    }
+}
+
+//Plain messages are validation errors, ex: try!(value.ok_or("Error: ..."))
+impl From<&'static str> for TrelloBSTError {
+    fn from(message: &'static str) -> TrelloBSTError {
+        TrelloBSTError::Validation(message.to_string())
+    }
+}
+
+impl From<String> for TrelloBSTError {
+    fn from(message: String) -> TrelloBSTError {
+        TrelloBSTError::Validation(message)
+    }
+}
+
+impl<T, E: Into<TrelloBSTError>> ResultContext<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, TrelloBSTError> {
+        self.map_err(|err| err.into().context(context))
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Hides the value of the token parameter so errors can be printed in public CI logs.
+pub fn redact_url(url: &str) -> String {
+
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None        => return url.to_string()
+    };
+
+    //ex: "token=..." or "github_token=..."
+    let params: Vec<String> = query.split('&').map(|param| {
+        let name = param.split('=').next().unwrap_or("");
+        if name == "token" || name.ends_with("_token") {format!("{}=<redacted>", name)} else {param.to_string()}
+    }).collect();
+
+    format!("{}?{}", path, params.join("&"))
+}
+
+//Gets the error message from an API error response, Trello answers with plain text or a JSON "message".
+pub fn api_message(response_body: &str) -> String {
+
+    let response_body = response_body.trim();
+    if let Ok(data) = serde_json::from_str::<Value>(response_body) {
+        if let Some(message) = data.lookup("message").and_then(|message| message.as_str()) {
+            return message.to_string();
+        }
+    }
+
+    //NOTE: Error pages can be long, only the start is kept.
+    response_body.chars().take(200).collect()
+}
+
+fn status_description(status: u16) -> &'static str {
+    match status {
+        400       => "The API rejected the request",
+        401       => "The API token is invalid or does not have the permission for this request",
+        403       => "The API token does not have access to this resource",
+        404       => "The requested resource was not found",
+        429       => "The API rate limit has been exceeded, giving up after the maximum number of attempts",
+        500...599 => "The API server failed to handle the request, giving up after the maximum number of attempts",
+        _         => "The API call failed with an unexpected status code"
+    }
+}
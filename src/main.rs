@@ -32,9 +32,10 @@ use std::io::{Write};
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 extern crate clap;
-use clap::{Arg, App, AppSettings, SubCommand};
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
 
 extern crate hyper;
 
@@ -43,13 +44,31 @@ extern crate serde_json;
 
 extern crate term;
 
+use error::TrelloBSTError;
+
 mod appveyor;
+mod authorize;
+mod branch_labels;
+mod changes;
 mod ci;
+mod ci_env;
 mod config;
+mod custom_fields;
+mod doctor;
+mod error;
+mod members;
 mod travis_ci;
 mod trello;
+mod trello_client;
 mod utils;
+mod prune;
 mod push;
+mod reports;
+mod run;
+mod setup;
+mod spool;
+mod status;
+mod template;
 
 
 ////////////////////////////////////////////////////////////
@@ -117,6 +136,765 @@ pub fn dir_path_validator(dir_path: String) -> Result<(), String> {
 }
 
 
+//Prints an error followed by its causes, one per line.
+pub fn print_error(term: &mut Box<term::StdoutTerminal>, err: &TrelloBSTError) {
+    let chain = err.chain();
+    writeln_red!(term, "{}", chain[0]);
+    for cause in &chain[1..] {
+        writeln_red!(term, "  Caused by: {}", cause);
+    }
+}
+
+//Prints an error and exits with the exit code of its category.
+pub fn exit_with_error(term: &mut Box<term::StdoutTerminal>, err: TrelloBSTError) -> ! {
+    print_error(term, &err);
+    exit(err.exit_code());
+}
+
+//Prints an error and keeps it if it's the first one, for steps that carry on after an error.
+pub fn keep_error(term: &mut Box<term::StdoutTerminal>, first_err: &mut Option<TrelloBSTError>, err: TrelloBSTError) {
+    print_error(term, &err);
+    if first_err.is_none() {
+        *first_err = Option::Some(err);
+    }
+}
+
+
+//Gets the configuration file location from the --config and --no-config options, the default is ~/.TrelloBST.cfg
+pub fn config_mode_from_matches(term: &mut Box<term::StdoutTerminal>, matches: &ArgMatches) -> Option<PathBuf> {
+
+    let mut config_mode: Option<PathBuf> = Option::None;
+
+    //Check config file cli options
+    if !matches.is_present("CONFIG") && !matches.is_present("NO-CONFIG") && !matches.is_present("PROFILE") {
+
+        //Default config
+        //Check if home directory works
+        let valid_path_found: bool;
+        match env::home_dir() {
+            Some(home_dir) => {
+                let mut config_file_path = home_dir;
+                config_file_path.push(".TrelloBST.cfg");
+                match file_path_validator(config_file_path.to_str().unwrap_or("~/.TrelloBST.cfg").to_string()) {
+                    Ok(()) => {
+                        println!("Config file location set to: {:?}", config_file_path);
+                        config_mode = Option::Some(config_file_path);
+                        valid_path_found = true;
+                    }
+                    Err(_) => {valid_path_found = false}
+                }
+            }
+            None           => {
+                writeln_red!(term, "Error: Failed to acquire the home directory path.");
+                valid_path_found = false;
+            }
+        }
+
+        if !valid_path_found {
+
+            let config_file_path_str = "./.TrelloBST.cfg".to_string();
+            let config_file_path = PathBuf::from(&config_file_path_str);
+
+            writeln_red!(term, "Error: Failed to read/create the configuration file in the home directory. Falling back to ./.TrelloBST.cfg");
+            match file_path_validator(config_file_path_str.clone()) {
+                Ok(()) => {
+                    config_mode = Option::Some(PathBuf::from(config_file_path));
+                    println!("Config file location set to: {}", config_file_path_str);
+                }
+                Err(_) => {
+                    config_mode = Option::None;
+                    writeln_red!(term, "Error: Failed to read/create the configuration file at ./.TrelloBST.cfg. TrelloBST will continue without saving inputted values into the configuration file.");
+                }
+            }
+        }
+
+    } else if matches.is_present("CONFIG") {
+        //Custom config
+        println!("Config file location set to: {}", matches.value_of("CONFIG").unwrap());
+        config_mode = Option::Some(PathBuf::from(matches.value_of("CONFIG").unwrap()));
+    } else if matches.is_present("PROFILE") {
+        //Named config
+        let config_file_path = profile_path(matches.value_of("PROFILE").unwrap());
+        match file_path_validator(config_file_path.to_string_lossy().to_string()) {
+            Ok(()) => {
+                println!("Config file location set to: {:?}", config_file_path);
+                config_mode = Option::Some(config_file_path);
+            }
+            Err(err) => {
+                writeln_red!(term, "Error: {}. TrelloBST will continue without saving inputted values into the configuration file.", err);
+            }
+        }
+    } else if matches.is_present("NO-CONFIG") {
+        config_mode = Option::None;
+    }
+
+    config_mode
+}
+
+//Loads the TrelloBST configuration file.
+pub fn load_config(term: &mut Box<term::StdoutTerminal>, config_mode: Option<PathBuf>) -> config::TrelloBSTConfig {
+
+    let status     = utils::StatusPrint::from_str(term, "Parsing the configuration file...");
+    let mut config = config::TrelloBSTConfig::new();
+
+    match config.load(config_mode) {
+        Ok(())   => {status.success(term);},
+        Err(err) => {
+            status.error(term);
+            print_error(term, &err);
+        }
+    }
+
+    config
+}
+
+
+pub fn push_args<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+    push_config_file_args(subcommand)
+    .arg(Arg::with_name("CARD_TITLE")
+         .short("t")
+         .long("title")
+         .help("Sets the title of the card, built from the CI's environment variables if not given.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("CARD_DESC")
+         .short("d")
+         .long("description")
+         .help("Sets the description of the card, links to the build log and commit if not given.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TITLE_TEMPLATE")
+         .conflicts_with("CARD_TITLE")
+         .long("title-template")
+         .help("Sets the title of the card from a template (ex: \"[{branch}]: #{build_number} {status}\"), overrides the \"card_title_template\" configuration value.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("DESC_TEMPLATE")
+         .conflicts_with("CARD_DESC")
+         .long("desc-template")
+         .help("Sets the description of the card from a template, overrides the \"card_desc_template\" configuration value.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TRELLO_API_TOKEN")
+         .short("T")
+         .long("token")
+         .help("Manually overrides the trello api token from the \"TRELLO_API_TOKEN\" environment variable.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TRELLO_LIST_ID")
+         .short("L")
+         .long("list-id")
+         .help("Manually overrides the trello list id from the \"TRELLO_API_LIST_ID\" environment variable.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TRELLO_BUILD_PASS_ID")
+         .short("P")
+         .long("pass-id")
+         .help("Manually overrides the trello build pass id from the \"TRELLO_API_BUILD_PASS_ID\" environment variable.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TRELLO_BUILD_FAIL_ID")
+         .short("F")
+         .long("fail-id")
+         .help("Manually overrides the trello build fail id from the \"TRELLO_API_FAIL_PASS_ID\" environment variable.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("UPDATE_KEY")
+         .short("u")
+         .long("update-key")
+         .help("Updates the most recent card whose title tags match the key (ex: \"branch+ci+os\") instead of creating a new one.")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("MAX_ATTEMPTS")
+         .long("max-attempts")
+         .help("Sets the number of attempts made for Trello API calls that can be retried, overrides the \"TRELLOBST_MAX_ATTEMPTS\" environment variable. (Default: 5)")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("TARGET")
+         .long("target")
+         .help("Pushes the card to a list, written as \"list_id[:pass_label_id[:fail_label_id]]\", can be used multiple times. Overrides the \"TRELLO_API_TARGETS\" environment variable.")
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .required(false))
+    .arg(Arg::with_name("TARGET_POLICY")
+         .long("target-policy")
+         .help("Sets whether all targets or any target must be pushed to for the push to succeed. (Default: all)")
+         .possible_values(&["all", "any"])
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("SPOOL_DIR")
+         .long("spool-dir")
+         .help("Sets the directory cards that could not be pushed are spooled to, overrides the \"TRELLOBST_SPOOL_DIR\" environment variable. (Default: ~/.TrelloBST.spool)")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("LABEL_BRANCH")
+         .long("label-branch")
+         .help("Adds a label named after the branch to the card, the label is created if the board doesn't have it.")
+         .takes_value(false))
+    .arg(Arg::with_name("LIST_CHANGES")
+         .long("list-changes")
+         .help("Lists the commits since the last passing build of the branch on failing build cards.")
+         .takes_value(false))
+    .arg(Arg::with_name("ASSIGN_AUTHOR")
+         .long("assign-author")
+         .help("Assigns the board member mapped to the commit author to failing build cards.")
+         .takes_value(false))
+    .arg(Arg::with_name("MEMBERS_FILE")
+         .long("members-file")
+         .help("Sets the git author email to board member mapping file used by --assign-author. (Default: ./.TrelloBST.members)")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("ATTACH")
+         .short("a")
+         .long("attach")
+         .help("Attaches a file or a url to the card, can be used multiple times.")
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .required(false))
+    .arg(Arg::with_name("JUNIT")
+         .long("junit")
+         .help("Adds the test cases of a JUnit XML report as a checklist on the card, can be used multiple times.")
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .required(false))
+    .arg(Arg::with_name("TAP")
+         .long("tap")
+         .help("Adds the test cases of a TAP report as a checklist on the card, can be used multiple times.")
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .required(false))
+}
+
+pub fn push_status_args<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+    subcommand
+    .arg(Arg::with_name("BUILD_PASS")
+         .conflicts_with_all(&["BUILD_FAIL", "BUILD_STATUS"])
+         .short("p")
+         .long("pass")
+         .help("Sets build status to passed.")
+         .takes_value(false))
+    .arg(Arg::with_name("BUILD_FAIL")
+         .conflicts_with_all(&["BUILD_PASS", "BUILD_STATUS"])
+         .short("f")
+         .long("fail")
+         .help("Sets build status to failed.")
+         .takes_value(false))
+    .arg(Arg::with_name("BUILD_STATUS")
+         .conflicts_with_all(&["BUILD_PASS", "BUILD_FAIL"])
+         .short("s")
+         .long("status")
+         .help("Sets build status, the label used comes from the matching \"TRELLO_API_BUILD_<STATUS>_ID\" environment variable.")
+         .possible_values(&["pending", "running", "passed", "failed", "errored", "cancelled"])
+         .takes_value(true))
+}
+
+pub fn state_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("STATE_FILE")
+    .long("state-file")
+    .help("Sets the file in which the id of the card pushed by \"push start\" is kept. (Default: ./.TrelloBST.state)")
+    .takes_value(true)
+    .required(false)
+}
+
+pub fn token_arg<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+    subcommand
+    .arg(Arg::with_name("TRELLO_API_TOKEN")
+         .short("T")
+         .long("token")
+         .help("Sets the trello api token, overrides the \"TRELLO_API_TOKEN\" environment variable and the configuration file.")
+         .takes_value(true)
+         .required(false))
+}
+
+pub fn build_status_from_matches(matches: &ArgMatches) -> Result<status::BuildStatus, TrelloBSTError> {
+    if matches.is_present("BUILD_PASS") {
+        Ok(status::BuildStatus::Passed)
+    } else if matches.is_present("BUILD_FAIL") {
+        Ok(status::BuildStatus::Failed)
+    } else if let Some(build_status) = matches.value_of("BUILD_STATUS") {
+        status::BuildStatus::from_str(build_status)
+    } else {
+        Err(TrelloBSTError::Validation("Error: One of --pass, --fail and --status must be used.".to_string()))
+    }
+}
+
+//Gets the path of a named configuration file, ~/.TrelloBST.<name>.cfg.
+pub fn profile_path(profile: &str) -> PathBuf {
+    let mut profile_path = env::home_dir().unwrap_or(PathBuf::from("."));
+    profile_path.push(format!(".TrelloBST.{}.cfg", profile));
+    profile_path
+}
+
+//Loads the configuration file given with --config or --profile, else the default one if there is one.
+//NOTE: Push and flush only read from it, values from the cli options and env vars take precedence.
+pub fn load_push_file_config(matches: &ArgMatches) -> Result<config::TrelloBSTConfig, TrelloBSTError> {
+
+    let mut config = config::TrelloBSTConfig::new();
+
+    let config_file_path = if let Some(config_file_path) = matches.value_of("PUSH_CONFIG") {
+        PathBuf::from(config_file_path)
+    } else if let Some(profile) = matches.value_of("PROFILE") {
+        profile_path(profile)
+    } else {
+        match env::home_dir() {
+            Some(mut config_file_path) => {
+                config_file_path.push(".TrelloBST.cfg");
+                if !config_file_path.is_file() {
+                    return Ok(config);
+                }
+                config_file_path
+            }
+            None => return Ok(config)
+        }
+    };
+
+    if !config_file_path.is_file() {
+        return Err(TrelloBSTError::config_io(&config_file_path.to_string_lossy(), "The configuration file or profile does not exist."));
+    }
+
+    try!(config.load(Option::Some(config_file_path)));
+    Ok(config)
+}
+
+pub fn push_config_file_args<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+    subcommand
+    .arg(Arg::with_name("PUSH_CONFIG")
+         .conflicts_with("PROFILE")
+         .long("config")
+         .help("Reads the values not given as cli options or env vars from a TrelloBST configuration file. (Default: ~/.TrelloBST.cfg if it exists)")
+         .takes_value(true)
+         .required(false))
+    .arg(Arg::with_name("PROFILE")
+         .conflicts_with("PUSH_CONFIG")
+         .long("profile")
+         .help("Reads the values not given as cli options or env vars from the ~/.TrelloBST.<name>.cfg configuration file.")
+         .takes_value(true)
+         .required(false))
+}
+
+//Gets a card template from the cli option or else from the configuration file.
+pub fn template_from_matches(matches: &ArgMatches, arg_name: &str, skip_arg_name: &str, config: &mut config::TrelloBSTConfig, config_key: &str) -> Result<Option<template::Template>, TrelloBSTError> {
+
+    let template = match matches.value_of(arg_name) {
+        Some(template) => template.to_string(),
+        None           => {
+            //An explicit title or description wins over the configured template
+            if matches.is_present(skip_arg_name) {
+                return Ok(Option::None);
+            }
+            config.get(config_key)
+        }
+    };
+
+    if template.is_empty() {
+        return Ok(Option::None);
+    }
+
+    template::Template::parse(&template).map(Option::Some)
+}
+
+pub fn push_config_from_matches(matches: &ArgMatches) -> Result<push::PushConfig, TrelloBSTError> {
+
+    let mut file_config = try!(load_push_file_config(matches));
+
+    //Targets from the cli options, else the env var, else the configuration file
+    let mut targets = Vec::new();
+    if let Some(cli_targets) = matches.values_of("TARGET") {
+        for target in cli_targets {
+            targets.push(try!(push::PushTarget::from_str(target)));
+        }
+    } else {
+        let env_targets = env::var("TRELLO_API_TARGETS").unwrap_or(String::new());
+        if !env_targets.is_empty() {
+            targets = try!(push::PushTarget::from_str_list(&env_targets));
+        } else {
+            targets = try!(push::PushTarget::from_str_list(&file_config.get("trello_targets")));
+        }
+    }
+
+    let mut push_config = try!(push::PushConfig::fill(matches.value_of("CARD_TITLE").unwrap_or("").to_string(),
+                                                      matches.value_of("CARD_DESC").unwrap_or("").to_string(),
+                                                      matches.value_of("TRELLO_BUILD_PASS_ID").unwrap_or("").to_string(),
+                                                      matches.value_of("TRELLO_BUILD_FAIL_ID").unwrap_or("").to_string(),
+                                                      matches.value_of("TRELLO_LIST_ID").unwrap_or("").to_string(),
+                                                      matches.value_of("TRELLO_API_TOKEN").unwrap_or("").to_string(),
+                                                      matches.value_of("UPDATE_KEY").unwrap_or("").to_string(),
+                                                      targets,
+                                                      &mut file_config));
+
+    if let Some(attachments) = matches.values_of("ATTACH") {
+        push_config.attachments = attachments.map(|attachment| attachment.to_string()).collect();
+    }
+
+    if let Some(junit_reports) = matches.values_of("JUNIT") {
+        push_config.junit_reports = junit_reports.map(|report| report.to_string()).collect();
+    }
+
+    if let Some(tap_reports) = matches.values_of("TAP") {
+        push_config.tap_reports = tap_reports.map(|report| report.to_string()).collect();
+    }
+
+    if let Some(max_attempts) = matches.value_of("MAX_ATTEMPTS") {
+        match max_attempts.parse::<usize>() {
+            Ok(max_attempts) if max_attempts > 0 => push_config.max_attempts = Option::Some(max_attempts),
+            _                                    => return Err(TrelloBSTError::Validation("Error: The maximum number of attempts must be a positive integer.".to_string()))
+        }
+    }
+
+    push_config.spool_dir     = matches.value_of("SPOOL_DIR").map(|spool_dir| spool_dir.to_string()).unwrap_or(spool::default_spool_dir());
+    push_config.label_branch  = matches.is_present("LABEL_BRANCH");
+    push_config.list_changes  = matches.is_present("LIST_CHANGES");
+    push_config.assign_author = matches.is_present("ASSIGN_AUTHOR");
+    push_config.members_file  = matches.value_of("MEMBERS_FILE").unwrap_or(members::DEFAULT_MEMBERS_FILE).to_string();
+
+    push_config.target_policy = match matches.value_of("TARGET_POLICY") {
+        Some(policy) => try!(push::TargetPolicy::from_str(policy)),
+        None         => {
+            let policy = file_config.get("trello_target_policy");
+            if policy.is_empty() {push::TargetPolicy::All} else {try!(push::TargetPolicy::from_str(&policy))}
+        }
+    };
+
+    //Custom field ids from env vars or else from the configuration file
+    for field in custom_fields::BuildField::all() {
+        let field_id = match env::var(field.env_var()) {
+            Ok(field_id) => field_id,
+            Err(_)       => file_config.get(&field.config_key())
+        };
+        if !field_id.is_empty() {
+            push_config.field_ids.insert(field, field_id);
+        }
+    }
+
+    push_config.title_template = try!(template_from_matches(matches, "TITLE_TEMPLATE", "CARD_TITLE", &mut file_config, "card_title_template"));
+    push_config.desc_template  = try!(template_from_matches(matches, "DESC_TEMPLATE",  "CARD_DESC",  &mut file_config, "card_desc_template"));
+
+    if push_config.card_title.is_empty() && push_config.title_template.is_none() {
+        return Err(TrelloBSTError::Validation("Error: No card title given and no CI provider detected, use --title or --title-template to set the card title.".to_string()));
+    }
+
+    Ok(push_config)
+}
+
+//Pushes the card along with its attachments and test results, returns the first error if anything failed.
+//The id of the card is kept in push_config.card_id once pushed.
+//NOTE: Errors are printed as they happen.
+pub fn push_card(term: &mut Box<term::StdoutTerminal>, api_key: &String, build_status: status::BuildStatus, push_config: &mut push::PushConfig) -> Result<(), TrelloBSTError> {
+
+    let mut first_err = Option::None;
+
+    //NOTE: The retry policy is shared by every API call.
+    if let Some(max_attempts) = push_config.max_attempts {
+        utils::set_max_attempts(max_attempts);
+    }
+
+    //Duration of builds not run by "TrelloBST run" or started by "push start", if the CI gives the start time
+    if status::ListStage::from_status(build_status) != status::ListStage::Running {
+        if let Some(started_at) = ci_env::CIEnvironment::detect().and_then(|ci_env| ci_env.started_at) {
+            run::apply_start_time(push_config, started_at);
+        }
+    }
+
+    //Render the card templates
+    if push_config.title_template.is_some() || push_config.desc_template.is_some() {
+        let values = template::values(build_status, &ci_env::CIEnvironment::detect(), &push_config.duration);
+        if let Some(card_title) = push_config.title_template.as_ref().map(|template| template.render(build_status, &values)) {
+            push_config.card_title = card_title;
+        }
+        if let Some(card_desc) = push_config.desc_template.as_ref().map(|template| template.render(build_status, &values)) {
+            push_config.card_desc = card_desc;
+        }
+    }
+
+    if push_config.append_status && push_config.title_template.is_none() {
+        push_config.card_title = format!("{} {}", push_config.card_title, build_status.title());
+    }
+
+    //Add the build result below the description
+    if !push_config.result_desc.is_empty() {
+        if !push_config.card_desc.is_empty() {
+            push_config.card_desc.push_str("\n");
+        }
+        push_config.card_desc.push_str(&push_config.result_desc.clone());
+    }
+
+    //Parse test reports
+    let mut report = reports::TestReport::new();
+    for path in &push_config.junit_reports {
+        let status = utils::StatusPrint::from_string(term, format!("Parsing the JUnit report \"{}\".", path));
+        match report.parse_junit(path) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                keep_error(term, &mut first_err, err);
+            }
+        }
+    }
+    for path in &push_config.tap_reports {
+        let status = utils::StatusPrint::from_string(term, format!("Parsing the TAP report \"{}\".", path));
+        match report.parse_tap(path) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                keep_error(term, &mut first_err, err);
+            }
+        }
+    }
+
+    if !report.cases.is_empty() {
+        push_config.card_title = format!("{} ({})", push_config.card_title, report.summary());
+    }
+
+    //Push to every target
+    let     target_configs = push_config.target_configs();
+    let     is_fan_out     = target_configs.len() > 1;
+    let mut target_results = Vec::new();
+    for (index, mut target_config) in target_configs.into_iter().enumerate() {
+
+        if is_fan_out {
+            println!("Target {} (list {}):", index + 1, target_config.list_id(build_status));
+        }
+
+        let target_result = push_card_to_target(term, api_key, build_status, &mut target_config, &report);
+        if index == 0 {
+            push_config.card_id     = target_config.card_id.clone();
+            push_config.spool_entry = target_config.spool_entry.clone();
+        }
+        if let Some(target) = push_config.targets.get_mut(index) {
+            target.card_id = target_config.card_id.clone();
+        }
+        target_results.push((target_config.list_id(build_status), target_result));
+    }
+
+    if is_fan_out {
+        for (index, &(ref list_id, ref target_result)) in target_results.iter().enumerate() {
+            if target_result.is_ok() {
+                writeln_green!(term, "Target {} (list {}): pushed.", index + 1, list_id);
+            } else {
+                writeln_red!(term, "Target {} (list {}): failed.", index + 1, list_id);
+            }
+        }
+    }
+
+    //The first failed target's error when the target policy isn't met
+    let is_target_success: Vec<bool> = target_results.iter().map(|&(_, ref target_result)| target_result.is_ok()).collect();
+    if !push_config.target_policy.is_success(&is_target_success) {
+        if let Some(err) = target_results.into_iter().filter_map(|(_, target_result)| target_result.err()).next() {
+            if first_err.is_none() {
+                first_err = Option::Some(err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None      => Ok(())
+    }
+}
+
+//Pushes the card to a single target along with its attachments, test results and custom fields.
+//NOTE: Errors are printed as they happen, the first one is returned.
+pub fn push_card_to_target(term: &mut Box<term::StdoutTerminal>, api_key: &String, build_status: status::BuildStatus, push_config: &mut push::PushConfig, report: &reports::TestReport) -> Result<(), TrelloBSTError> {
+
+    let mut first_err = Option::None;
+
+    //List the changes since the last passing build on failing builds
+    //NOTE: Every card gets a line with its branch and commit so that later failing builds can find the last passing one.
+    if push_config.list_changes {
+        match changes::build_commit() {
+            Some((branch, sha)) => {
+                if status::ListStage::from_status(build_status) == status::ListStage::Failed {
+                    let status = utils::StatusPrint::from_str(term, "Listing the commits since the last passing build.");
+                    let section = match changes::last_passing_commit(api_key, push_config, &branch) {
+                        Ok(Some(last_sha)) => changes::commits_since(&last_sha).map(|commits| Some(changes::render(&last_sha, &commits))),
+                        Ok(None)           => Ok(None),
+                        Err(err)           => Err(err)
+                    };
+                    match section {
+                        Ok(section) => {
+                            status.success(term);
+                            if let Some(section) = section {
+                                if !push_config.card_desc.is_empty() {
+                                    push_config.card_desc.push_str("\n\n");
+                                }
+                                push_config.card_desc.push_str(&section);
+                            }
+                        }
+                        Err(err) => {
+                            status.error(term);
+                            print_error(term, &err);
+                            writeln_red!(term, "The commits since the last passing build will not be listed.");
+                        }
+                    }
+                }
+
+                //At the top so that it is never truncated into the description attachment
+                push_config.card_desc = format!("{}\n{}", changes::marker(&branch, &sha), push_config.card_desc);
+            }
+            None => {
+                writeln_red!(term, "The branch and commit being built are unknown, the commits since the last passing build will not be listed.");
+            }
+        }
+    }
+
+    //Branch label
+    let mut label_branch = Option::None;
+    if push_config.label_branch {
+        let status = utils::StatusPrint::from_str(term, "Finding the branch label.");
+        let branch = match ci_env::CIEnvironment::detect() {
+            Some(ref ci_env) if !ci_env.branch.is_empty() => Ok(ci_env.branch.clone()),
+            _                                             => push::title_key(&push_config.card_title, "branch").map(|key| key[0].clone())
+        };
+        match branch.and_then(|branch| branch_labels::branch_label_id(api_key, &push_config.trello_api_token, &push_config.list_id(build_status), &branch).map(|label_id| (branch, label_id))) {
+            Ok((branch, label_id)) => {
+                status.success(term);
+                push_config.extra_label_ids = vec![label_id];
+                label_branch                = Option::Some(branch);
+            }
+            Err(err) => {
+                status.error(term);
+                print_error(term, &err);
+                writeln_red!(term, "The card will not have a branch label.");
+            }
+        }
+    }
+
+    //Assign the commit author to failing builds
+    if push_config.assign_author && status::ListStage::from_status(build_status) == status::ListStage::Failed {
+        let status = utils::StatusPrint::from_str(term, "Resolving the commit author to a board member.");
+        match members::resolve_author(api_key, push_config, &push_config.list_id(build_status)) {
+            Ok(member_id) => {
+                status.success(term);
+                push_config.member_ids = vec![member_id];
+            }
+            Err(err) => {
+                status.error(term);
+                print_error(term, &err);
+                writeln_red!(term, "The card will not be assigned to the commit author.");
+            }
+        }
+    }
+
+    //Push card to Trello
+    let     status = utils::StatusPrint::from_str(term, "Pushing card to Trello.");
+    let mut result = push::push(api_key.clone(), build_status, push_config);
+
+    //The cached branch label may have been deleted from the board, find or create it again and retry
+    if let Some(ref branch) = label_branch {
+        if result.as_ref().err().map(branch_labels::is_stale_label_error).unwrap_or(false) {
+            let list_id = push_config.list_id(build_status);
+            branch_labels::forget_branch_label(&list_id, branch);
+            if let Ok(label_id) = branch_labels::branch_label_id(api_key, &push_config.trello_api_token, &list_id, branch) {
+                push_config.extra_label_ids = vec![label_id];
+                result = push::push(api_key.clone(), build_status, push_config);
+            }
+        }
+    }
+
+    let card_id = match result {
+        Ok(card_id) => {
+            status.success(term);
+            card_id
+        }
+        Err(err) => {
+            status.error(term);
+            let err = err.context("Error while pushing the card to Trello");
+            print_error(term, &err);
+            if spool::is_spoolable(&err) {
+                if let Some(path) = spool_card(term, build_status, push_config) {
+                    push_config.spool_entry = path.to_string_lossy().to_string();
+                }
+            }
+            return Err(err);
+        }
+    };
+
+    push_config.card_id = card_id.clone();
+
+    //Build metadata
+    if !push_config.field_ids.is_empty() {
+        let status = utils::StatusPrint::from_str(term, "Filling the card's custom fields.");
+        match custom_fields::fill_fields(api_key, push_config, &card_id) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                keep_error(term, &mut first_err, err);
+            }
+        }
+    }
+
+    //Attachments
+    if let Err(err) = push_attachments(term, api_key, push_config, &card_id) {
+        if first_err.is_none() {
+            first_err = Option::Some(err);
+        }
+    }
+
+    //Test results
+    if !report.cases.is_empty() {
+        let status = utils::StatusPrint::from_str(term, "Adding the test results to the card.");
+        match reports::push_checklists(api_key, push_config, &card_id, report) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                keep_error(term, &mut first_err, err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None      => Ok(())
+    }
+}
+
+//Spools a card that could not be pushed so "flush" can push it later, returns the spool entry file.
+pub fn spool_card(term: &mut Box<term::StdoutTerminal>, build_status: status::BuildStatus, push_config: &push::PushConfig) -> Option<PathBuf> {
+
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_)       => 0
+    };
+
+    match spool::spool(&push_config.spool_dir, &push_config.spool_entry(build_status, timestamp)) {
+        Ok(path) => {
+            writeln_red!(term, "The card has been spooled to {}, use \"TrelloBST flush\" to push it once Trello is reachable.", path.display());
+            Some(path)
+        }
+        Err(err) => {
+            print_error(term, &err);
+            None
+        }
+    }
+}
+
+//Attaches every --attach value to the card, returns the first error if any of them failed.
+//NOTE: Errors are printed as they happen.
+pub fn push_attachments(term: &mut Box<term::StdoutTerminal>, api_key: &String, push_config: &push::PushConfig, card_id: &String) -> Result<(), TrelloBSTError> {
+
+    let mut first_err = Option::None;
+    for attachment in &push_config.attachments {
+        let status = utils::StatusPrint::from_string(term, format!("Attaching \"{}\" to the card.", attachment));
+        match push::attach(api_key, push_config, card_id, attachment) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                keep_error(term, &mut first_err, err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None      => Ok(())
+    }
+}
+
+
 ////////////////////////////////////////////////////////////
 //                          Main                          //
 ////////////////////////////////////////////////////////////
@@ -139,57 +917,128 @@ fn main() {
     let matches = App::new("TrelloBST")
     .version(trellobst_version)
     .setting(AppSettings::SubcommandsNegateReqs)
-    .subcommand(SubCommand::with_name("push")
+    .subcommand(push_status_args(push_args(SubCommand::with_name("push")
                 .about("Pushes a build status to a trello board")
-                .arg(Arg::with_name("CARD_TITLE")
-                     .short("t")
-                     .long("title")
-                     .help("Sets the title of the card.")
+                .setting(AppSettings::SubcommandsNegateReqs)))
+                .subcommand(push_args(SubCommand::with_name("start")
+                            .about("Pushes a running build card to the running builds list and saves its id for \"push finish\""))
+                            .arg(state_file_arg()))
+                .subcommand(push_status_args(push_args(SubCommand::with_name("finish")
+                            .about("Updates the card pushed by \"push start\" with the build status and moves it to the matching list")))
+                            .arg(state_file_arg()))
+    )
+    .subcommand(push_args(SubCommand::with_name("run")
+                .about("Runs a build command and pushes its result to a trello board")
+                .setting(AppSettings::TrailingVarArg))
+                .arg(Arg::with_name("LOG_LINES")
+                     .short("l")
+                     .long("log-lines")
+                     .help("Sets the number of trailing log lines included in the card description. (Default: 20)")
                      .takes_value(true)
+                     .required(false))
+                .arg(Arg::with_name("COMMAND")
+                     .help("The build command to run, use \"--\" to separate it from TrelloBST's options.")
+                     .multiple(true)
                      .required(true))
-                .arg(Arg::with_name("CARD_DESC")
-                     .short("d")
-                     .long("description")
-                     .help("Sets the description of the card.")
+    )
+    .subcommand(push_config_file_args(SubCommand::with_name("flush")
+                .about("Pushes the spooled cards that could not be pushed earlier, oldest first"))
+                .arg(Arg::with_name("SPOOL_DIR")
+                     .long("spool-dir")
+                     .help("Sets the spool directory, overrides the \"TRELLOBST_SPOOL_DIR\" environment variable. (Default: ~/.TrelloBST.spool)")
                      .takes_value(true)
                      .required(false))
-                .arg(Arg::with_name("BUILD_PASS")
-                     .conflicts_with("BUILD_FAIL")
-                     .short("p")
-                     .long("pass")
-                     .help("Sets build status to passed.")
-                     .takes_value(false))
-                .arg(Arg::with_name("BUILD_FAIL")
-                     .conflicts_with("BUILD_PASS")
-                     .short("f")
-                     .long("fail")
-                     .help("Sets build status to failed.")
-                     .takes_value(false))
                 .arg(Arg::with_name("TRELLO_API_TOKEN")
                      .short("T")
                      .long("token")
                      .help("Manually overrides the trello api token from the \"TRELLO_API_TOKEN\" environment variable.")
                      .takes_value(true)
                      .required(false))
+    )
+    .subcommand(token_arg(push_config_file_args(SubCommand::with_name("prune")
+                .about("Archives old build cards from the build status lists")))
+                .arg(Arg::with_name("KEEP")
+                     .short("k")
+                     .long("keep")
+                     .help("Sets the number of most recent cards kept for each group of cards.")
+                     .takes_value(true)
+                     .required(false))
+                .arg(Arg::with_name("PER")
+                     .long("per")
+                     .help("Sets the title tags used to group cards (ex: \"branch\" or \"branch+os\"). (Default: branch)")
+                     .takes_value(true)
+                     .required(false))
+                .arg(Arg::with_name("OLDER_THAN")
+                     .long("older-than")
+                     .help("Only archives cards older than the given age (ex: \"30d\", \"12h\" or \"2w\").")
+                     .takes_value(true)
+                     .required(false))
                 .arg(Arg::with_name("TRELLO_LIST_ID")
                      .short("L")
                      .long("list-id")
-                     .help("Manually overrides the trello list id from the \"TRELLO_API_LIST_ID\" environment variable.")
+                     .help("Prunes the given list instead of the lists from the configuration file.")
                      .takes_value(true)
                      .required(false))
-                .arg(Arg::with_name("TRELLO_BUILD_PASS_ID")
-                     .short("P")
-                     .long("pass-id")
-                     .help("Manually overrides the trello build pass id from the \"TRELLO_API_BUILD_PASS_ID\" environment variable.")
+                .arg(Arg::with_name("DRY_RUN")
+                     .long("dry-run")
+                     .help("Prints the cards that would be archived without archiving them.")
+                     .takes_value(false))
+    )
+    .subcommand(SubCommand::with_name("doctor")
+                .about("Checks the configuration file against the Trello, Travis CI and AppVeyor APIs")
+    )
+    .subcommand(SubCommand::with_name("setup")
+                .about("Sets up the trello board without prompting, for provisioning scripts")
+                .arg(Arg::with_name("BOARD")
+                     .long("board")
+                     .help("Name or id of the board, the name of the new board with --template.")
+                     .takes_value(true)
+                     .required(true))
+                .arg(Arg::with_name("CREATE_BOARD")
+                     .long("create-board")
+                     .help("Creates the board, its list and its labels if no open board has this name or id.")
+                     .takes_value(false))
+                .arg(Arg::with_name("LIST")
+                     .long("list")
+                     .help("Name or id of the list the build statuses are pushed to.")
+                     .takes_value(true)
+                     .required_unless("TEMPLATE"))
+                .arg(Arg::with_name("PASS_LABEL")
+                     .long("pass-label")
+                     .help("Label of the passed builds as <name|id>[:color], the color is needed to create it.")
+                     .takes_value(true)
+                     .required_unless("TEMPLATE"))
+                .arg(Arg::with_name("FAIL_LABEL")
+                     .long("fail-label")
+                     .help("Label of the failed builds as <name|id>[:color], the color is needed to create it.")
+                     .takes_value(true)
+                     .required_unless("TEMPLATE"))
+                .arg(Arg::with_name("TEMPLATE")
+                     .conflicts_with_all(&["CREATE_BOARD", "LIST", "PASS_LABEL", "FAIL_LABEL"])
+                     .long("template")
+                     .help("Creates a new board from a board template: \"kanban\", a ~/.TrelloBST.templates/<name>.json file or the path of a template file.")
                      .takes_value(true)
                      .required(false))
-                .arg(Arg::with_name("TRELLO_BUILD_FAIL_ID")
-                     .short("F")
-                     .long("fail-id")
-                     .help("Manually overrides the trello build fail id from the \"TRELLO_API_FAIL_PASS_ID\" environment variable.")
+                .arg(Arg::with_name("YES")
+                     .short("y")
+                     .long("yes")
+                     .help("Creates the list and labels that are missing from the board.")
+                     .takes_value(false))
+                .arg(Arg::with_name("TRELLO_API_TOKEN")
+                     .short("T")
+                     .long("token")
+                     .help("Sets the trello api token, overrides the \"TRELLO_API_TOKEN\" environment variable and the configuration file.")
                      .takes_value(true)
                      .required(false))
     )
+    .subcommand(SubCommand::with_name("token")
+                .about("Shows or revokes the trello api token")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(token_arg(SubCommand::with_name("info")
+                            .about("Shows when the token expires and what it can access")))
+                .subcommand(token_arg(SubCommand::with_name("revoke")
+                            .about("Revokes the token on Trello and removes it from the configuration file")))
+    )
     .arg(Arg::with_name("CONFIG")
          .conflicts_with("NO-CONFIG")
          .short("c")
@@ -203,6 +1052,20 @@ fn main() {
          .long("no-config")
          .help("Won't use a configuration file for TrelloBST.")
          .takes_value(false))
+    .arg(Arg::with_name("PROFILE")
+         .conflicts_with_all(&["CONFIG", "NO-CONFIG"])
+         .long("profile")
+         .help("Uses the ~/.TrelloBST.<name>.cfg configuration file, \"push --profile <name>\" reads from it.")
+         .takes_value(true))
+    .arg(Arg::with_name("TOKEN_EXPIRATION")
+         .long("token-expiration")
+         .help("Sets when the trello api token made by the setup expires: 1day, 30days or never. (Asked if not set)")
+         .possible_values(&authorize::EXPIRATIONS)
+         .takes_value(true))
+    .arg(Arg::with_name("TOKEN_SCOPE")
+         .long("token-scope")
+         .help("Sets the scopes of the trello api token made by the setup, comma separated: read, write and account, write is required. (Asked if not set)")
+         .takes_value(true))
     .arg(Arg::with_name("OUTPUT_DIR")
          .conflicts_with("PRINT_OUTPUT")
          .short("o")
@@ -221,99 +1084,527 @@ fn main() {
 
     if let Some(push_matches) = matches.subcommand_matches("push") {
 
-        //Validate --pass --fail
-        if !push_matches.is_present("BUILD_PASS") && !push_matches.is_present("BUILD_FAIL") {
-            writeln_red!(term, "Error: One of --pass and --fail must be used.");
-            exit(-1);
+        //Start a build, the card goes in the running builds list
+        if let Some(start_matches) = push_matches.subcommand_matches("start") {
+
+            let state_file  = start_matches.value_of("STATE_FILE").unwrap_or("./.TrelloBST.state");
+            let mut push_config = match push_config_from_matches(start_matches) {
+                Ok(config) => config,
+                Err(err)   => {
+                    exit_with_error(&mut term, err);
+                }
+            };
+
+            //Kept so "push finish" can fill the build duration
+            let started_at = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(now) => now.as_secs(),
+                Err(_)  => 0
+            };
+
+            //NOTE: The state is saved even if the push failed, as long as the card was created or spooled, so that
+            //      "push finish" doesn't push a second card.
+            let push_result     = push_card(&mut term, &trello_api_key.to_string(), status::BuildStatus::Running, &mut push_config);
+            let exit_code       = push_result.err().map(|err| err.exit_code()).unwrap_or(0);
+            let target_card_ids = push_config.targets.iter().skip(1).map(|target| target.card_id.clone()).collect::<Vec<String>>();
+            if push_config.card_id.is_empty() && push_config.spool_entry.is_empty() && target_card_ids.iter().all(|card_id| card_id.is_empty()) {
+                exit(exit_code);
+            }
+
+            //Kept so a spooled "push finish" doesn't overwrite a card changed since
+            let last_activity = if push_config.card_id.is_empty() {
+                String::new()
+            } else {
+                push::last_activity(trello_api_key, &push_config.trello_api_token, &push_config.card_id).unwrap_or(String::new())
+            };
+
+            let build_state = push::BuildState {
+                card_id:         push_config.card_id.clone(),
+                started_at:      started_at,
+                last_activity:   last_activity,
+                spool_entry:     push_config.spool_entry.clone(),
+                spooled_title:   if push_config.spool_entry.is_empty() {String::new()} else {push_config.card_title.clone()},
+                target_card_ids: target_card_ids
+            };
+            match push::save_build_state(state_file, &build_state) {
+                Ok(())   => exit(exit_code),
+                Err(err) => {
+                    exit_with_error(&mut term, err);
+                }
+            }
         }
 
+        //Finish a build, the card started by "push start" is updated and moved
+        if let Some(finish_matches) = push_matches.subcommand_matches("finish") {
+
+            let state_file   = finish_matches.value_of("STATE_FILE").unwrap_or("./.TrelloBST.state");
+            let build_status = match build_status_from_matches(finish_matches) {
+                Ok(build_status) => build_status,
+                Err(err)         => {
+                    exit_with_error(&mut term, err);
+                }
+            };
+
+            let mut push_config = match push_config_from_matches(finish_matches) {
+                Ok(config) => config,
+                Err(err)   => {
+                    exit_with_error(&mut term, err);
+                }
+            };
+
+            match push::load_build_state(state_file) {
+                Ok(build_state) => {
+                    push_config.card_id       = build_state.card_id;
+                    push_config.last_activity = build_state.last_activity;
+                    run::apply_start_time(&mut push_config, build_state.started_at);
+
+                    //NOTE: The targets are given again to "push finish", in the same order.
+                    for (target, card_id) in push_config.targets.iter_mut().skip(1).zip(build_state.target_card_ids) {
+                        target.card_id = card_id;
+                    }
+
+                    //The running card was spooled, use it once flushed, else drop it from the spool
+                    if push_config.card_id.is_empty() {
+                        let list_id  = push_config.list_id(status::BuildStatus::Running);
+                        let label_id = push_config.label_id(status::BuildStatus::Running).unwrap_or(String::new());
+                        match spool::take_spooled_card(trello_api_key, &push_config.trello_api_token, &build_state.spool_entry, &list_id, &build_state.spooled_title, &label_id) {
+                            Ok(Some(card_id)) => push_config.card_id = card_id,
+                            Ok(None)          => writeln_red!(term, "The running build card is not on Trello, a new card will be pushed instead."),
+                            Err(err)          => {
+                                print_error(&mut term, &err);
+                                writeln_red!(term, "A new card will be pushed instead.");
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    print_error(&mut term, &err);
+                    writeln_red!(term, "A new card will be pushed instead.");
+                }
+            }
+
+            match push_card(&mut term, &trello_api_key.to_string(), build_status, &mut push_config) {
+                Ok(())   => {
+                    let _ = fs::remove_file(state_file);
+                    exit(0);
+                }
+                Err(err) => exit(err.exit_code())
+            }
+        }
+
+        //Validate --pass --fail --status
+        let build_status = match build_status_from_matches(push_matches) {
+            Ok(build_status) => build_status,
+            Err(err)         => {
+                exit_with_error(&mut term, err);
+            }
+        };
+
         //Create config struct
-        let status = utils::StatusPrint::from_str(&mut term, "Pushing card to Trello.");
-        let push_config = match push::PushConfig::fill(push_matches.value_of("CARD_TITLE").unwrap().to_string(),
-                                    push_matches.value_of("CARD_DESC").unwrap_or("").to_string(),
-                                    push_matches.value_of("TRELLO_BUILD_PASS_ID").unwrap_or("").to_string(),
-                                    push_matches.value_of("TRELLO_BUILD_FAIL_ID").unwrap_or("").to_string(),
-                                    push_matches.value_of("TRELLO_LIST_ID").unwrap_or("").to_string(),
-                                    push_matches.value_of("TRELLO_API_TOKEN").unwrap_or("").to_string()) {
+        let mut push_config = match push_config_from_matches(push_matches) {
             Ok(config) => config,
             Err(err)   => {
-                status.error(&mut term);
-                writeln_red!(term, "{}", err);
-                exit(-1);
+                exit_with_error(&mut term, err);
+            }
+        };
+
+        //Push card to Trello, errors are already printed
+        match push_card(&mut term, &trello_api_key.to_string(), build_status, &mut push_config) {
+            Ok(())   => exit(0),
+            Err(err) => exit(err.exit_code())
+        }
+    }
+
+
+    if let Some(run_matches) = matches.subcommand_matches("run") {
+
+        let command: Vec<String> = run_matches.values_of("COMMAND").unwrap().map(|arg| arg.to_string()).collect();
+        let log_lines: usize     = match run_matches.value_of("LOG_LINES").unwrap_or("20").parse::<usize>() {
+            Ok(log_lines) => log_lines,
+            Err(_)        => {
+                exit_with_error(&mut term, TrelloBSTError::Validation("Error: The number of log lines must be a number, 0 leaves the log out of the card.".to_string()));
+            }
+        };
+
+        //NOTE: The command still runs if the push config is invalid (ex: secure variables unavailable on pull requests).
+        let push_config = push_config_from_matches(run_matches);
+
+        //Run the build
+        let result = match run::run(&command, log_lines) {
+            Ok(result) => result,
+            Err(err)   => {
+                exit_with_error(&mut term, err.context("Error while running the build command"));
             }
         };
 
         //Push card to Trello
-        match push::push(trello_api_key.to_string(), push_matches.is_present("BUILD_PASS"), push_config) {
-            Ok(()) => {
+        match push_config {
+            Ok(mut push_config) => {
+                run::apply_result(&mut push_config, &result);
+
+                //A failed build keeps its exit code, a passed build exits with the push error's
+                if let Err(err) = push_card(&mut term, &trello_api_key.to_string(), result.status(), &mut push_config) {
+                    if result.exit_code == 0 {
+                        writeln_red!(term, "The build passed but its status could not be pushed to Trello.");
+                        exit(err.exit_code());
+                    }
+                }
+            }
+            Err(err) => {
+                print_error(&mut term, &err);
+                writeln_red!(term, "The build status will not be pushed to Trello.");
+            }
+        }
+
+        exit(result.exit_code);
+    }
+
+
+    if let Some(flush_matches) = matches.subcommand_matches("flush") {
+
+        let spool_dir = flush_matches.value_of("SPOOL_DIR").map(|spool_dir| spool_dir.to_string()).unwrap_or(spool::default_spool_dir());
+
+        //Api token from the cli option, the env var or the configuration file, empty values are skipped as push does
+        let mut file_config = match load_push_file_config(flush_matches) {
+            Ok(config) => config,
+            Err(err)   => {
+                exit_with_error(&mut term, err);
+            }
+        };
+        let trello_api_token = push::env_or_config(flush_matches.value_of("TRELLO_API_TOKEN").unwrap_or("").to_string(), "TRELLO_API_TOKEN", &mut file_config, "trello_api_token");
+
+        if trello_api_token.is_empty() {
+            exit_with_error(&mut term, TrelloBSTError::Validation("Error: No Trello API token found, use --token, the \"TRELLO_API_TOKEN\" environment variable or a configuration file with a token.".to_string()));
+        }
+
+        let entries = match spool::entries(&spool_dir) {
+            Ok(entries) => entries,
+            Err(err)    => {
+                exit_with_error(&mut term, err);
+            }
+        };
+
+        if entries.is_empty() {
+            println!("No spooled cards to push.");
+            exit(0);
+        }
+
+        //Replay in order, stop at the first failure so the order is kept
+        for (path, entry) in entries {
+
+            let status = utils::StatusPrint::from_string(&mut term, format!("Pushing \"{}\".", entry.title));
+
+            let result = match spool::is_delivered(trello_api_key, &trello_api_token, &entry) {
+                Ok(true)  => Ok(()),
+                Ok(false) => {
+                    let mut label_ids = vec![entry.label_id.clone()];
+                    label_ids.extend(entry.extra_label_ids.iter().cloned());
+                    label_ids.retain(|label_id| !label_id.is_empty());
+                    push::send_card(trello_api_key, &trello_api_token, &entry.card_id, &entry.list_id, &entry.title, &entry.desc, &label_ids, &entry.member_ids).map(|_| ())
+                }
+                Err(err)  => Err(err)
+            };
+
+            match result.and_then(|()| spool::remove(&path)) {
+                Ok(())   => status.success(&mut term),
+                Err(err) => {
+                    status.error(&mut term);
+                    writeln_red!(term, "The remaining cards are kept in {}.", spool_dir);
+                    exit_with_error(&mut term, err);
+                }
+            }
+        }
+
+        exit(0);
+    }
+
+
+    if let Some(prune_matches) = matches.subcommand_matches("prune") {
+
+        //The configuration file given to prune, else the one given before it
+        let mut config = if prune_matches.is_present("PUSH_CONFIG") || prune_matches.is_present("PROFILE") {
+            match load_push_file_config(prune_matches) {
+                Ok(config) => config,
+                Err(err)   => {
+                    exit_with_error(&mut term, err);
+                }
+            }
+        } else {
+            let config_mode = config_mode_from_matches(&mut term, &matches);
+            load_config(&mut term, config_mode)
+        };
+
+        //Api token from the cli option, the env var or the configuration file
+        let trello_api_token = push::env_or_config(prune_matches.value_of("TRELLO_API_TOKEN").unwrap_or("").to_string(), "TRELLO_API_TOKEN", &mut config, "trello_api_token");
+        if trello_api_token.is_empty() {
+            exit_with_error(&mut term, TrelloBSTError::Validation("Error: No Trello API token found, use --token, the \"TRELLO_API_TOKEN\" environment variable or a configuration file with a token.".to_string()));
+        }
+
+        //Lists to prune
+        let mut list_ids = Vec::new();
+        if let Some(list_id) = prune_matches.value_of("TRELLO_LIST_ID") {
+            list_ids.push(list_id.to_string());
+        } else {
+            list_ids.push(config.get("trello_list_id"));
+            for stage in status::ListStage::all() {
+                list_ids.push(config.get(&stage.config_key()));
+            }
+            list_ids.retain(|list_id| !list_id.is_empty());
+        }
+
+        if list_ids.is_empty() {
+            exit_with_error(&mut term, TrelloBSTError::Validation("Error: No list id found in the configuration file, run the setup or use --list-id.".to_string()));
+        }
+
+        let keep = match prune_matches.value_of("KEEP") {
+            Some(keep) => match keep.parse::<usize>() {
+                Ok(keep) if keep > 0 => Option::Some(keep),
+                _                    => {
+                    exit_with_error(&mut term, TrelloBSTError::Validation("Error: The number of cards to keep must be a positive integer.".to_string()));
+                }
+            },
+            None => Option::None
+        };
+
+        let older_than = match prune_matches.value_of("OLDER_THAN") {
+            Some(older_than) => match prune::parse_age(older_than) {
+                Ok(older_than) => Option::Some(older_than),
+                Err(err)       => {
+                    exit_with_error(&mut term, err);
+                }
+            },
+            None => Option::None
+        };
+
+        let prune_config = prune::PruneConfig {
+            trello_api_token: trello_api_token,
+            list_ids:         list_ids,
+            keep:             keep,
+            per:              prune_matches.value_of("PER").unwrap_or("branch").to_string(),
+            older_than:       older_than
+        };
+
+        //Find cards to archive
+        let status = utils::StatusPrint::from_str(&mut term, "Acquiring the build cards from Trello.");
+        let cards  = match prune::find_prunable_cards(&trello_api_key.to_string(), &prune_config) {
+            Ok(cards) => {
                 status.success(&mut term);
-                exit(0);
+                cards
             }
             Err(err) => {
                 status.error(&mut term);
-                writeln_red!(term, "Error while pushing the card to Trello: {}", err);
-                exit(-1);
+                exit_with_error(&mut term, err);
+            }
+        };
+
+        if cards.is_empty() {
+            println!("No cards to archive.");
+            exit(0);
+        }
+
+        //Archive
+        let mut is_success = true;
+        for card in &cards {
+            if prune_matches.is_present("DRY_RUN") {
+                println!("Would archive: {}", card.name);
+                continue;
+            }
+
+            let status = utils::StatusPrint::from_string(&mut term, format!("Archiving \"{}\".", card.name));
+            match prune::archive_card(&trello_api_key.to_string(), &prune_config, &card.id) {
+                Ok(())   => status.success(&mut term),
+                Err(err) => {
+                    status.error(&mut term);
+                    print_error(&mut term, &err);
+                    is_success = false;
+                }
             }
         }
+
+        if is_success {
+            exit(0);
+        } else {
+            exit(1);
+        }
     }
 
 
-    //If push subcommand not used i.e. generate a CI config
-    let mut config_mode: Option<PathBuf> = Option::None;
-    let mut output_mode: Option<PathBuf> = Option::None;
+    if matches.subcommand_matches("doctor").is_some() {
 
-    //Check config file cli options
-    if !matches.is_present("CONFIG") && !matches.is_present("NO_CONFIG") {
+        let config_mode = config_mode_from_matches(&mut term, &matches);
+        let mut config  = load_config(&mut term, config_mode);
 
-        //Default config
-        //Check if home directory works
-        let valid_path_found: bool;
-        match env::home_dir() {
-            Some(home_dir) => {
-                let mut config_file_path = home_dir;
-                config_file_path.push(".TrelloBST.cfg");
-                match file_path_validator(config_file_path.to_str().unwrap_or("~/.TrelloBST.cfg").to_string()) {
-                    Ok(()) => {
-                        println!("Config file location set to: {:?}", config_file_path);
-                        config_mode = Option::Some(config_file_path);
-                        valid_path_found = true;
-                    }
-                    Err(_) => {valid_path_found = false}
+        let mut is_healthy = true;
+        for finding in doctor::diagnose(&trello_api_key, &mut config) {
+            match finding.severity {
+                doctor::Severity::Ok      => {writeln_green!(term, "[OK]   {}", finding.message);}
+                doctor::Severity::Warning => {writeln_red!(term, "[WARN] {}", finding.message);}
+                doctor::Severity::Error   => {
+                    writeln_red!(term, "[FAIL] {}", finding.message);
+                    is_healthy = false;
                 }
             }
-            None           => {
-                writeln_red!(term, "Error: Failed to acquire the home directory path.");
-                valid_path_found = false;
+            if !finding.fix.is_empty() {
+                println!("       Fix: {}", finding.fix);
             }
         }
 
-        if !valid_path_found {
+        //NOTE: Failed checks are findings rather than errors, they exit with 1 like a failed build, see TrelloBSTError::exit_code.
+        if is_healthy {
+            exit(0);
+        } else {
+            exit(1);
+        }
+    }
 
-            let config_file_path_str = "./.TrelloBST.cfg".to_string();
-            let config_file_path = PathBuf::from(&config_file_path_str);
 
-            writeln_red!(term, "Error: Failed to read/create the configuration file in the home directory. Falling back to ./.TrelloBST.cfg");
-            match file_path_validator(config_file_path_str.clone()) {
-                Ok(()) => {
-                    config_mode = Option::Some(PathBuf::from(config_file_path));
-                    println!("Config file location set to: {}", config_file_path_str);
-                }
-                Err(_) => {
-                    config_mode = Option::None;
-                    writeln_red!(term, "Error: Failed to read/create the configuration file at ./.TrelloBST.cfg. TrelloBST will continue without saving inputted values into the configuration file.");
+    if let Some(setup_matches) = matches.subcommand_matches("setup") {
+
+        let config_mode = config_mode_from_matches(&mut term, &matches);
+        let mut config  = load_config(&mut term, config_mode);
+
+        let trello_api_token = match setup_matches.value_of("TRELLO_API_TOKEN") {
+            Some(token) => token.to_string(),
+            None        => env::var("TRELLO_API_TOKEN").unwrap_or(config.get("trello_api_token"))
+        };
+        if trello_api_token.is_empty() {
+            exit_with_error(&mut term, TrelloBSTError::Validation("Error: No trello api token, use --token, the TRELLO_API_TOKEN environment variable or a configuration file with a token.".to_string()));
+        }
+
+        //Board template
+        if let Some(template) = setup_matches.value_of("TEMPLATE") {
+
+            let board_template = match setup::BoardTemplate::load(template) {
+                Ok(board_template) => board_template,
+                Err(err)           => {exit_with_error(&mut term, err);}
+            };
+
+            match setup::setup_from_template(&mut term, &trello_api_key, &mut config, &trello_api_token, setup_matches.value_of("BOARD").unwrap(), &board_template) {
+                Ok(())   => (),
+                Err(err) => {exit_with_error(&mut term, err.context("A fatal error occured while setting up the trello board"));}
+            }
+
+            match config.save() {
+                Ok(())   => exit(0),
+                Err(err) => {exit_with_error(&mut term, err);}
+            }
+        }
+
+        let mut label_specs = Vec::new();
+        for arg_name in &["PASS_LABEL", "FAIL_LABEL"] {
+            match setup::LabelSpec::parse(setup_matches.value_of(arg_name).unwrap()) {
+                Ok(label_spec) => label_specs.push(label_spec),
+                Err(err)       => {exit_with_error(&mut term, err);}
+            }
+        }
+        let fail_label = label_specs.pop().unwrap();
+        let pass_label = label_specs.pop().unwrap();
+
+        let options = setup::SetupOptions {
+            trello_api_token: trello_api_token,
+            board:            setup_matches.value_of("BOARD").unwrap().to_string(),
+            create_board:     setup_matches.is_present("CREATE_BOARD"),
+            list:             setup_matches.value_of("LIST").unwrap().to_string(),
+            pass_label:       pass_label,
+            fail_label:       fail_label,
+            assume_yes:       setup_matches.is_present("YES")
+        };
+
+        match setup::setup(&mut term, &trello_api_key, &mut config, &options) {
+            Ok(())   => (),
+            Err(err) => {exit_with_error(&mut term, err.context("A fatal error occured while setting up the trello board"));}
+        }
+
+        match config.save() {
+            Ok(())   => exit(0),
+            Err(err) => {exit_with_error(&mut term, err);}
+        }
+    }
+
+
+    if let Some(token_matches) = matches.subcommand_matches("token") {
+
+        let config_mode = config_mode_from_matches(&mut term, &matches);
+        let mut config  = load_config(&mut term, config_mode);
+
+        let (action, action_matches) = match token_matches.subcommand() {
+            (action, Some(action_matches)) => (action, action_matches),
+            _                              => exit(1)
+        };
+
+        let trello_api_token = match action_matches.value_of("TRELLO_API_TOKEN") {
+            Some(token) => token.to_string(),
+            None        => env::var("TRELLO_API_TOKEN").unwrap_or(config.get("trello_api_token"))
+        };
+        if trello_api_token.is_empty() {
+            exit_with_error(&mut term, TrelloBSTError::Validation("Error: No trello api token, use --token, the TRELLO_API_TOKEN environment variable or a configuration file with a token.".to_string()));
+        }
+        let client = trello_client::TrelloClient::new(&trello_api_key, &trello_api_token);
+
+        if action == "info" {
+
+            let token = match client.token_info() {
+                Ok(token) => token,
+                Err(err)  => {exit_with_error(&mut term, err.context("Failed to get the token's information"));}
+            };
+
+            println!("Created:     {}", token.date_created);
+            match token.date_expires {
+                Some(date_expires) => println!("Expires:     {}", date_expires),
+                None               => println!("Expires:     never")
+            }
+            for permission in &token.permissions {
+                let access = match (permission.read, permission.write) {
+                    (true, true)  => "read, write",
+                    (true, false) => "read",
+                    (false, true) => "write",
+                    _             => "none"
+                };
+                let model = if permission.id_model == "*" {"all".to_string()} else {permission.id_model.clone()};
+                println!("Permission:  {} {} ({})", model, permission.model_type, access);
+            }
+            exit(0);
+        }
+
+        //Revoke
+        let status = utils::StatusPrint::from_str(&mut term, "Revoking the token.");
+        match client.revoke_token() {
+            Ok(())   => {status.success(&mut term);}
+            Err(err) => {
+                status.error(&mut term);
+
+                //A token Trello doesn't know is as good as revoked, it's still removed from the configuration file
+                let is_unknown_token = match *err.root() {
+                    TrelloBSTError::HttpStatus {status, ..} => status == 401 || status == 404,
+                    TrelloBSTError::Api {ref message, ..}   => message == "invalid token",
+                    _                                       => false
+                };
+                if !is_unknown_token {
+                    exit_with_error(&mut term, err.context("Failed to revoke the token"));
                 }
+                print_error(&mut term, &err);
+                writeln_red!(term, "Trello doesn't know the token, it was already revoked or it expired.");
             }
         }
 
-    } else if matches.is_present("CONFIG") {
-        //Custom config
-        println!("Config file location set to: {}", matches.value_of("CONFIG").unwrap());
-        config_mode = Option::Some(PathBuf::from(matches.value_of("CONFIG").unwrap()));
-    } else if matches.is_present("NO_CONFIG") {
-        config_mode = Option::None;
+        //Only the revoked token is removed, the configuration file may hold another one.
+        if config.get("trello_api_token") == trello_api_token {
+            config.set("trello_api_token", "");
+            match config.save() {
+                Ok(())   => println!("The token was removed from the configuration file."),
+                Err(err) => {exit_with_error(&mut term, err);}
+            }
+        }
+        exit(0);
     }
 
 
+    //If push subcommand not used i.e. generate a CI config
+    let     config_mode: Option<PathBuf>;
+    let mut output_mode: Option<PathBuf> = Option::None;
+
+    config_mode = config_mode_from_matches(&mut term, &matches);
+
+
     //Check CI config output cli options
     if !matches.is_present("OUTPUT_DIR") && !matches.is_present("PRINT_OUTPUT") {
 
@@ -339,37 +1630,58 @@ fn main() {
 
 
     //Load Config
-    let mut status = utils::StatusPrint::from_str(&mut term, "Parsing the configuration file...");
-    let mut config = config::TrelloBSTConfig::new();
-
-    match config.load(config_mode) {
-        Ok(())   => {status.success(&mut term);},
-        Err(err) => {
-            status.error(&mut term);
-            writeln_red!(term, "{}", err);
-        }
-    }
+    let mut config = load_config(&mut term, config_mode);
 
     //Setup Trello API values
     let mut trello: trello::Trello = trello::Trello::new();
-    trello.setup_api_token(&mut term, &trello_api_key, &mut config);
+    let token_request = if matches.is_present("TOKEN_EXPIRATION") || matches.is_present("TOKEN_SCOPE") {
+        let default_request = authorize::TokenRequest::default();
+        let expiration      = matches.value_of("TOKEN_EXPIRATION").unwrap_or(&default_request.expiration).to_string();
+        let scopes          = matches.value_of("TOKEN_SCOPE").map(|scopes| scopes.to_string()).unwrap_or(default_request.scope());
+        match authorize::TokenRequest::parse(&expiration, &scopes) {
+            Ok(token_request) => Option::Some(token_request),
+            Err(err)          => {exit_with_error(&mut term, err);}
+        }
+    } else {
+        Option::None
+    };
+    trello.setup_api_token(&mut term, &trello_api_key, &mut config, token_request);
 
     //  Select/Create the board (get an id)
     match trello.setup_board(&mut term, &trello_api_key, &mut config) {
         Ok(())   => (),
-        Err(err) => {panic!("A fatal error occured while settting up the trello board: {}", err);}
+        Err(err) => {exit_with_error(&mut term, err.context("A fatal error occured while setting up the trello board"));}
     }
 
     //  Select/Create the list (get an id)
     match trello.setup_list(&mut term, &trello_api_key, &mut config) {
         Ok(())   => (),
-        Err(err) => {panic!("A fatal error occured while settting up the trello board: {}", err);}
+        Err(err) => {exit_with_error(&mut term, err.context("A fatal error occured while setting up the trello board"));}
     }
 
     //  Select/Create the labels (get an id)
     match trello.setup_labels(&mut term, &trello_api_key, &mut config) {
         Ok(())   => (),
-        Err(err) => {panic!("A fatal error occured while settting up the trello board: {}", err);}
+        Err(err) => {exit_with_error(&mut term, err.context("A fatal error occured while setting up the trello board"));}
+    }
+
+    //  Select/Create the custom fields (get an id)
+    match trello.setup_custom_fields(&mut term, &trello_api_key, &mut config) {
+        Ok(())   => (),
+        Err(err) => {print_error(&mut term, &err.context("Error while setting up the custom fields"));}
+    }
+
+    //  Map git authors to board members, the mapping goes next to the CI config file
+    let members_file = match output_mode.clone() {
+        Some(mut dirpath) => {
+            dirpath.push(".TrelloBST.members");
+            dirpath.to_string_lossy().to_string()
+        }
+        None => members::DEFAULT_MEMBERS_FILE.to_string()
+    };
+    match trello.setup_members(&mut term, &trello_api_key, &mut config, &members_file) {
+        Ok(())   => (),
+        Err(err) => {print_error(&mut term, &err.context("Error while mapping git authors to board members"));}
     }
 
 
@@ -383,12 +1695,12 @@ fn main() {
         ci_manager.register_ci(Box::new(appveyor::AppVeyor{}));
 
         //Save config
-        status = utils::StatusPrint::from_str(&mut term, "Saving configuration file...");
+        let status = utils::StatusPrint::from_str(&mut term, "Saving configuration file...");
         match config.save() {
             Ok(())   => {status.success(&mut term);},
             Err(err) => {
                 status.error(&mut term);
-                writeln_red!(term, "Error: Failed to save the configuration file: {}, TrelloBST will continue without saving inputted values into the configuration file.", err);
+                print_error(&mut term, &err);
             }
         }
 
@@ -396,8 +1708,7 @@ fn main() {
         let (filename, file_data) = match ci_manager.generate_ci_config(&mut term, &mut config) {
             Ok(data) => data,
             Err(err) => {
-                writeln_red!(term, "Failed to generate the CI config file: {}", err);
-                exit(-1);
+                exit_with_error(&mut term, err.context("Failed to generate the CI config file"));
             }
         };
 
@@ -412,8 +1723,7 @@ fn main() {
                     Ok(_file) => {_file}
                     Err(err) => {
                         status.error(&mut term);
-                        writeln_red!(term, "Failed to create {} due to {}", filename, err);
-                        exit(-1);
+                        exit_with_error(&mut term, TrelloBSTError::config_io(&filename, err).context(format!("Failed to create {}", filename)));
                     }
                 };
 
@@ -0,0 +1,234 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+use ci_env::CIEnvironment;
+use error::TrelloBSTError;
+use push::PushConfig;
+use trello_client::{Member, TrelloClient};
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+pub const DEFAULT_MEMBERS_FILE: &'static str = "./.TrelloBST.members";
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Loads the git author email to Trello member (id or username) mapping, emails are lowercase.
+pub fn load_mapping(members_file: &str) -> Result<BTreeMap<String, String>, TrelloBSTError> {
+
+    let mut file = match File::open(Path::new(members_file)) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::config_io(members_file, err).context("Error: Failed to open the members mapping file"))
+    };
+
+    let mut file_data = String::new();
+    match file.read_to_string(&mut file_data) {
+        Ok(_)    => (),
+        Err(err) => return Err(TrelloBSTError::config_io(members_file, err).context("Error: Failed to read the members mapping file"))
+    }
+
+    parse_mapping(&file_data)
+}
+
+//Parses the members mapping, ex: {"dev@example.com": "@alex"}
+fn parse_mapping(file_data: &str) -> Result<BTreeMap<String, String>, TrelloBSTError> {
+
+    let json_data: Value = match serde_json::from_str(&file_data) {
+        Ok(json_data) => json_data,
+        Err(err)      => return Err(TrelloBSTError::parse("the members mapping file", err))
+    };
+
+    let json_object = try!(json_data.as_object().ok_or(TrelloBSTError::parse("the members mapping file", "The JSON data does not describe a JSON object.")));
+
+    let mut mapping = BTreeMap::new();
+    for (email, member) in json_object {
+        let member = try!(member.as_str().ok_or(TrelloBSTError::parse("the members mapping file", "A member is not a string.")));
+        mapping.insert(email.trim().to_lowercase(), member.to_string());
+    }
+
+    Ok(mapping)
+}
+
+pub fn save_mapping(members_file: &str, mapping: &BTreeMap<String, String>) -> Result<(), TrelloBSTError> {
+
+    let mut json_map: BTreeMap<String, Value> = BTreeMap::new();
+    for (email, member) in mapping {
+        json_map.insert(email.clone(), Value::String(member.clone()));
+    }
+
+    let json_string = match serde_json::to_string_pretty(&Value::Object(json_map)) {
+        Ok(json_string) => json_string,
+        Err(err)        => return Err(TrelloBSTError::parse("the members mapping", err))
+    };
+
+    let mut file = match File::create(Path::new(members_file)) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::config_io(members_file, err).context("Error: Failed to create the members mapping file"))
+    };
+
+    match file.write_all(json_string.as_bytes()) {
+        Ok(())   => Ok(()),
+        Err(err) => Err(TrelloBSTError::config_io(members_file, err).context("Error: Failed to write the members mapping file"))
+    }
+}
+
+//Gets the email of the commit's author from the CI's env vars or else from "git log -1".
+pub fn author_email() -> Result<String, TrelloBSTError> {
+
+    if let Some(ci_env) = CIEnvironment::detect() {
+        if !ci_env.author_email.is_empty() {
+            return Ok(ci_env.author_email);
+        }
+    }
+
+    let output = match Command::new("git").arg("log").arg("-1").arg("--format=%ae").output() {
+        Ok(output) => output,
+        Err(_)     => return Err(TrelloBSTError::Validation("Error: Failed to run \"git log -1\" to get the commit author.".to_string()))
+    };
+
+    git_log_email(&output.stdout, output.status.success())
+}
+
+//Gets the email from the output of "git log -1 --format=%ae".
+fn git_log_email(stdout: &[u8], is_success: bool) -> Result<String, TrelloBSTError> {
+
+    let email = String::from_utf8_lossy(stdout).trim().to_string();
+    if !is_success || email.is_empty() {
+        return Err(TrelloBSTError::Validation("Error: \"git log -1\" did not give the commit author, is this a git repository?".to_string()));
+    }
+
+    Ok(email)
+}
+
+//Gets the members of the board the list belongs to.
+pub fn board_members(api_key: &String, trello_api_token: &String, list_id: &String) -> Result<Vec<Member>, TrelloBSTError> {
+    let client = TrelloClient::new(api_key, trello_api_token);
+    let list   = try!(client.list(list_id));
+    client.board_members(&list.id_board)
+}
+
+//Resolves the commit's author to the id of a board member.
+pub fn resolve_author(api_key: &String, push_data: &PushConfig, list_id: &String) -> Result<String, TrelloBSTError> {
+
+    let mapping = try!(load_mapping(&push_data.members_file));
+    let email   = try!(author_email());
+
+    let member  = try!(mapped_member(&mapping, &email));
+    let members = try!(board_members(api_key, &push_data.trello_api_token, list_id));
+    find_member(members, &member, &email)
+}
+
+//Gets the member (id or username) the email is mapped to, lowercase and without the "@".
+fn mapped_member(mapping: &BTreeMap<String, String>, email: &str) -> Result<String, TrelloBSTError> {
+    match mapping.get(&email.trim().to_lowercase()) {
+        Some(member) => Ok(member.trim_left_matches('@').to_lowercase()),
+        None         => Err(TrelloBSTError::Validation(format!("Error: The commit author \"{}\" is not in the members mapping file.", email)))
+    }
+}
+
+//Gets the id of the board member with the given id or username.
+fn find_member(members: Vec<Member>, member: &str, email: &str) -> Result<String, TrelloBSTError> {
+
+    for board_member in members {
+        if board_member.id.to_lowercase() == member || board_member.username.to_lowercase() == member {
+            return Ok(board_member.id);
+        }
+    }
+
+    Err(TrelloBSTError::Validation(format!("Error: \"{}\", the member mapped to \"{}\", is not a member of the board.", member, email)))
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn member(id: &str, username: &str) -> Member {
+        Member {
+            id:        id.to_string(),
+            username:  username.to_string(),
+            full_name: String::new()
+        }
+    }
+
+    fn members() -> Vec<Member> {
+        vec![member("m1", "dev"), member("m2", "alex")]
+    }
+
+    #[test]
+    fn emails_match_case_insensitively() {
+        let mapping = parse_mapping("{\"Dev@Example.com \": \"@Alex\"}").unwrap();
+        assert_eq!(mapping.get("dev@example.com"), Some(&"@Alex".to_string()));
+        assert_eq!(mapped_member(&mapping, "DEV@example.COM").unwrap(), "alex");
+    }
+
+    #[test]
+    fn the_mapping_picks_the_board_member() {
+        //The author's email name is a board member's username, the mapped member is still the one used
+        let mapping = parse_mapping("{\"dev@example.com\": \"m2\"}").unwrap();
+        let member  = mapped_member(&mapping, "dev@example.com").unwrap();
+        assert_eq!(find_member(members(), &member, "dev@example.com").unwrap(), "m2");
+    }
+
+    #[test]
+    fn unmapped_authors_and_unknown_members_are_errors() {
+        let mapping = parse_mapping("{\"dev@example.com\": \"nobody\"}").unwrap();
+        assert!(mapped_member(&mapping, "other@example.com").is_err());
+        assert!(find_member(members(), "nobody", "dev@example.com").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_mapping_files() {
+        assert!(parse_mapping("not json").is_err());
+        assert!(parse_mapping("[\"dev@example.com\"]").is_err());
+        assert!(parse_mapping("{\"dev@example.com\": 42}").is_err());
+    }
+
+    #[test]
+    fn empty_git_log_email_is_an_error() {
+        assert_eq!(git_log_email(b"dev@example.com\n", true).unwrap(), "dev@example.com");
+        assert!(git_log_email(b"\n", true).is_err());
+        assert!(git_log_email(b"dev@example.com\n", false).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use error::TrelloBSTError;
+use push;
+use trello_client::{Card, TrelloClient};
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+pub struct PruneConfig {
+    pub trello_api_token: String,
+    pub list_ids:         Vec<String>,
+    pub keep:             Option<usize>,
+    pub per:              String,
+    pub older_than:       Option<u64>
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Finds the cards to archive, most recent first in each group.
+//A card is archived if it is not in the "keep" most recent cards of its group and if it is older than "older_than",
+//when only one of the two is set, only that one is checked.
+//NOTE: Cards without the title tags used by the group key were not pushed by TrelloBST and are left alone.
+pub fn find_prunable_cards(api_key: &String, prune_config: &PruneConfig) -> Result<Vec<Card>, TrelloBSTError> {
+
+    if prune_config.keep.is_none() && prune_config.older_than.is_none() {
+        return Err(TrelloBSTError::Validation("Error: At least one of --keep and --older-than must be used.".to_string()));
+    }
+
+    //Validate the group key before doing any API call.
+    for field in prune_config.per.split('+') {
+        try!(push::title_tag_index(field));
+    }
+
+    //Group cards by key
+    let     client                                   = TrelloClient::new(api_key, &prune_config.trello_api_token);
+    let mut groups: BTreeMap<Vec<String>, Vec<Card>> = BTreeMap::new();
+    for list_id in &prune_config.list_ids {
+
+        let cards = try!(client.list_cards(list_id));
+
+        for card in cards {
+            match push::title_key(&card.name, &prune_config.per) {
+                Ok(key) => groups.entry(key).or_insert(Vec::new()).push(card),
+                Err(_)  => ()
+            }
+        }
+    }
+
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_)  => 0
+    };
+
+    //Select the cards to archive
+    let mut prunable_cards = Vec::new();
+    for (_, mut cards) in groups {
+
+        cards.sort_by(|a, b| card_timestamp(&b.id).cmp(&card_timestamp(&a.id)));
+
+        for (index, card) in cards.into_iter().enumerate() {
+
+            let is_excess = match prune_config.keep {
+                Some(keep) => index >= keep,
+                None       => true
+            };
+
+            let is_old = match prune_config.older_than {
+                Some(older_than) => now.saturating_sub(card_timestamp(&card.id)) > older_than,
+                None             => true
+            };
+
+            if is_excess && is_old {
+                prunable_cards.push(card);
+            }
+        }
+    }
+
+    Ok(prunable_cards)
+}
+
+
+//Archives a card, archived cards can still be restored from Trello.
+pub fn archive_card(api_key: &String, prune_config: &PruneConfig, card_id: &String) -> Result<(), TrelloBSTError> {
+
+    try!(TrelloClient::new(api_key, &prune_config.trello_api_token).archive_card(card_id));
+    Ok(())
+}
+
+
+//Gets the creation time of a card, the first 8 hex digits of a Trello id are its creation timestamp.
+pub fn card_timestamp(card_id: &String) -> u64 {
+    if card_id.len() < 8 {
+        return 0;
+    }
+    u64::from_str_radix(&card_id[..8], 16).unwrap_or(0)
+}
+
+
+//Parses an age such as "30d", "12h", "2w", "45m" or "90s" into seconds.
+pub fn parse_age(age: &str) -> Result<u64, TrelloBSTError> {
+
+    let age = age.trim();
+    if age.is_empty() {
+        return Err(TrelloBSTError::Validation("Error: Empty age given.".to_string()));
+    }
+
+    let (amount, multiplier) = match age.chars().last().unwrap() {
+        's' => (&age[..age.len() - 1], 1),
+        'm' => (&age[..age.len() - 1], 60),
+        'h' => (&age[..age.len() - 1], 60 * 60),
+        'd' => (&age[..age.len() - 1], 60 * 60 * 24),
+        'w' => (&age[..age.len() - 1], 60 * 60 * 24 * 7),
+        _   => (age, 60 * 60 * 24)
+    };
+
+    match amount.parse::<u64>().ok().map(|amount| amount.checked_mul(multiplier)) {
+        Some(Some(seconds)) => Ok(seconds),
+        Some(None)          => Err(TrelloBSTError::Validation("Error: The age is too large.".to_string())),
+        None                => Err(TrelloBSTError::Validation("Error: Invalid age, use a number followed by s, m, h, d or w (ex: \"30d\").".to_string()))
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_age_units() {
+        assert_eq!(parse_age("90s").unwrap(), 90);
+        assert_eq!(parse_age("45m").unwrap(), 45 * 60);
+        assert_eq!(parse_age("12h").unwrap(), 12 * 60 * 60);
+        assert_eq!(parse_age("2w").unwrap(),  2 * 7 * 24 * 60 * 60);
+        assert_eq!(parse_age("30").unwrap(),  30 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn parse_age_rejects_invalid_and_overflowing_ages() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("-3d").is_err());
+        assert!(parse_age("99999999999999999w").is_err());
+    }
+}
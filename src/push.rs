@@ -23,24 +23,102 @@
     SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
+use std::collections::BTreeMap;
 use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
 
-extern crate url;
-use self::url::percent_encoding;
+use ci_env::CIEnvironment;
+use config;
+use custom_fields::BuildField;
+use error::TrelloBSTError;
+use spool::SpoolEntry;
+use status::{BuildStatus, ListStage};
+use template::Template;
+use trello_client::{Card, CardData, TrelloClient};
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//Position of the compiler tag, the only tag a generated title can leave out.
+const COMPILER_TAG_INDEX: usize = 3;
+
+//Trello's description length limit, in characters.
+pub const MAX_DESC_LENGTH:         usize        = 16384;
+pub const DESC_OVERFLOW_FILE_NAME: &'static str = "description.txt";
 
-use utils;
 
 ////////////////////////////////////////////////////////////
 //                        Structs                         //
 ////////////////////////////////////////////////////////////
 
+#[derive(Clone)]
 pub struct PushConfig {
-    pub trello_api_token:         String,
-    pub trello_api_list_id:       String,
-    pub trello_api_build_pass_id: String,
-    pub trello_api_build_fail_id: String,
-    pub card_title:               String,
-    pub card_desc:                String,
+    pub trello_api_token:          String,
+    pub trello_api_list_id:        String,
+    pub trello_api_label_ids:      BTreeMap<BuildStatus, String>,
+    pub trello_api_stage_list_ids: BTreeMap<ListStage, String>,
+    pub card_title:                String,
+    pub card_desc:                 String,
+    pub append_status:             bool,
+    pub title_template:            Option<Template>,
+    pub desc_template:             Option<Template>,
+    pub duration:                  String,
+    pub duration_secs:             Option<u64>,
+    pub result_desc:               String,
+    pub spool_dir:                 String,
+    pub max_attempts:              Option<usize>,
+    pub label_branch:              bool,
+    pub list_changes:              bool,
+    pub extra_label_ids:           Vec<String>,
+    pub assign_author:             bool,
+    pub members_file:              String,
+    pub member_ids:                Vec<String>,
+    pub field_ids:                 BTreeMap<BuildField, String>,
+    pub update_key:                String,
+    pub attachments:               Vec<String>,
+    pub junit_reports:             Vec<String>,
+    pub tap_reports:               Vec<String>,
+    pub card_id:                   String,
+    //dateLastActivity of the card when "push start" was done with it, kept in spooled updates.
+    pub last_activity:             String,
+    //Spool entry file of a card that could not be pushed.
+    pub spool_entry:               String,
+    pub targets:                   Vec<PushTarget>,
+    pub target_policy:             TargetPolicy,
+}
+
+//A list the card is pushed to along with the labels used on its board, see PushConfig::target_configs.
+#[derive(Clone)]
+pub struct PushTarget {
+    pub list_id:       String,
+    pub pass_label_id: String,
+    pub fail_label_id: String,
+    //Card pushed to this target by "push start".
+    pub card_id:       String
+}
+
+//What "push start" leaves in the state file for "push finish", written as "name=value" lines.
+pub struct BuildState {
+    pub card_id:         String,
+    //Unix time of "push start", 0 if unknown.
+    pub started_at:      u64,
+    //dateLastActivity of the card once "push start" was done with it.
+    pub last_activity:   String,
+    //Spool entry file and title of the card when "push start" spooled it instead.
+    pub spool_entry:     String,
+    pub spooled_title:   String,
+    //Cards of the targets after the first, empty for those that failed.
+    pub target_card_ids: Vec<String>
+}
+
+//When a push to several targets is a success.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetPolicy {
+    All,
+    Any
 }
 
 
@@ -56,68 +134,284 @@ impl PushConfig {
                 cli_build_pass_id: String,
                 cli_build_fail_id: String,
                 cli_list_id:       String,
-                cli_api_token:     String) -> Result<PushConfig, &'static str> {
+                cli_api_token:     String,
+                cli_update_key:    String,
+                cli_targets:       Vec<PushTarget>,
+                file_config:       &mut config::TrelloBSTConfig) -> Result<PushConfig, TrelloBSTError> {
 
-        let mut tmp_trello_api_token         = String::new();
-        let mut tmp_trello_api_list_id       = String::new();
-        let mut tmp_trello_api_build_pass_id = String::new();
-        let mut tmp_trello_api_build_fail_id = String::new();
+        //NOTE: Every value comes from the cli option, else the env var, else the configuration file (profile).
 
-        //NOTE: No need to check card title and card desc
+        let mut tmp_trello_api_token          = String::new();
+        let mut tmp_trello_api_list_id        = String::new();
+        let mut tmp_trello_api_label_ids      = BTreeMap::new();
+        let mut tmp_trello_api_stage_list_ids = BTreeMap::new();
+        let mut tmp_card_title                = cli_card_title;
+        let mut tmp_card_desc                 = cli_card_desc;
+        let mut tmp_append_status             = false;
 
-        //Get build pass id from env var (if not given as a cli option, fail if not in env var)
-        if cli_build_pass_id.is_empty() {
-            //If empty check env var.
-            match env::var("TRELLO_API_BUILD_PASS_ID") {
-                Ok(val)  => {tmp_trello_api_build_pass_id = val;}
-                Err(_) => {return Err("Error getting the \"TRELLO_API_BUILD_PASS_ID\" environment variable, either undefined or value not in Unicode");}
+        //Fill the card title and desc from the CI's env vars when they're not given
+        if tmp_card_title.is_empty() || tmp_card_desc.is_empty() {
+            match CIEnvironment::detect() {
+                Some(ci_env) => {
+                    if tmp_card_title.is_empty() {
+                        tmp_card_title    = ci_env.title();
+                        tmp_append_status = true;
+                    }
+                    if tmp_card_desc.is_empty() {
+                        tmp_card_desc = ci_env.description();
+                    }
+                }
+                None => ()
             }
-        } else {
-            tmp_trello_api_build_pass_id = cli_build_pass_id;
         }
 
-        //Get build fail id from env var (if not given as a cli option, fail if not in env var)
-        if cli_build_fail_id.is_empty() {
-            //If empty check env var.
-            match env::var("TRELLO_API_BUILD_FAIL_ID") {
-                Ok(val)  => {tmp_trello_api_build_fail_id = val;}
-                Err(_) => {return Err("Error getting the \"TRELLO_API_BUILD_FAIL_ID\" environment variable, either undefined or value not in Unicode");}
-            }
-        } else {
-            tmp_trello_api_build_fail_id = cli_build_fail_id;
+        //Validate the update key early so that we don't fail after the build has been pushed
+        //NOTE: Templated titles are only known once pushed.
+        if !cli_update_key.is_empty() && !tmp_card_title.is_empty() {
+            try!(title_key(&tmp_card_title, &cli_update_key));
         }
 
-        //Get card list id from env var (if not given as a cli option, fail if not in env var)
-        if cli_list_id.is_empty() {
-            //If empty check env var.
-            match env::var("TRELLO_API_LIST_ID") {
-                Ok(val)  => {tmp_trello_api_list_id = val;}
-                Err(_) => {return Err("Error getting the \"TRELLO_API_LIST_ID\" environment variable, either undefined or value not in Unicode");}
+        //Get the label id of each build status, pass and fail ids can be given as cli options.
+        //NOTE: Missing label ids are fine, see PushConfig::label_id.
+        for status in BuildStatus::all() {
+            let cli_label_id = match status {
+                BuildStatus::Passed => cli_build_pass_id.clone(),
+                BuildStatus::Failed => cli_build_fail_id.clone(),
+                _                   => String::new()
+            };
+            let label_id = env_or_config(cli_label_id, &status.env_var(), file_config, &status.config_key());
+            if !label_id.is_empty() {
+                tmp_trello_api_label_ids.insert(status, label_id);
             }
-        } else {
-            tmp_trello_api_list_id = cli_list_id;
         }
 
-        //Get api token from env var (if not given as a cli option, fail if not in env var)
-        if cli_api_token.is_empty() {
-            //If empty check env var.
-            match env::var("TRELLO_API_TOKEN") {
-                Ok(val)  => {tmp_trello_api_token = val;}
-                Err(_) => {return Err("Error getting the \"TRELLO_API_TOKEN\" environment variable, either undefined or value not in Unicode");}
+        //Get the list id of each build stage, those are optional.
+        for stage in ListStage::all() {
+            let list_id = env_or_config(String::new(), &stage.env_var(), file_config, &stage.config_key());
+            if !list_id.is_empty() {
+                tmp_trello_api_stage_list_ids.insert(stage, list_id);
             }
-        } else {
-            tmp_trello_api_token = cli_api_token;
+        }
+
+        //Get card list id, fail if there isn't one and there are no build stage lists or targets
+        tmp_trello_api_list_id = env_or_config(cli_list_id, "TRELLO_API_LIST_ID", file_config, "trello_list_id");
+        if tmp_trello_api_list_id.is_empty() && tmp_trello_api_stage_list_ids.len() != ListStage::all().len() && cli_targets.is_empty() {
+            return Err(TrelloBSTError::Validation("Error: No list id found, use --list-id, the \"TRELLO_API_LIST_ID\" environment variable or a configuration file with a list.".to_string()));
+        }
+
+        //Targets after the first don't use the configured labels, see PushConfig::target_configs
+        if cli_targets.iter().skip(1).any(|target| target.pass_label_id.is_empty() || target.fail_label_id.is_empty()) {
+            return Err(TrelloBSTError::Validation("Error: Every target after the first needs its own pass and fail labels, ex: \"list_id:pass_label_id:fail_label_id\".".to_string()));
+        }
+
+        //Get api token, fail if there isn't one
+        tmp_trello_api_token = env_or_config(cli_api_token, "TRELLO_API_TOKEN", file_config, "trello_api_token");
+        if tmp_trello_api_token.is_empty() {
+            return Err(TrelloBSTError::Validation("Error: No Trello API token found, use --token, the \"TRELLO_API_TOKEN\" environment variable or a configuration file with a token.".to_string()));
         }
 
         Ok(PushConfig {
-            trello_api_token:         tmp_trello_api_token,
-            trello_api_list_id:       tmp_trello_api_list_id,
-            trello_api_build_pass_id: tmp_trello_api_build_pass_id,
-            trello_api_build_fail_id: tmp_trello_api_build_fail_id,
-            card_title:               cli_card_title,
-            card_desc:                cli_card_desc
+            trello_api_token:          tmp_trello_api_token,
+            trello_api_list_id:        tmp_trello_api_list_id,
+            trello_api_label_ids:      tmp_trello_api_label_ids,
+            trello_api_stage_list_ids: tmp_trello_api_stage_list_ids,
+            card_title:                tmp_card_title,
+            card_desc:                 tmp_card_desc,
+            append_status:             tmp_append_status,
+            title_template:            Option::None,
+            desc_template:             Option::None,
+            duration:                  String::new(),
+            duration_secs:             Option::None,
+            result_desc:               String::new(),
+            spool_dir:                 String::new(),
+            max_attempts:              Option::None,
+            label_branch:              false,
+            list_changes:              false,
+            extra_label_ids:           Vec::new(),
+            assign_author:             false,
+            members_file:              String::new(),
+            member_ids:                Vec::new(),
+            field_ids:                 BTreeMap::new(),
+            update_key:                cli_update_key,
+            attachments:               Vec::new(),
+            junit_reports:             Vec::new(),
+            tap_reports:               Vec::new(),
+            card_id:                   String::new(),
+            last_activity:             String::new(),
+            spool_entry:               String::new(),
+            targets:                   cli_targets,
+            target_policy:             TargetPolicy::All
         })
     }
+
+    //Gets the config used for each target, the config itself if there are no targets.
+    //NOTE: Only the first target uses the configured labels and custom fields, the other targets can be on another
+    //      board so they only get their own pass and fail labels.
+    pub fn target_configs(&self) -> Vec<PushConfig> {
+
+        if self.targets.is_empty() {
+            return vec![self.clone()];
+        }
+
+        let mut target_configs = Vec::new();
+        for (index, target) in self.targets.iter().enumerate() {
+            let mut target_config = self.clone();
+
+            target_config.targets                   = Vec::new();
+            target_config.trello_api_list_id        = target.list_id.clone();
+            target_config.trello_api_stage_list_ids = BTreeMap::new();
+
+            if index > 0 {
+                target_config.card_id              = target.card_id.clone();
+                target_config.last_activity        = String::new();
+                target_config.field_ids            = BTreeMap::new();
+                target_config.trello_api_label_ids = BTreeMap::new();
+            }
+
+            if !target.pass_label_id.is_empty() {
+                target_config.trello_api_label_ids.insert(BuildStatus::Passed, target.pass_label_id.clone());
+            }
+            if !target.fail_label_id.is_empty() {
+                target_config.trello_api_label_ids.insert(BuildStatus::Failed, target.fail_label_id.clone());
+            }
+
+            target_configs.push(target_config);
+        }
+
+        target_configs
+    }
+
+    //Gets the label of a build status, errored and cancelled builds without their own label use the failed label.
+    //NOTE: The setup can skip the optional status labels, cards are then pushed without a status label.
+    pub fn label_id(&self, status: BuildStatus) -> Option<String> {
+        match self.trello_api_label_ids.get(&status) {
+            Some(label_id) => Option::Some(label_id.clone()),
+            None           => match status {
+                BuildStatus::Errored | BuildStatus::Cancelled => self.trello_api_label_ids.get(&BuildStatus::Failed).cloned(),
+                _                                             => Option::None
+            }
+        }
+    }
+
+    //Gets the list a card with the given build status goes in, the build stage's list if there is one.
+    pub fn list_id(&self, status: BuildStatus) -> String {
+        match self.trello_api_stage_list_ids.get(&ListStage::from_status(status)) {
+            Some(list_id) => list_id.clone(),
+            None          => self.trello_api_list_id.clone()
+        }
+    }
+
+    //Gets every list a card of this build can be in, the list of the build status first.
+    //ex: a running card is still in the running builds list when the build finishes.
+    pub fn list_ids(&self, status: BuildStatus) -> Vec<String> {
+        let mut list_ids = vec![self.list_id(status)];
+        for list_id in self.trello_api_stage_list_ids.values().chain(Some(&self.trello_api_list_id)) {
+            if !list_id.is_empty() && !list_ids.contains(list_id) {
+                list_ids.push(list_id.clone());
+            }
+        }
+        list_ids
+    }
+
+    //The push as "flush" replays it, timestamp is the unix time it was spooled at.
+    pub fn spool_entry(&self, status: BuildStatus, timestamp: u64) -> SpoolEntry {
+        SpoolEntry {
+            title:           self.card_title.clone(),
+            desc:            self.card_desc.clone(),
+            label_id:        self.label_id(status).unwrap_or(String::new()),
+            extra_label_ids: self.extra_label_ids.clone(),
+            list_id:         self.list_id(status),
+            card_id:         self.card_id.clone(),
+            last_activity:   self.last_activity.clone(),
+            member_ids:      self.member_ids.clone(),
+            timestamp:       timestamp
+        }
+    }
+}
+
+
+impl PushTarget {
+
+    //ex: "list_id", "list_id:pass_label_id" or "list_id:pass_label_id:fail_label_id"
+    pub fn from_str(target: &str) -> Result<PushTarget, TrelloBSTError> {
+
+        let fields: Vec<&str> = target.trim().split(':').map(|field| field.trim()).collect();
+        if fields.len() > 3 || fields[0].is_empty() {
+            return Err(TrelloBSTError::Validation("Error: Invalid target, targets are written as \"list_id[:pass_label_id[:fail_label_id]]\".".to_string()));
+        }
+
+        Ok(PushTarget {
+            list_id:       fields[0].to_string(),
+            pass_label_id: fields.get(1).unwrap_or(&"").to_string(),
+            fail_label_id: fields.get(2).unwrap_or(&"").to_string(),
+            card_id:       String::new()
+        })
+    }
+
+    //Targets separated by commas, as in the TRELLO_API_TARGETS env var and the "trello_targets" config value.
+    pub fn from_str_list(targets: &str) -> Result<Vec<PushTarget>, TrelloBSTError> {
+        let mut push_targets = Vec::new();
+        for target in targets.split(',').filter(|target| !target.trim().is_empty()) {
+            push_targets.push(try!(PushTarget::from_str(target)));
+        }
+        Ok(push_targets)
+    }
+}
+
+impl BuildState {
+
+    //NOTE: State files of older versions only hold the card id.
+    pub fn from_str(state: &str) -> BuildState {
+
+        let mut build_state = BuildState {
+            card_id:         String::new(),
+            started_at:      0,
+            last_activity:   String::new(),
+            spool_entry:     String::new(),
+            spooled_title:   String::new(),
+            target_card_ids: Vec::new()
+        };
+
+        for line in state.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            match line.find('=') {
+                Some(index) => match &line[..index] {
+                    "card_id"         => build_state.card_id         = line[index + 1..].to_string(),
+                    "started_at"      => build_state.started_at      = line[index + 1..].parse::<u64>().unwrap_or(0),
+                    "last_activity"   => build_state.last_activity   = line[index + 1..].to_string(),
+                    "spool_entry"     => build_state.spool_entry     = line[index + 1..].to_string(),
+                    "spooled_title"   => build_state.spooled_title   = line[index + 1..].to_string(),
+                    "target_card_ids" => build_state.target_card_ids = line[index + 1..].split(',').map(|card_id| card_id.to_string()).collect(),
+                    _                 => ()
+                },
+                None => build_state.card_id = line.to_string()
+            }
+        }
+
+        build_state
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("card_id={}\nstarted_at={}\nlast_activity={}\nspool_entry={}\nspooled_title={}\ntarget_card_ids={}\n",
+                self.card_id, self.started_at, self.last_activity, self.spool_entry, self.spooled_title, self.target_card_ids.join(","))
+    }
+}
+
+impl TargetPolicy {
+
+    pub fn from_str(policy: &str) -> Result<TargetPolicy, TrelloBSTError> {
+        match &policy.trim().to_lowercase()[..] {
+            "all" => Ok(TargetPolicy::All),
+            "any" => Ok(TargetPolicy::Any),
+            _     => Err(TrelloBSTError::Validation("Error: Invalid target policy, valid policies are all and any.".to_string()))
+        }
+    }
+
+    pub fn is_success(&self, target_results: &Vec<bool>) -> bool {
+        match *self {
+            TargetPolicy::All => target_results.iter().all(|is_success| *is_success),
+            TargetPolicy::Any => target_results.iter().any(|is_success| *is_success)
+        }
+    }
 }
 
 
@@ -125,30 +419,417 @@ impl PushConfig {
 //                       Functions                        //
 ////////////////////////////////////////////////////////////
 
-pub fn push(api_key: String, is_pass: bool, push_data: PushConfig) -> Result<(), &'static str> {
+//Gets a value from the cli option if given, else from the env var, else from the configuration file.
+pub fn env_or_config(cli_value: String, env_var: &str, file_config: &mut config::TrelloBSTConfig, config_key: &str) -> String {
 
-    //Setup push packet header.
+    if !cli_value.is_empty() {
+        return cli_value;
+    }
 
-    let label: String;
-    if is_pass {
-        label = push_data.trello_api_build_pass_id;
-    } else {
-        label = push_data.trello_api_build_fail_id;
+    match env::var(env_var) {
+        Ok(val) => {
+            if !val.is_empty() {
+                return val;
+            }
+        }
+        Err(_)  => ()
     }
 
-    let card_title: String = percent_encoding::percent_encode(push_data.card_title.as_bytes(), percent_encoding::USERINFO_ENCODE_SET).collect();
-    let card_desc : String = percent_encoding::percent_encode(push_data.card_desc.as_bytes(),  percent_encoding::USERINFO_ENCODE_SET).collect();
+    file_config.get(config_key)
+}
+
+//Pushes the card and returns its id.
+//NOTE: The card found for the update key is kept in push_data, so a spooled push updates it instead of creating one.
+pub fn push(api_key: String, status: BuildStatus, push_data: &mut PushConfig) -> Result<String, TrelloBSTError> {
+
+    let list_id = push_data.list_id(status);
+
+    try!(resolve_card(&TrelloClient::new(&api_key, &push_data.trello_api_token), status, push_data));
+
+    //Branch label and such go alongside the build status label
+    let mut label_ids: Vec<String> = push_data.label_id(status).into_iter().collect();
+    label_ids.extend(push_data.extra_label_ids.iter().cloned());
+
+    send_card(&api_key, &push_data.trello_api_token, &push_data.card_id, &list_id, &push_data.card_title, &push_data.card_desc, &label_ids, &push_data.member_ids)
+}
 
-    let api_call = format!("https://api.trello.com/1/cards?key={}&token={}&idList={}&name={}&desc={}&idLabels={}&due=null&pos=top",
-                           api_key,
-                           push_data.trello_api_token,
-                           push_data.trello_api_list_id,
-                           card_title,
-                           card_desc,
-                           label);
+
+//Creates a card, or updates it when its id is given, and returns its id.
+//The card data is sent as a JSON body, descriptions over Trello's limit are truncated and the rest is attached as a text file.
+//NOTE: Updated cards lose the text file of their previous description.
+pub fn send_card(api_key:          &str,
+                 trello_api_token: &str,
+                 card_id:          &str,
+                 list_id:          &str,
+                 card_title:       &str,
+                 card_desc:        &str,
+                 label_ids:        &Vec<String>,
+                 member_ids:       &Vec<String>) -> Result<String, TrelloBSTError> {
+
+    let client                     = TrelloClient::new(api_key, trello_api_token);
+    let (card_desc, desc_overflow) = split_desc(card_desc);
+
+    let card_data = CardData {
+        list_id:    list_id.to_string(),
+        name:       card_title.to_string(),
+        desc:       card_desc,
+        label_ids:  label_ids.clone(),
+        member_ids: member_ids.clone()
+    };
 
     //Send off the packet
-    try!(utils::rest_api_call_post(&api_call));
+    let card = if card_id.is_empty() {
+        try!(client.create_card(&card_data))
+    } else {
+        let card = try!(client.update_card(card_id, &card_data));
+
+        //The rest of the previous description no longer goes with the card
+        for attachment in try!(client.card_attachments(&card.id)) {
+            if attachment.name == DESC_OVERFLOW_FILE_NAME {
+                try!(client.delete_attachment(&card.id, &attachment.id));
+            }
+        }
+        card
+    };
+
+    if !desc_overflow.is_empty() {
+        try!(client.attach_file(&card.id, DESC_OVERFLOW_FILE_NAME, desc_overflow.as_bytes()));
+    }
+
+    Ok(card.id)
+}
+
+
+//Gets when the card last changed, ex: "2016-10-18T05:36:28.123Z".
+pub fn last_activity(api_key: &str, trello_api_token: &str, card_id: &str) -> Result<String, TrelloBSTError> {
+    Ok(try!(TrelloClient::new(api_key, trello_api_token).card(card_id)).date_last_activity)
+}
+
+
+//Splits a description at Trello's length limit, returns the description to send and what didn't fit.
+pub fn split_desc(card_desc: &str) -> (String, String) {
+
+    if card_desc.chars().count() <= MAX_DESC_LENGTH {
+        return (card_desc.to_string(), String::new());
+    }
+
+    let note      = format!("\n\n[Truncated, the rest of the description is in the attached {}]", DESC_OVERFLOW_FILE_NAME);
+    let kept_len  = MAX_DESC_LENGTH - note.chars().count();
+    let kept      = card_desc.chars().take(kept_len).collect::<String>();
+    let overflow  = card_desc.chars().skip(kept_len).collect::<String>();
 
+    (kept + &note, overflow)
+}
+
+
+//Attaches a local file or a url to a card.
+pub fn attach(api_key: &String, push_data: &PushConfig, card_id: &String, attachment: &String) -> Result<(), TrelloBSTError> {
+
+    let client = TrelloClient::new(api_key, &push_data.trello_api_token);
+
+    //Urls are registered as link attachments.
+    if attachment.starts_with("http://") || attachment.starts_with("https://") {
+        try!(client.attach_url(card_id, attachment));
+        return Ok(());
+    }
+
+    //Local files are uploaded.
+    let path = Path::new(attachment);
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::file_io(attachment, err).context("Error: Failed to open the file to attach"))
+    };
+
+    let mut file_data: Vec<u8> = Vec::new();
+    match file.read_to_end(&mut file_data) {
+        Ok(_)    => (),
+        Err(err) => return Err(TrelloBSTError::file_io(attachment, err).context("Error: Failed to read the file to attach"))
+    }
+
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().to_string(),
+        None            => return Err(TrelloBSTError::Validation("Error: The attachment path does not point to a file.".to_string()))
+    };
+
+    try!(client.attach_file(card_id, &file_name, &file_data));
     Ok(())
 }
+
+
+//Looks for the card to update if we're in update mode, cards with a known id are updated and moved to the list.
+//The card's last activity is kept along with its id so a spooled update isn't replayed over a newer one.
+pub fn resolve_card(client: &TrelloClient, status: BuildStatus, push_data: &mut PushConfig) -> Result<(), TrelloBSTError> {
+
+    if !push_data.card_id.is_empty() || push_data.update_key.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(card) = try!(find_matching_card(client, push_data, status)) {
+        push_data.card_id = card.id;
+        if push_data.last_activity.is_empty() {
+            push_data.last_activity = card.date_last_activity;
+        }
+    }
+    Ok(())
+}
+
+//Gets the most recently updated card whose title matches the update key.
+//Every list the card can be in is searched, see PushConfig::list_ids.
+fn find_matching_card(client: &TrelloClient, push_data: &PushConfig, status: BuildStatus) -> Result<Option<Card>, TrelloBSTError> {
+
+    let     key                       = try!(title_key(&push_data.card_title, &push_data.update_key));
+    let mut latest_card: Option<Card> = Option::None;
+
+    for list_id in push_data.list_ids(status) {
+        for card in try!(client.list_cards(&list_id)) {
+            if title_key(&card.name, &push_data.update_key).ok().as_ref() != Some(&key) {
+                continue;
+            }
+            let is_latest = match latest_card {
+                Some(ref latest_card) => card.date_last_activity > latest_card.date_last_activity,
+                None                  => true
+            };
+            if is_latest {
+                latest_card = Option::Some(card);
+            }
+        }
+    }
+
+    Ok(latest_card)
+}
+
+
+//Saves what "push start" leaves for "push finish".
+pub fn save_build_state(state_file: &str, build_state: &BuildState) -> Result<(), TrelloBSTError> {
+
+    let mut file = match File::create(Path::new(state_file)) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::config_io(state_file, err).context("Error: Failed to create the state file"))
+    };
+
+    match file.write_all(build_state.to_string().as_bytes()) {
+        Ok(())   => Ok(()),
+        Err(err) => Err(TrelloBSTError::config_io(state_file, err).context("Error: Failed to write the build state to the state file"))
+    }
+}
+
+
+//Loads the build state saved by "push start".
+pub fn load_build_state(state_file: &str) -> Result<BuildState, TrelloBSTError> {
+
+    let mut file = match File::open(Path::new(state_file)) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::config_io(state_file, err).context("Error: Failed to open the state file, was \"push start\" used?"))
+    };
+
+    let mut state = String::new();
+    match file.read_to_string(&mut state) {
+        Ok(_)    => (),
+        Err(err) => return Err(TrelloBSTError::config_io(state_file, err).context("Error: Failed to read the state file"))
+    }
+
+    let build_state = BuildState::from_str(&state);
+    if build_state.card_id.is_empty() && build_state.spool_entry.is_empty() && build_state.target_card_ids.iter().all(|card_id| card_id.is_empty()) {
+        return Err(TrelloBSTError::Validation("Error: The state file does not contain a card id.".to_string()));
+    }
+
+    Ok(build_state)
+}
+
+
+//Extracts the leading "[...]" tags of a card title.
+//ex: "[master][Travis-CI][linux][gcc]: #12 PASSED" gives ["master", "Travis-CI", "linux", "gcc"]
+//NOTE: Branch names can contain "]" but not "[", ":" or spaces, so the tags are split at "][" and end at a "]"
+//      followed by ":", a space or the end of the title. Other titles are split at every "]".
+pub fn parse_title_tags(title: &str) -> Vec<String> {
+
+    let title = title.trim_left();
+    if !title.starts_with('[') {
+        return Vec::new();
+    }
+
+    let tags_end = title.char_indices()
+                        .find(|&(index, c)| c == ']' && title[index + 1..].chars().next().map(|next| next == ':' || next.is_whitespace()).unwrap_or(true))
+                        .map(|(index, _)| index);
+
+    if let Some(tags_end) = tags_end {
+        return title[1..tags_end].split("][").map(|tag| tag.to_string()).collect();
+    }
+
+    let mut tags      = Vec::new();
+    let mut remainder = title;
+
+    while remainder.starts_with('[') {
+        match remainder.find(']') {
+            Some(end) => {
+                tags.push(remainder[1..end].to_string());
+                remainder = &remainder[end + 1..];
+            }
+            None => break
+        }
+    }
+
+    tags
+}
+
+
+//Gets the position of a named title tag, tags can also be given by position.
+pub fn title_tag_index(field: &str) -> Result<usize, TrelloBSTError> {
+    match field.trim() {
+        "branch"   => Ok(0),
+        "ci"       => Ok(1),
+        "os"       => Ok(2),
+        "compiler" => Ok(3),
+        other      => {
+            match other.parse::<usize>() {
+                Ok(index) => Ok(index),
+                Err(_)    => Err(TrelloBSTError::Validation("Error: Invalid key field, valid fields are \"branch\", \"ci\", \"os\", \"compiler\" or a tag position.".to_string()))
+            }
+        }
+    }
+}
+
+
+//Builds the key of a card title from a key description such as "branch+ci+os".
+pub fn title_key(title: &str, key_desc: &str) -> Result<Vec<String>, TrelloBSTError> {
+
+    let     tags = parse_title_tags(title);
+    let mut key  = Vec::new();
+
+    for field in key_desc.split('+') {
+        let index = try!(title_tag_index(field));
+        match tags.get(index) {
+            Some(tag) => key.push(tag.clone()),
+            None      => {
+                //Generated titles leave the compiler tag out when there is no compiler.
+                if index != COMPILER_TAG_INDEX || tags.len() != COMPILER_TAG_INDEX {
+                    return Err(TrelloBSTError::Validation("Error: The card title does not contain every tag used by the key.".to_string()));
+                }
+                key.push(String::new());
+            }
+        }
+    }
+
+    Ok(key)
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use config;
+    use status::BuildStatus;
+    use utils::mock_server::{response, MockServer};
+
+    use super::*;
+
+    #[test]
+    fn parse_title_tags_generated_title() {
+        assert_eq!(parse_title_tags("[master][Travis-CI][linux][gcc]: #12 PASSED"), vec!["master", "Travis-CI", "linux", "gcc"]);
+        assert_eq!(parse_title_tags("  [master][Travis-CI][linux]: #12"),            vec!["master", "Travis-CI", "linux"]);
+    }
+
+    #[test]
+    fn parse_title_tags_branch_with_brackets() {
+        assert_eq!(parse_title_tags("[fix]][Travis-CI][linux]: #3"),    vec!["fix]", "Travis-CI", "linux"]);
+        assert_eq!(parse_title_tags("[a]b][AppVeyor][windows]: #4"),    vec!["a]b", "AppVeyor", "windows"]);
+        assert_eq!(parse_title_tags("[master][CI]: see [docs] and ]["), vec!["master", "CI"]);
+    }
+
+    #[test]
+    fn parse_title_tags_other_titles() {
+        assert!(parse_title_tags("Build #12 passed").is_empty());
+        assert_eq!(parse_title_tags("[master]"),            vec!["master"]);
+        assert_eq!(parse_title_tags("[master][linux]#12"),  vec!["master", "linux"]);
+        assert_eq!(parse_title_tags("[unterminated"),       Vec::<String>::new());
+    }
+
+    #[test]
+    fn title_key_by_name_and_position() {
+        let title = "[master][Travis-CI][linux][gcc]: #12 PASSED";
+        assert_eq!(title_key(title, "branch+os").unwrap(), vec!["master", "linux"]);
+        assert_eq!(title_key(title, "1+compiler").unwrap(), vec!["Travis-CI", "gcc"]);
+        assert!(title_key(title, "commit").is_err());
+    }
+
+    #[test]
+    fn title_key_without_compiler_tag() {
+        assert_eq!(title_key("[master][Travis-CI][linux]: #12", "branch+compiler").unwrap(), vec!["master", ""]);
+        assert!(title_key("[master][Travis-CI]: #12", "compiler").is_err());
+        assert!(title_key("[master][Travis-CI][linux]: #12", "4").is_err());
+    }
+
+    #[test]
+    fn build_state_round_trip() {
+        let build_state = BuildState::from_str(&BuildState {
+            card_id:         String::new(),
+            started_at:      1476768988,
+            last_activity:   String::new(),
+            spool_entry:     "/tmp/spool/00000000001476768988.json".to_string(),
+            spooled_title:   "[master][Travis-CI]: #12 RUNNING".to_string(),
+            target_card_ids: vec!["5801c7d4".to_string(), String::new()]
+        }.to_string());
+
+        assert_eq!(build_state.started_at, 1476768988);
+        assert_eq!(build_state.target_card_ids, vec!["5801c7d4", ""]);
+        assert_eq!(build_state.spool_entry, "/tmp/spool/00000000001476768988.json");
+        assert_eq!(build_state.spooled_title, "[master][Travis-CI]: #12 RUNNING");
+    }
+
+    #[test]
+    fn build_state_legacy_format() {
+        let build_state = BuildState::from_str("5801c7d4e5f6a7b8c9d0e1f2\n");
+        assert_eq!(build_state.card_id, "5801c7d4e5f6a7b8c9d0e1f2");
+        assert_eq!(build_state.started_at, 0);
+        assert!(build_state.spool_entry.is_empty());
+    }
+
+    #[test]
+    fn secondary_targets_only_use_their_own_labels() {
+        let mut push_config = PushConfig::fill("Build #1".to_string(), "desc".to_string(), "pass".to_string(), "fail".to_string(), "list".to_string(),
+                                               "token".to_string(), String::new(), Vec::new(), &mut config::TrelloBSTConfig::new()).unwrap();
+        push_config.card_id = "first_card".to_string();
+        push_config.trello_api_label_ids.insert(BuildStatus::Running, "running".to_string());
+        push_config.targets = vec![PushTarget::from_str("list").unwrap(), PushTarget::from_str("other_list:other_pass:other_fail").unwrap()];
+        push_config.targets[1].card_id = "second_card".to_string();
+
+        let target_configs = push_config.target_configs();
+        assert_eq!(target_configs[0].card_id, "first_card");
+        assert_eq!(target_configs[0].label_id(BuildStatus::Running), Some("running".to_string()));
+        assert_eq!(target_configs[1].card_id, "second_card");
+        assert_eq!(target_configs[1].label_id(BuildStatus::Running), None);
+        assert_eq!(target_configs[1].label_id(BuildStatus::Errored), Some("other_fail".to_string()));
+    }
+
+    #[test]
+    fn secondary_targets_need_labels() {
+        let targets = vec![PushTarget::from_str("list").unwrap(), PushTarget::from_str("other_list:other_pass").unwrap()];
+        assert!(PushConfig::fill("Build #1".to_string(), "desc".to_string(), "pass".to_string(), "fail".to_string(), String::new(),
+                                 "token".to_string(), String::new(), targets, &mut config::TrelloBSTConfig::new()).is_err());
+    }
+
+    #[test]
+    fn spooled_updates_keep_the_matched_card() {
+        let server = MockServer::start(vec![response("200 OK", &[], "[{\"id\": \"c1\", \"name\": \"[master][Travis-CI][linux]: #11 PASSED\", \"dateLastActivity\": \"2016-10-18T05:36:28.123Z\"}, \
+                                                                      {\"id\": \"c2\", \"name\": \"[dev][Travis-CI][linux]: #10 PASSED\", \"dateLastActivity\": \"2016-10-19T05:36:28.123Z\"}]")]);
+        let client = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+
+        let mut push_config = PushConfig::fill("[master][Travis-CI][linux]: #12 FAILED".to_string(), "desc".to_string(), "pass".to_string(), "fail".to_string(), "list".to_string(),
+                                               "token".to_string(), "branch+os".to_string(), Vec::new(), &mut config::TrelloBSTConfig::new()).unwrap();
+        resolve_card(&client, BuildStatus::Failed, &mut push_config).unwrap();
+
+        let entry = push_config.spool_entry(BuildStatus::Failed, 1476768988);
+        assert_eq!(entry.card_id, "c1");
+        assert_eq!(entry.last_activity, "2016-10-18T05:36:28.123Z");
+        assert_eq!(entry.list_id, "list");
+    }
+
+    #[test]
+    fn title_key_missing_tags() {
+        assert!(title_key("Build #12", "branch").is_err());
+    }
+}
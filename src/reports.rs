@@ -0,0 +1,377 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+extern crate xml;
+use self::xml::reader::{EventReader, XmlEvent};
+
+use error::TrelloBSTError;
+use push;
+use trello_client::TrelloClient;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//Skipped tests are neither passed nor failed, they are left out of the counts.
+#[derive(Clone)]
+pub struct TestCase {
+    pub suite:   String,
+    pub name:    String,
+    pub passed:  bool,
+    pub skipped: bool
+}
+
+pub struct TestReport {
+    pub cases: Vec<TestCase>
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl TestReport {
+
+    pub fn new() -> TestReport {
+        TestReport {
+            cases: Vec::new()
+        }
+    }
+
+    //ex: "41/42 passed" or "41/42 passed, 3 skipped"
+    pub fn summary(&self) -> String {
+        let cases: Vec<&TestCase> = self.cases.iter().collect();
+        summary(&cases)
+    }
+
+    //Parses a JUnit XML report, test cases are grouped by their <testsuite>.
+    pub fn parse_junit(&mut self, path: &str) -> Result<(), TrelloBSTError> {
+
+        let file = match File::open(Path::new(path)) {
+            Ok(file) => file,
+            Err(err) => return Err(TrelloBSTError::file_io(path, err))
+        };
+
+        self.read_junit(BufReader::new(file), path)
+    }
+
+    //NOTE: Test suites can be nested, the cases of a parent suite after a nested one go back to the parent.
+    pub fn read_junit<R: Read>(&mut self, reader: R, path: &str) -> Result<(), TrelloBSTError> {
+
+        let mut suite_names: Vec<String> = Vec::new();
+        let mut current_case             = Option::None;
+
+        for event in EventReader::new(reader) {
+            match event {
+                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    match &name.local_name[..] {
+                        "testsuite" => {
+                            let mut suite_name = String::new();
+                            for attribute in &attributes {
+                                if attribute.name.local_name == "name" {
+                                    suite_name = attribute.value.clone();
+                                }
+                            }
+                            suite_names.push(suite_name);
+                        }
+                        "testcase"  => {
+                            let mut case = TestCase {
+                                suite:   suite_names.last().cloned().unwrap_or(String::new()),
+                                name:    String::new(),
+                                passed:  true,
+                                skipped: false
+                            };
+                            for attribute in &attributes {
+                                if attribute.name.local_name == "name" {
+                                    case.name = attribute.value.clone();
+                                } else if attribute.name.local_name == "classname" && case.suite.is_empty() {
+                                    case.suite = attribute.value.clone();
+                                }
+                            }
+                            current_case = Option::Some(case);
+                        }
+                        "failure" | "error" => {
+                            if let Some(ref mut case) = current_case {
+                                case.passed = false;
+                            }
+                        }
+                        "skipped" => {
+                            if let Some(ref mut case) = current_case {
+                                case.skipped = true;
+                            }
+                        }
+                        _ => ()
+                    }
+                }
+                Ok(XmlEvent::EndElement {name}) => {
+                    match &name.local_name[..] {
+                        "testcase"  => {
+                            if let Some(case) = current_case.take() {
+                                self.cases.push(case);
+                            }
+                        }
+                        "testsuite" => {
+                            suite_names.pop();
+                        }
+                        _ => ()
+                    }
+                }
+                Ok(_)    => (),
+                Err(err) => return Err(TrelloBSTError::parse(&format!("\"{}\"", path), err))
+            }
+        }
+
+        Ok(())
+    }
+
+    //Parses a TAP report, test cases are grouped by the report's file name.
+    pub fn parse_tap(&mut self, path: &str) -> Result<(), TrelloBSTError> {
+
+        let file = match File::open(Path::new(path)) {
+            Ok(file) => file,
+            Err(err) => return Err(TrelloBSTError::file_io(path, err))
+        };
+
+        let suite_name = match Path::new(path).file_stem() {
+            Some(file_stem) => file_stem.to_string_lossy().to_string(),
+            None            => path.to_string()
+        };
+
+        self.read_tap(BufReader::new(file), &suite_name, path)
+    }
+
+    pub fn read_tap<R: BufRead>(&mut self, reader: R, suite_name: &str, path: &str) -> Result<(), TrelloBSTError> {
+
+        for line in reader.lines() {
+
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Err(TrelloBSTError::file_io(path, err))
+            };
+
+            //Test lines look like "ok 1 - description # directive" or "not ok 2 - description"
+            let line = line.trim();
+            let (mut passed, remainder) = if line.starts_with("ok ") {
+                (true, &line[3..])
+            } else if line.starts_with("not ok ") {
+                (false, &line[7..])
+            } else {
+                continue;
+            };
+
+            //Split the directive out of the description
+            let (description, directive) = match remainder.find('#') {
+                Some(index) => (&remainder[..index], remainder[index + 1..].trim().to_uppercase()),
+                None        => (remainder, String::new())
+            };
+
+            //NOTE: Failing TODO tests are expected to fail and do not count as failures.
+            if directive.starts_with("TODO") {
+                passed = true;
+            }
+            let skipped = directive.starts_with("SKIP");
+
+            //Strip the test number and the optional dash
+            let description = description.trim().trim_left_matches(|c: char| c.is_digit(10)).trim().trim_left_matches('-').trim();
+
+            self.cases.push(TestCase {
+                suite:   suite_name.to_string(),
+                name:    description.to_string(),
+                passed:  passed,
+                skipped: skipped
+            });
+        }
+
+        Ok(())
+    }
+
+    //Gets the test suite names in the order they first appear.
+    pub fn suites(&self) -> Vec<String> {
+        let mut suites: Vec<String> = Vec::new();
+        for case in &self.cases {
+            if !suites.contains(&case.suite) {
+                suites.push(case.suite.clone());
+            }
+        }
+        suites
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Above this amount of test cases, a checklist is created for each test suite instead of a single "Tests" checklist.
+const MAX_SINGLE_CHECKLIST_ITEMS: usize = 100;
+
+//Most failed tests listed on the card, each check item is an API call.
+const MAX_FAILED_ITEMS: usize = 100;
+
+//ex: "41/42 passed" or "41/42 passed, 3 skipped"
+pub fn summary(cases: &Vec<&TestCase>) -> String {
+
+    let skipped = cases.iter().filter(|case| case.skipped).count();
+    let passed  = cases.iter().filter(|case| case.passed && !case.skipped).count();
+
+    let mut summary = format!("{}/{} passed", passed, cases.len() - skipped);
+    if skipped > 0 {
+        summary.push_str(&format!(", {} skipped", skipped));
+    }
+    summary
+}
+
+//Small reports get every test in a "Tests" checklist.
+//Large ones get a checklist per test suite with its summary and failed tests only, up to MAX_FAILED_ITEMS in total.
+pub fn push_checklists(api_key: &String, push_data: &push::PushConfig, card_id: &String, report: &TestReport) -> Result<(), TrelloBSTError> {
+
+    let client = TrelloClient::new(api_key, &push_data.trello_api_token);
+
+    if report.cases.len() <= MAX_SINGLE_CHECKLIST_ITEMS {
+        let checklist = try!(client.create_checklist(card_id, "Tests"));
+        for case in &report.cases {
+            try!(client.add_check_item(&checklist.id, &item_name(case), case.passed && !case.skipped));
+        }
+        return Ok(());
+    }
+
+    let mut listed_failures = 0;
+    let     failures        = report.cases.iter().filter(|case| !case.passed && !case.skipped).count();
+
+    for suite in report.suites() {
+        let cases: Vec<&TestCase> = report.cases.iter().filter(|case| case.suite == suite).collect();
+        let checklist             = try!(client.create_checklist(card_id, &format!("Tests: {} ({})", suite, summary(&cases))));
+
+        for case in cases.iter().filter(|case| !case.passed && !case.skipped) {
+            if listed_failures == MAX_FAILED_ITEMS {
+                try!(client.add_check_item(&checklist.id, &format!("{} more failed tests are not listed.", failures - listed_failures), false));
+                return Ok(());
+            }
+            try!(client.add_check_item(&checklist.id, &item_name(case), false));
+            listed_failures += 1;
+        }
+    }
+
+    Ok(())
+}
+
+fn item_name(case: &TestCase) -> String {
+    if case.skipped {format!("{} (skipped)", case.name)} else {case.name.clone()}
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn junit(xml: &str) -> TestReport {
+        let mut report = TestReport::new();
+        report.read_junit(xml.as_bytes(), "report.xml").unwrap();
+        report
+    }
+
+    fn tap(text: &str) -> TestReport {
+        let mut report = TestReport::new();
+        report.read_tap(text.as_bytes(), "report", "report.tap").unwrap();
+        report
+    }
+
+    #[test]
+    fn junit_counts_failures_and_errors() {
+        let report = junit(r#"<testsuite name="unit">
+                                  <testcase name="a"/>
+                                  <testcase name="b"><failure message="boom"/></testcase>
+                                  <testcase name="c"><error/></testcase>
+                              </testsuite>"#);
+        assert_eq!(report.cases.len(), 3);
+        assert_eq!(report.summary(), "1/3 passed");
+    }
+
+    #[test]
+    fn junit_skipped_is_not_counted() {
+        let report = junit(r#"<testsuite name="unit">
+                                  <testcase name="a"/>
+                                  <testcase name="b"><skipped/></testcase>
+                              </testsuite>"#);
+        assert!(report.cases[1].skipped);
+        assert_eq!(report.summary(), "1/1 passed, 1 skipped");
+    }
+
+    #[test]
+    fn junit_nested_suites_restore_the_parent() {
+        let report = junit(r#"<testsuites>
+                                  <testsuite name="outer">
+                                      <testsuite name="inner"><testcase name="a"/></testsuite>
+                                      <testcase name="b"/>
+                                  </testsuite>
+                                  <testcase name="c" classname="lonely"/>
+                              </testsuites>"#);
+        let suites: Vec<&str> = report.cases.iter().map(|case| &case.suite[..]).collect();
+        assert_eq!(suites, vec!["inner", "outer", "lonely"]);
+    }
+
+    #[test]
+    fn junit_invalid_xml_is_a_parse_error() {
+        let mut report = TestReport::new();
+        match report.read_junit("<testsuite><testcase".as_bytes(), "report.xml") {
+            Err(TrelloBSTError::Parse {..}) => (),
+            _                               => panic!("expected a parse error")
+        }
+    }
+
+    #[test]
+    fn tap_results_and_directives() {
+        let report = tap("1..4\n\
+                          ok 1 - first\n\
+                          not ok 2 - second\n\
+                          not ok 3 - third # TODO not done\n\
+                          ok 4 fourth # SKIP no network\n\
+                          # a comment\n");
+        let names: Vec<&str> = report.cases.iter().map(|case| &case.name[..]).collect();
+        assert_eq!(names, vec!["first", "second", "third", "fourth"]);
+        assert!(!report.cases[1].passed);
+        assert!(report.cases[2].passed);
+        assert!(report.cases[3].skipped);
+        assert_eq!(report.cases[0].suite, "report");
+        assert_eq!(report.summary(), "2/3 passed, 1 skipped");
+    }
+
+    #[test]
+    fn tap_and_junit_skips_agree() {
+        let junit_report = junit(r#"<testsuite name="s"><testcase name="a"><skipped/></testcase></testsuite>"#);
+        let tap_report   = tap("ok 1 - a # skip\n");
+        assert_eq!(junit_report.summary(), tap_report.summary());
+    }
+}
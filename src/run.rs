@@ -0,0 +1,217 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use error::TrelloBSTError;
+use push;
+use status::BuildStatus;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+pub struct RunResult {
+    pub exit_code: i32,
+    pub duration:  Duration,
+    pub log_tail:  Vec<String>
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl RunResult {
+
+    pub fn status(&self) -> BuildStatus {
+        if self.exit_code == 0 {
+            BuildStatus::Passed
+        } else {
+            BuildStatus::Failed
+        }
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Runs the build command, streaming its output while keeping the last log_lines lines.
+pub fn run(command: &Vec<String>, log_lines: usize) -> Result<RunResult, TrelloBSTError> {
+
+    if command.is_empty() {
+        return Err(TrelloBSTError::Validation("Error: No build command given.".to_string()));
+    }
+
+    let start_time = Instant::now();
+    let mut child  = match Command::new(&command[0])
+                                   .args(&command[1..])
+                                   .stdin(Stdio::inherit())
+                                   .stdout(Stdio::piped())
+                                   .stderr(Stdio::piped())
+                                   .spawn() {
+        Ok(child) => child,
+        Err(err)  => return Err(TrelloBSTError::config_io(&command[0], format!("Failed to start the build command: {}", err)))
+    };
+
+    //Stream stdout and stderr, both end up in the same log tail.
+    let log_tail      = Arc::new(Mutex::new(VecDeque::with_capacity(log_lines + 1)));
+    let stdout_thread = stream_output(child.stdout.take().unwrap(), false, log_tail.clone(), log_lines);
+    let stderr_thread = stream_output(child.stderr.take().unwrap(), true,  log_tail.clone(), log_lines);
+
+    let exit_status = match child.wait() {
+        Ok(exit_status) => exit_status,
+        Err(err)        => return Err(TrelloBSTError::config_io(&command[0], format!("Failed to wait for the build command: {}", err)))
+    };
+
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+
+    let duration = start_time.elapsed();
+
+    let exit_code = exit_code(&exit_status);
+    let log_tail: Vec<String> = match log_tail.lock() {
+        Ok(log_tail) => log_tail.iter().cloned().collect(),
+        Err(_)       => Vec::new()
+    };
+
+    Ok(RunResult {
+        exit_code: exit_code,
+        duration:  duration,
+        log_tail:  log_tail
+    })
+}
+
+
+//Adds the build result to the card's description.
+//NOTE: The status is only appended to generated titles, see PushConfig::append_status.
+pub fn apply_result(push_config: &mut push::PushConfig, result: &RunResult) {
+
+    push_config.duration      = format_duration(&result.duration);
+    push_config.duration_secs = Option::Some(result.duration.as_secs());
+    push_config.result_desc   = format!("[Duration]\\: {}\n[Exit Code]\\: {}", push_config.duration, result.exit_code);
+
+    if !result.log_tail.is_empty() {
+        push_config.result_desc.push_str("\n[Log]\\:\n```\n");
+        for line in &result.log_tail {
+            push_config.result_desc.push_str(line);
+            push_config.result_desc.push_str("\n");
+        }
+        push_config.result_desc.push_str("```");
+    }
+}
+
+
+//Sets the build duration from the build's start time (unix time) when it isn't known yet, ex: for "push finish".
+pub fn apply_start_time(push_config: &mut push::PushConfig, started_at: u64) {
+
+    if push_config.duration_secs.is_some() || started_at == 0 {
+        return;
+    }
+
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_)  => return
+    };
+
+    let duration = Duration::from_secs(now.saturating_sub(started_at));
+    push_config.duration      = format_duration(&duration);
+    push_config.duration_secs = Option::Some(duration.as_secs());
+}
+
+
+//Formats a duration as "1h 2m 3s".
+pub fn format_duration(duration: &Duration) -> String {
+
+    let total_secs = duration.as_secs();
+    let hours      = total_secs / 3600;
+    let minutes    = (total_secs % 3600) / 60;
+    let seconds    = total_secs % 60;
+
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+
+//Exit code of the build, a build killed by a signal exits with 128 + the signal number like in a shell.
+#[cfg(unix)]
+fn exit_code(exit_status: &ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    exit_status.code().or(exit_status.signal().map(|signal| 128 + signal)).unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(exit_status: &ExitStatus) -> i32 {
+    exit_status.code().unwrap_or(1)
+}
+
+
+fn stream_output<R: Read + Send + 'static>(output: R, is_stderr: bool, log_tail: Arc<Mutex<VecDeque<String>>>, log_lines: usize) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+
+        let mut reader = BufReader::new(output);
+        let mut line   = Vec::new();
+
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0)  => break,
+                Ok(_)  => (),
+                Err(_) => break
+            }
+
+            //Echo the output as is.
+            if is_stderr {
+                let _ = io::stderr().write_all(&line);
+            } else {
+                let _ = io::stdout().write_all(&line);
+                let _ = io::stdout().flush();
+            }
+
+            //Keep the tail.
+            if log_lines > 0 {
+                if let Ok(mut log_tail) = log_tail.lock() {
+                    log_tail.push_back(String::from_utf8_lossy(&line).trim_right().to_string());
+                    while log_tail.len() > log_lines {
+                        log_tail.pop_front();
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,534 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+extern crate term;
+
+use config;
+use error::TrelloBSTError;
+use status::{BuildStatus, ListStage};
+use trello;
+use trello_client::TrelloClient;
+use utils;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//A label given as "name:color" on the command line, the colour is only needed to create the label.
+pub struct LabelSpec {
+    pub name:  String,
+    pub color: Option<String>
+}
+
+//Options of "TrelloBST setup", everything the interactive setup would ask for.
+pub struct SetupOptions {
+    pub trello_api_token: String,
+    pub board:            String,
+    pub create_board:     bool,
+    pub list:             String,
+    pub pass_label:       LabelSpec,
+    pub fail_label:       LabelSpec,
+    pub assume_yes:       bool
+}
+
+//Lists and labels created on a new board by "setup --template", with the config keys their ids are written to.
+pub struct BoardTemplate {
+    pub lists:  Vec<TemplateItem>,
+    pub labels: Vec<TemplateItem>
+}
+
+//The colour is empty for lists.
+pub struct TemplateItem {
+    pub name:       String,
+    pub color:      String,
+    pub config_key: String
+}
+
+//An existing board, list or label, the colour is empty for boards and lists.
+struct NamedItem {
+    id:    String,
+    name:  String,
+    color: String
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl LabelSpec {
+
+    //ex: "Passed:green", "Passed" or a label id.
+    pub fn parse(spec: &str) -> Result<LabelSpec, TrelloBSTError> {
+
+        let (name, color) = match spec.rfind(':') {
+            Some(index) => (spec[..index].trim().to_string(), Option::Some(spec[index + 1..].trim().to_lowercase())),
+            None        => (spec.trim().to_string(), Option::None)
+        };
+
+        if name.is_empty() {
+            return Err(TrelloBSTError::Validation(format!("Error: The label \"{}\" has no name, labels are given as <name|id>[:color].", spec)));
+        }
+
+        if let Some(ref color) = color {
+            if !trello::LABEL_COLORS.contains(&&color[..]) {
+                return Err(TrelloBSTError::Validation(format!("Error: Invalid label color \"{}\", valid colors are {}.", color, trello::LABEL_COLORS.join(", "))));
+            }
+        }
+
+        Ok(LabelSpec {
+            name:  name,
+            color: color
+        })
+    }
+}
+
+impl BoardTemplate {
+
+    //Loads a template file, else ~/.TrelloBST.templates/<name>.json, else the built-in template of that name.
+    pub fn load(template: &str) -> Result<BoardTemplate, TrelloBSTError> {
+
+        let mut user_template_path = env::home_dir().unwrap_or(PathBuf::from("."));
+        user_template_path.push(".TrelloBST.templates");
+        user_template_path.push(format!("{}.json", template));
+
+        for template_path in &[PathBuf::from(template), user_template_path] {
+            if template_path.is_file() {
+                let path          = template_path.to_string_lossy().to_string();
+                let mut file_data = String::new();
+                try!(File::open(template_path).and_then(|mut file| file.read_to_string(&mut file_data))
+                     .map_err(|err| TrelloBSTError::config_io(&path, err).context(format!("Error: Failed to read the board template {}", path))));
+                return BoardTemplate::from_json(&path, &file_data);
+            }
+        }
+
+        match template {
+            "kanban" => BoardTemplate::from_json("the kanban board template", include_str!("templates/kanban.json")),
+            _        => Err(TrelloBSTError::Validation(format!("Error: Unknown board template \"{}\", the built-in templates are: kanban.", template)))
+        }
+    }
+
+    //ex: {"lists": [{"name": "Passed", "config_key": "trello_list_passed_id"}], "labels": [{"name": "Passed", "color": "green", "config_key": "trello_label_pass_id"}]}
+    pub fn from_json(what: &str, json_data: &str) -> Result<BoardTemplate, TrelloBSTError> {
+
+        let data: Value = match serde_json::from_str(json_data) {
+            Ok(data) => data,
+            Err(err) => return Err(TrelloBSTError::parse(what, err))
+        };
+
+        let board_template = BoardTemplate {
+            lists:  try!(template_items(what, &data, "lists", false)),
+            labels: try!(template_items(what, &data, "labels", true))
+        };
+
+        if board_template.lists.is_empty() {
+            return Err(TrelloBSTError::parse(what, "The template has no lists."));
+        }
+
+        //Keys name a list or a label so a template can't overwrite the token or another setting
+        //NOTE: Keys push doesn't read (ex: "trello_list_queued_id") are saved all the same, for other tools and later versions.
+        for &(items, prefix) in &[(&board_template.lists, "trello_list_"), (&board_template.labels, "trello_label_")] {
+            if let Some(item) = items.iter().find(|item| !is_template_key(&item.config_key, prefix)) {
+                return Err(TrelloBSTError::parse(what, format!("The config key \"{}\" of \"{}\" is invalid, keys are written as \"{}<name>_id\".", item.config_key, item.name, prefix)));
+            }
+        }
+
+        Ok(board_template)
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Resolves the board, list and labels to ids, creating the missing ones, and writes them to the config.
+//NOTE: Nothing is prompted, missing lists and labels are only created with --yes and a missing board with --create-board.
+pub fn setup(term: &mut Box<term::StdoutTerminal>, api_key: &str, config: &mut config::TrelloBSTConfig, options: &SetupOptions) -> Result<(), TrelloBSTError> {
+
+    let token  = &options.trello_api_token[..];
+    let client = TrelloClient::new(api_key, token);
+
+    //Board
+    let status = utils::StatusPrint::from_string(term, format!("Resolving the board \"{}\".", options.board));
+    let (board_id, is_board_created) = match resolve_board(&client, options) {
+        Ok(board) => {
+            status.success(term);
+            board
+        }
+        Err(err) => {
+            status.error(term);
+            return Err(err);
+        }
+    };
+
+    //Lists and labels of a board we just created are missing by definition.
+    let can_create = options.assume_yes || is_board_created;
+
+    //List
+    let status  = utils::StatusPrint::from_string(term, format!("Resolving the list \"{}\".", options.list));
+    let list_id = match resolve_list(&client, &board_id, &options.list, can_create) {
+        Ok(list_id) => {
+            status.success(term);
+            list_id
+        }
+        Err(err) => {
+            status.error(term);
+            return Err(err);
+        }
+    };
+
+    //Labels
+    let mut label_ids = Vec::new();
+    for &(build_status, spec) in &[(BuildStatus::Passed, &options.pass_label), (BuildStatus::Failed, &options.fail_label)] {
+        let status = utils::StatusPrint::from_string(term, format!("Resolving the build {} label \"{}\".", build_status.name(), spec.name));
+        match resolve_label(&client, &board_id, spec, can_create) {
+            Ok(label_id) => {
+                status.success(term);
+                label_ids.push((build_status, label_id));
+            }
+            Err(err) => {
+                status.error(term);
+                return Err(err);
+            }
+        }
+    }
+
+    //Optional label ids from another board would be rejected by push.
+    if config.get("trello_board_id") != board_id {
+        for build_status in BuildStatus::all() {
+            if !build_status.is_required() {
+                config.set(&build_status.config_key(), "");
+            }
+        }
+    }
+
+    config.set("trello_api_token", token);
+    config.set("trello_board_id", &board_id);
+    config.set("trello_list_id", &list_id);
+    for stage in ListStage::all() {
+        config.set(&stage.config_key(), "");
+    }
+    for &(build_status, ref label_id) in &label_ids {
+        config.set(&build_status.config_key(), label_id);
+    }
+
+    println!("trello_board_id: {}", board_id);
+    println!("trello_list_id: {}", list_id);
+    for &(build_status, ref label_id) in &label_ids {
+        println!("{}: {}", build_status.config_key(), label_id);
+    }
+
+    Ok(())
+}
+
+//Creates a board with the template's lists and labels and writes their ids to the config.
+//NOTE: The template replaces the list layout and the build status labels, those it doesn't set are cleared.
+pub fn setup_from_template(term: &mut Box<term::StdoutTerminal>, api_key: &str, config: &mut config::TrelloBSTConfig, trello_api_token: &str, board_name: &str, board_template: &BoardTemplate) -> Result<(), TrelloBSTError> {
+
+    let client = TrelloClient::new(api_key, trello_api_token);
+
+    //A second board with the same name would make "setup --board <name>" ambiguous.
+    let status = utils::StatusPrint::from_str(term, "Acquiring board list from Trello.");
+    match open_boards(&client).and_then(|boards| find_item("board", &boards, board_name, Option::None)) {
+        Ok(Option::None)    => status.success(term),
+        Ok(Option::Some(_)) => {
+            status.error(term);
+            return Err(TrelloBSTError::Validation(format!("Error: A board named \"{}\" already exists, the template needs a new board.", board_name)));
+        }
+        Err(err) => {
+            status.error(term);
+            return Err(err);
+        }
+    }
+
+    let mut ids = Vec::new();
+
+    //Board, without Trello's default labels so only the template's are on it.
+    let status   = utils::StatusPrint::from_string(term, format!("Creating the board \"{}\".", board_name));
+    let board_id = match client.create_board(board_name, false, false) {
+        Ok(board) => {
+            status.success(term);
+            board.id
+        }
+        Err(err) => {
+            status.error(term);
+            return Err(err);
+        }
+    };
+    ids.push(("trello_board_id".to_string(), board_id.clone()));
+
+    //A half made board would make the next run fail on its name, it is closed instead
+    if let Err(err) = create_template_items(term, &client, &board_id, board_template, &mut ids) {
+        let status = utils::StatusPrint::from_string(term, format!("Closing the board \"{}\".", board_name));
+        return match client.close_board(&board_id) {
+            Ok(_) => {
+                status.success(term);
+                Err(err.context("Error: The board template could not be created, the new board has been closed"))
+            }
+            Err(_) => {
+                status.error(term);
+                Err(err.context(format!("Error: The board template could not be created and the new board could not be closed, close \"{}\" on Trello before running the setup again", board_name)))
+            }
+        };
+    }
+
+    config.set("trello_api_token", trello_api_token);
+    config.set("trello_list_id", "");
+    for stage in ListStage::all() {
+        config.set(&stage.config_key(), "");
+    }
+    for build_status in BuildStatus::all() {
+        config.set(&build_status.config_key(), "");
+    }
+    for &(ref config_key, ref id) in &ids {
+        config.set(config_key, id);
+        println!("{}: {}", config_key, id);
+    }
+
+    Ok(())
+}
+
+//Creates the template's lists and labels on the new board, their ids are added to ids.
+fn create_template_items(term: &mut Box<term::StdoutTerminal>, client: &TrelloClient, board_id: &str, board_template: &BoardTemplate, ids: &mut Vec<(String, String)>) -> Result<(), TrelloBSTError> {
+
+    for list in &board_template.lists {
+        let status = utils::StatusPrint::from_string(term, format!("Creating the list \"{}\".", list.name));
+        match client.create_list(board_id, &list.name) {
+            Ok(created_list) => {
+                status.success(term);
+                ids.push((list.config_key.clone(), created_list.id));
+            }
+            Err(err) => {
+                status.error(term);
+                return Err(err);
+            }
+        }
+    }
+
+    for label in &board_template.labels {
+        let status = utils::StatusPrint::from_string(term, format!("Creating the label \"{}\".", label.name));
+        match client.create_label(board_id, &label.name, &label.color) {
+            Ok(created_label) => {
+                status.success(term);
+                ids.push((label.config_key.clone(), created_label.id));
+            }
+            Err(err) => {
+                status.error(term);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn open_boards(client: &TrelloClient) -> Result<Vec<NamedItem>, TrelloBSTError> {
+    let boards = try!(client.my_open_boards());
+    Ok(boards.into_iter().map(|board| NamedItem {id: board.id, name: board.name, color: String::new()}).collect())
+}
+
+//Gets the id of the board and whether it was just created.
+fn resolve_board(client: &TrelloClient, options: &SetupOptions) -> Result<(String, bool), TrelloBSTError> {
+
+    let boards = try!(open_boards(client));
+    if let Some(board_id) = try!(find_item("board", &boards, &options.board, Option::None)) {
+        return Ok((board_id, false));
+    }
+
+    if !options.create_board {
+        return Err(TrelloBSTError::Validation(format!("Error: No open board is named \"{}\" or has this id, use --create-board to create it.", options.board)));
+    }
+
+    Ok((try!(client.create_board(&options.board, false, true)).id, true))
+}
+
+fn resolve_list(client: &TrelloClient, board_id: &str, list: &str, can_create: bool) -> Result<String, TrelloBSTError> {
+
+    let board_lists = try!(client.board_lists(board_id));
+
+    let lists: Vec<NamedItem> = board_lists.into_iter().map(|list| NamedItem {id: list.id, name: list.name, color: String::new()}).collect();
+    if let Some(list_id) = try!(find_item("list", &lists, list, Option::None)) {
+        return Ok(list_id);
+    }
+
+    if !can_create {
+        return Err(TrelloBSTError::Validation(format!("Error: The board has no open list named \"{}\" or with this id, use --yes to create it.", list)));
+    }
+
+    Ok(try!(client.create_list(board_id, list)).id)
+}
+
+fn resolve_label(client: &TrelloClient, board_id: &str, spec: &LabelSpec, can_create: bool) -> Result<String, TrelloBSTError> {
+
+    let board_labels = try!(client.board_labels(board_id));
+
+    let labels: Vec<NamedItem> = board_labels.into_iter().map(|label| NamedItem {color: label.color_name(), id: label.id, name: label.name}).collect();
+    if let Some(label_id) = try!(find_item("label", &labels, &spec.name, spec.color.as_ref().map(|color| &color[..]))) {
+        return Ok(label_id);
+    }
+
+    let color = match spec.color {
+        Some(ref color) => color.clone(),
+        None            => return Err(TrelloBSTError::Validation(format!("Error: The board has no label named \"{}\" or with this id, give it a color (<name>:<color>) to create it.", spec.name)))
+    };
+
+    if !can_create {
+        return Err(TrelloBSTError::Validation(format!("Error: The board has no {} label named \"{}\" or with this id, use --yes to create it.", color, spec.name)));
+    }
+
+    Ok(try!(client.create_label(board_id, &spec.name, &color)).id)
+}
+
+//Finds an item by id, else by name, exact names win over names differing only by case.
+//When a colour is given, labels of another colour are ignored.
+//NOTE: Several items matching the same name is an error rather than a guess, the id must be used instead.
+fn find_item(kind: &str, items: &Vec<NamedItem>, wanted: &str, color: Option<&str>) -> Result<Option<String>, TrelloBSTError> {
+
+    if let Some(item) = items.iter().find(|item| item.id == wanted) {
+        return Ok(Option::Some(item.id.clone()));
+    }
+
+    let candidates: Vec<&NamedItem> = items.iter().filter(|item| color.map_or(true, |color| item.color == color)).collect();
+
+    let mut matches: Vec<&NamedItem> = candidates.iter().cloned().filter(|item| item.name == wanted).collect();
+    if matches.is_empty() {
+        matches = candidates.iter().cloned().filter(|item| item.name.to_lowercase() == wanted.to_lowercase()).collect();
+    }
+
+    match matches.len() {
+        0 => Ok(Option::None),
+        1 => Ok(Option::Some(matches[0].id.clone())),
+        _ => {
+            let ids: Vec<String> = matches.iter().map(|item| item.id.clone()).collect();
+            Err(TrelloBSTError::Validation(format!("Error: \"{}\" is ambiguous, {} {}s match it ({}), use the {} id instead.", wanted, matches.len(), kind, ids.join(", "), kind)))
+        }
+    }
+}
+
+//Gets the "lists" or "labels" entries of a board template, labels also need a valid colour.
+//ex: "trello_list_queued_id" for the "trello_list_" prefix, "trello_list_id" is the single list's key.
+fn is_template_key(config_key: &str, prefix: &str) -> bool {
+
+    if config_key == format!("{}id", prefix) {
+        return true;
+    }
+    if !config_key.starts_with(prefix) || !config_key.ends_with("_id") || config_key.len() <= prefix.len() + 3 {
+        return false;
+    }
+
+    config_key[prefix.len()..config_key.len() - 3].chars().all(|c| match c {
+        'a'...'z' | '0'...'9' | '_' => true,
+        _                           => false
+    })
+}
+
+fn template_items(what: &str, data: &Value, field: &str, needs_color: bool) -> Result<Vec<TemplateItem>, TrelloBSTError> {
+
+    let entries = match data.lookup(field) {
+        Some(entries) => try!(entries.as_array().ok_or(TrelloBSTError::parse(what, format!("The \"{}\" field does not describe an array.", field)))).clone(),
+        None          => Vec::new()
+    };
+
+    let mut items = Vec::new();
+    for entry in &entries {
+
+        let get_string = |key: &str| entry.lookup(key).and_then(|value| value.as_str()).unwrap_or("").trim().to_string();
+        let item       = TemplateItem {
+            name:       get_string("name"),
+            color:      get_string("color").to_lowercase(),
+            config_key: get_string("config_key")
+        };
+
+        if item.name.is_empty() || item.config_key.is_empty() {
+            return Err(TrelloBSTError::parse(what, format!("An entry of \"{}\" is missing its \"name\" or \"config_key\".", field)));
+        }
+        if needs_color && !trello::LABEL_COLORS.contains(&&item.color[..]) {
+            return Err(TrelloBSTError::parse(what, format!("The label \"{}\" has an invalid color, valid colors are {}.", item.name, trello::LABEL_COLORS.join(", "))));
+        }
+
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn builtin_template_lists_and_labels() {
+        let board_template = BoardTemplate::load("kanban").unwrap();
+        let list_keys: Vec<&str>  = board_template.lists.iter().map(|list| &list.config_key[..]).collect();
+        let label_keys: Vec<&str> = board_template.labels.iter().map(|label| &label.config_key[..]).collect();
+
+        assert_eq!(list_keys, vec!["trello_list_queued_id", "trello_list_running_id", "trello_list_passed_id", "trello_list_failed_id", "trello_list_archived_id"]);
+        assert_eq!(label_keys.len(), 9);
+        assert!(label_keys.contains(&"trello_label_pass_id"));
+        assert!(label_keys.contains(&"trello_label_linux_id"));
+    }
+
+    #[test]
+    fn template_keys_push_does_not_read_are_kept() {
+        let template       = "{\"lists\": [{\"name\": \"Queued\", \"config_key\": \"trello_list_queued_id\"}], \"labels\": [{\"name\": \"Linux\", \"color\": \"sky\", \"config_key\": \"trello_label_linux_id\"}]}";
+        let board_template = BoardTemplate::from_json("the template", template).unwrap();
+        assert_eq!(board_template.lists[0].config_key, "trello_list_queued_id");
+        assert_eq!(board_template.labels[0].config_key, "trello_label_linux_id");
+    }
+
+    #[test]
+    fn template_keys_must_name_a_list_or_label() {
+        let template = "{\"lists\": [{\"name\": \"Builds\", \"config_key\": \"trello_api_token\"}]}";
+        assert!(BoardTemplate::from_json("the template", template).is_err());
+
+        let template = "{\"lists\": [{\"name\": \"Builds\", \"config_key\": \"trello_label_pass_id\"}]}";
+        assert!(BoardTemplate::from_json("the template", template).is_err());
+
+        let template = "{\"lists\": [{\"name\": \"Builds\", \"config_key\": \"trello_list_\"}]}";
+        assert!(BoardTemplate::from_json("the template", template).is_err());
+    }
+
+    #[test]
+    fn template_with_known_keys() {
+        let template = "{\"lists\": [{\"name\": \"Builds\", \"config_key\": \"trello_list_id\"}], \"labels\": [{\"name\": \"Passed\", \"color\": \"green\", \"config_key\": \"trello_label_pass_id\"}]}";
+        assert!(BoardTemplate::from_json("the template", template).is_ok());
+    }
+}
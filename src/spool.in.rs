@@ -0,0 +1,237 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use error::TrelloBSTError;
+use trello_client::TrelloClient;
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//A push that could not be delivered, replayed by "flush".
+//NOTE: label_id is empty for build statuses pushed without a label.
+#[derive(Serialize, Deserialize)]
+pub struct SpoolEntry {
+    pub title:           String,
+    pub desc:            String,
+    pub label_id:        String,
+    #[serde(default)]
+    pub extra_label_ids: Vec<String>,
+    pub list_id:         String,
+    pub card_id:         String,
+    //dateLastActivity of the updated card when it was last pushed, empty if unknown.
+    #[serde(default)]
+    pub last_activity:   String,
+    pub member_ids:      Vec<String>,
+    pub timestamp:       u64
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the spool directory from the TRELLOBST_SPOOL_DIR env var, ~/.TrelloBST.spool by default.
+pub fn default_spool_dir() -> String {
+
+    match env::var("TRELLOBST_SPOOL_DIR") {
+        Ok(spool_dir) => {
+            if !spool_dir.is_empty() {
+                return spool_dir;
+            }
+        }
+        Err(_) => ()
+    }
+
+    match env::home_dir() {
+        Some(mut home_dir) => {
+            home_dir.push(".TrelloBST.spool");
+            home_dir.to_string_lossy().to_string()
+        }
+        None => "./.TrelloBST.spool".to_string()
+    }
+}
+
+//Writes an entry to the spool, file names sort in the order the entries were spooled.
+pub fn spool(spool_dir: &str, entry: &SpoolEntry) -> Result<PathBuf, TrelloBSTError> {
+
+    match fs::create_dir_all(spool_dir) {
+        Ok(())   => (),
+        Err(err) => return Err(TrelloBSTError::config_io(spool_dir, err).context("Error: Failed to create the spool directory"))
+    }
+
+    let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() * 1000000000 + duration.subsec_nanos() as u64,
+        Err(_)       => 0
+    };
+
+    let mut path = PathBuf::from(spool_dir);
+    path.push(format!("{:020}.json", nanos));
+
+    let entry_json = match serde_json::to_string_pretty(entry) {
+        Ok(entry_json) => entry_json,
+        Err(err)       => return Err(TrelloBSTError::parse("the spool entry", err))
+    };
+
+    let mut file = match File::create(&path) {
+        Ok(file) => file,
+        Err(err) => return Err(TrelloBSTError::config_io(&path.to_string_lossy(), err).context("Error: Failed to create the spool entry file"))
+    };
+
+    match file.write_all(entry_json.as_bytes()) {
+        Ok(())   => Ok(path),
+        Err(err) => Err(TrelloBSTError::config_io(&path.to_string_lossy(), err).context("Error: Failed to write the spool entry file"))
+    }
+}
+
+//Gets the spooled entries, oldest first.
+pub fn entries(spool_dir: &str) -> Result<Vec<(PathBuf, SpoolEntry)>, TrelloBSTError> {
+
+    let dir_entries = match fs::read_dir(spool_dir) {
+        Ok(dir_entries) => dir_entries,
+        Err(_)          => return Ok(Vec::new())
+    };
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for dir_entry in dir_entries {
+        match dir_entry {
+            Ok(dir_entry) => {
+                let path = dir_entry.path();
+                if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                    paths.push(path);
+                }
+            }
+            Err(err) => return Err(TrelloBSTError::config_io(spool_dir, err).context("Error: Failed to read the spool directory"))
+        }
+    }
+    paths.sort();
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let mut file_data = String::new();
+        match File::open(&path).and_then(|mut file| file.read_to_string(&mut file_data)) {
+            Ok(_)    => (),
+            Err(err) => return Err(TrelloBSTError::config_io(&path.to_string_lossy(), err).context("Error: Failed to read a spool entry file"))
+        }
+        match serde_json::from_str(&file_data) {
+            Ok(entry) => entries.push((path, entry)),
+            Err(err)  => return Err(TrelloBSTError::parse(&format!("the spool entry file \"{}\"", path.display()), err))
+        }
+    }
+
+    Ok(entries)
+}
+
+//Checks if an entry no longer needs to be pushed: a new card already on its list, or an updated card changed since
+//it was spooled (ex: by a later build), replaying the update would overwrite the newer card.
+pub fn is_delivered(api_key: &str, trello_api_token: &str, entry: &SpoolEntry) -> Result<bool, TrelloBSTError> {
+
+    let client = TrelloClient::new(api_key, trello_api_token);
+
+    if !entry.card_id.is_empty() {
+        if entry.last_activity.is_empty() {
+            return Ok(false);
+        }
+        let card = try!(client.card(&entry.card_id));
+        return Ok(card.date_last_activity > entry.last_activity);
+    }
+
+    Ok(try!(find_card(&client, &entry.list_id, &entry.title, &entry.label_id)).is_some())
+}
+
+//Gets the id of the card a spooled "push start" created, once "flush" pushed it.
+//NOTE: An entry still in the spool is removed instead, the card of "push finish" replaces it.
+pub fn take_spooled_card(api_key: &str, trello_api_token: &str, spool_entry: &str, list_id: &str, title: &str, label_id: &str) -> Result<Option<String>, TrelloBSTError> {
+
+    let path = PathBuf::from(spool_entry);
+    if path.exists() {
+        try!(remove(&path));
+        return Ok(None);
+    }
+
+    find_card(&TrelloClient::new(api_key, trello_api_token), list_id, title, label_id)
+}
+
+//Only errors that can go away by themselves are spooled, others would fail again on "flush".
+pub fn is_spoolable(err: &TrelloBSTError) -> bool {
+    match *err.root() {
+        TrelloBSTError::Transport {..}          => true,
+        TrelloBSTError::HttpStatus {status, ..} => status == 429 || status >= 500,
+        _                                       => false
+    }
+}
+
+pub fn remove(path: &PathBuf) -> Result<(), TrelloBSTError> {
+    match fs::remove_file(path) {
+        Ok(())   => Ok(()),
+        Err(err) => Err(TrelloBSTError::config_io(&path.to_string_lossy(), err).context("Error: Failed to remove the spool entry file"))
+    }
+}
+
+//Finds an open card of a list by its title and label, an empty label matches any card.
+fn find_card(client: &TrelloClient, list_id: &str, title: &str, label_id: &str) -> Result<Option<String>, TrelloBSTError> {
+    let cards = try!(client.list_cards(list_id));
+    Ok(cards.into_iter()
+            .find(|card| card.name == title && (label_id.is_empty() || card.id_labels.iter().any(|card_label_id| card_label_id == label_id)))
+            .map(|card| card.id))
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn http_status(status: u16) -> TrelloBSTError {
+        TrelloBSTError::HttpStatus {request: "PUT cards/1".to_string(), status: status, message: String::new()}
+    }
+
+    #[test]
+    fn transient_errors_are_spoolable() {
+        assert!(is_spoolable(&TrelloBSTError::Transport {request: "POST cards".to_string(), cause: "timed out".to_string()}));
+        assert!(is_spoolable(&http_status(429)));
+        assert!(is_spoolable(&http_status(503)));
+        assert!(is_spoolable(&http_status(502).context("Error while pushing the card to Trello")));
+    }
+
+    #[test]
+    fn permanent_errors_are_not_spoolable() {
+        assert!(!is_spoolable(&http_status(400)));
+        assert!(!is_spoolable(&http_status(404)));
+        assert!(!is_spoolable(&TrelloBSTError::Api {request: "POST cards".to_string(), message: "invalid token".to_string()}));
+        assert!(!is_spoolable(&TrelloBSTError::Validation("Error: No list".to_string())));
+    }
+}
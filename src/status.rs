@@ -0,0 +1,164 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use error::TrelloBSTError;
+
+
+////////////////////////////////////////////////////////////
+//                         Enums                          //
+////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildStatus {
+    Pending,
+    Running,
+    Passed,
+    Failed,
+    Errored,
+    Cancelled
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl BuildStatus {
+
+    pub fn all() -> Vec<BuildStatus> {
+        vec![BuildStatus::Pending,
+             BuildStatus::Running,
+             BuildStatus::Passed,
+             BuildStatus::Failed,
+             BuildStatus::Errored,
+             BuildStatus::Cancelled]
+    }
+
+    pub fn from_str(status: &str) -> Result<BuildStatus, TrelloBSTError> {
+        match &status.trim().to_lowercase()[..] {
+            "pending"   => Ok(BuildStatus::Pending),
+            "running"   => Ok(BuildStatus::Running),
+            "passed"    => Ok(BuildStatus::Passed),
+            "failed"    => Ok(BuildStatus::Failed),
+            "errored"   => Ok(BuildStatus::Errored),
+            "cancelled" => Ok(BuildStatus::Cancelled),
+            _           => Err(TrelloBSTError::Validation("Error: Invalid build status, valid statuses are pending, running, passed, failed, errored and cancelled.".to_string()))
+        }
+    }
+
+    //ex: "passed"
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BuildStatus::Pending   => "pending",
+            BuildStatus::Running   => "running",
+            BuildStatus::Passed    => "passed",
+            BuildStatus::Failed    => "failed",
+            BuildStatus::Errored   => "errored",
+            BuildStatus::Cancelled => "cancelled"
+        }
+    }
+
+    //ex: "PASSED", used in card titles.
+    pub fn title(&self) -> String {
+        self.name().to_uppercase()
+    }
+
+    //NOTE: Passed and failed keep the "pass" and "fail" names used by previous configs and CI files.
+    fn short_name(&self) -> &'static str {
+        match *self {
+            BuildStatus::Passed => "pass",
+            BuildStatus::Failed => "fail",
+            _                   => self.name()
+        }
+    }
+
+    //Key of the label id in the TrelloBST config, ex: "trello_label_pass_id"
+    pub fn config_key(&self) -> String {
+        format!("trello_label_{}_id", self.short_name())
+    }
+
+    //Environment variable holding the label id for "TrelloBST push", ex: "TRELLO_API_BUILD_PASS_ID"
+    pub fn env_var(&self) -> String {
+        format!("TRELLO_API_BUILD_{}_ID", self.short_name().to_uppercase())
+    }
+
+    //Passed and failed labels are needed for a working setup, the others are optional.
+    pub fn is_required(&self) -> bool {
+        *self == BuildStatus::Passed || *self == BuildStatus::Failed
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == BuildStatus::Passed
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       List Stages                      //
+////////////////////////////////////////////////////////////
+
+//Lists a card goes through when the build statuses are split by build stage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ListStage {
+    Running,
+    Passed,
+    Failed
+}
+
+impl ListStage {
+
+    pub fn all() -> Vec<ListStage> {
+        vec![ListStage::Running,
+             ListStage::Passed,
+             ListStage::Failed]
+    }
+
+    //Errored and cancelled builds end up with the failed ones.
+    pub fn from_status(status: BuildStatus) -> ListStage {
+        match status {
+            BuildStatus::Pending | BuildStatus::Running => ListStage::Running,
+            BuildStatus::Passed                         => ListStage::Passed,
+            _                                           => ListStage::Failed
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ListStage::Running => "running",
+            ListStage::Passed  => "passed",
+            ListStage::Failed  => "failed"
+        }
+    }
+
+    //Key of the list id in the TrelloBST config, ex: "trello_list_running_id"
+    pub fn config_key(&self) -> String {
+        format!("trello_list_{}_id", self.name())
+    }
+
+    //Environment variable holding the list id for "TrelloBST push", ex: "TRELLO_API_LIST_RUNNING_ID"
+    pub fn env_var(&self) -> String {
+        format!("TRELLO_API_LIST_{}_ID", self.name().to_uppercase())
+    }
+}
@@ -0,0 +1,311 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::collections::BTreeMap;
+use std::env;
+
+use ci_env::CIEnvironment;
+use error::TrelloBSTError;
+use status::{BuildStatus, ListStage};
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+pub const PLACEHOLDERS: &'static [&'static str] = &["branch",
+                                                    "build_number",
+                                                    "status",
+                                                    "commit",
+                                                    "commit_short",
+                                                    "duration",
+                                                    "provider",
+                                                    "os",
+                                                    "compiler",
+                                                    "repo",
+                                                    "log_url",
+                                                    "commit_url"];
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//A card title or description template.
+//ex: "[{branch}][{provider}]: #{build_number} {status}{?fail} ({commit_short}){/fail}"
+//  {name}           is replaced by the value of a placeholder from PLACEHOLDERS
+//  {env:VAR}        is replaced by the value of the VAR environment variable
+//  {?pass}..{/pass} is only kept for passed builds
+//  {?fail}..{/fail} is only kept for failed, errored and cancelled builds
+//  {{ and }}        are a literal { and }
+#[derive(Clone)]
+pub struct Template {
+    sections: Vec<Section>
+}
+
+#[derive(Clone)]
+enum Section {
+    Text(String),
+    Placeholder(String),
+    Env(String),
+    Conditional(ListStage, Vec<Section>)
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl Template {
+
+    pub fn parse(template: &str) -> Result<Template, TrelloBSTError> {
+
+        //Sections being parsed, one entry per open conditional section
+        let mut stack: Vec<(Option<ListStage>, Vec<Section>)> = vec![(Option::None, Vec::new())];
+        let mut text  = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    if chars.peek() == Some(&'{') {
+                        chars.next();
+                        text.push('{');
+                        continue;
+                    }
+
+                    let mut tag    = String::new();
+                    let mut closed = false;
+                    while let Some(c) = chars.next() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        tag.push(c);
+                    }
+                    if !closed {
+                        return Err(TrelloBSTError::Validation(format!("Error: Unclosed placeholder \"{{{}\" in the template \"{}\".", tag, template)));
+                    }
+
+                    if !text.is_empty() {
+                        stack.last_mut().unwrap().1.push(Section::Text(text.clone()));
+                        text.clear();
+                    }
+
+                    if tag.starts_with('?') {
+                        let stage = try!(condition_from_str(&tag[1..], template));
+                        stack.push((Option::Some(stage), Vec::new()));
+                    } else if tag.starts_with('/') {
+                        let stage = try!(condition_from_str(&tag[1..], template));
+                        if stack.len() < 2 || stack.last().unwrap().0 != Option::Some(stage) {
+                            return Err(TrelloBSTError::Validation(format!("Error: \"{{{}}}\" does not close an open section in the template \"{}\".", tag, template)));
+                        }
+                        let (_, sections) = stack.pop().unwrap();
+                        stack.last_mut().unwrap().1.push(Section::Conditional(stage, sections));
+                    } else if tag.starts_with("env:") {
+                        if tag.len() == 4 {
+                            return Err(TrelloBSTError::Validation(format!("Error: Missing environment variable name in \"{{{}}}\" in the template \"{}\".", tag, template)));
+                        }
+                        stack.last_mut().unwrap().1.push(Section::Env(tag[4..].to_string()));
+                    } else if PLACEHOLDERS.contains(&&tag[..]) {
+                        stack.last_mut().unwrap().1.push(Section::Placeholder(tag));
+                    } else {
+                        return Err(TrelloBSTError::Validation(format!("Error: Unknown placeholder \"{{{}}}\" in the template \"{}\", valid placeholders are {{{}}} and {{env:VAR}}.", tag, template, PLACEHOLDERS.join("}, {"))));
+                    }
+                }
+                '}' => {
+                    if chars.peek() == Some(&'}') {
+                        chars.next();
+                    }
+                    text.push('}');
+                }
+                _   => text.push(c)
+            }
+        }
+
+        if stack.len() != 1 {
+            return Err(TrelloBSTError::Validation(format!("Error: Unclosed \"{{?{}}}\" section in the template \"{}\".", condition_name(stack.last().unwrap().0.unwrap()), template)));
+        }
+
+        let (_, mut sections) = stack.pop().unwrap();
+        if !text.is_empty() {
+            sections.push(Section::Text(text));
+        }
+
+        Ok(Template{sections: sections})
+    }
+
+    pub fn render(&self, status: BuildStatus, values: &BTreeMap<&'static str, String>) -> String {
+        let mut output = String::new();
+        render_sections(&self.sections, ListStage::from_status(status), values, &mut output);
+        output
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Gets the value of every placeholder, those not known are left empty.
+pub fn values(status: BuildStatus, ci_env: &Option<CIEnvironment>, duration: &str) -> BTreeMap<&'static str, String> {
+
+    let mut values = BTreeMap::new();
+    for placeholder in PLACEHOLDERS {
+        values.insert(*placeholder, String::new());
+    }
+
+    values.insert("status",   status.title());
+    values.insert("duration", duration.to_string());
+
+    if let Some(ref ci_env) = *ci_env {
+        values.insert("branch",       ci_env.branch.clone());
+        values.insert("build_number", ci_env.build_number.clone());
+        values.insert("commit",       ci_env.commit.clone());
+        values.insert("commit_short", ci_env.commit.chars().take(7).collect());
+        values.insert("provider",     ci_env.provider.clone());
+        values.insert("os",           ci_env.os.clone());
+        values.insert("compiler",     ci_env.compiler.clone());
+        values.insert("repo",         ci_env.repo_slug.clone());
+        values.insert("log_url",      ci_env.log_url.clone());
+        values.insert("commit_url",   ci_env.commit_url.clone());
+    }
+
+    values
+}
+
+fn render_sections(sections: &Vec<Section>, stage: ListStage, values: &BTreeMap<&'static str, String>, output: &mut String) {
+    for section in sections {
+        match *section {
+            Section::Text(ref text)              => output.push_str(text),
+            Section::Placeholder(ref name)       => output.push_str(values.get(&name[..]).map(|val| &val[..]).unwrap_or("")),
+            Section::Env(ref name)               => output.push_str(&env::var(name).unwrap_or(String::new())),
+            Section::Conditional(cond, ref subs) => {
+                if cond == stage {
+                    render_sections(subs, stage, values, output);
+                }
+            }
+        }
+    }
+}
+
+fn condition_from_str(name: &str, template: &str) -> Result<ListStage, TrelloBSTError> {
+    match name {
+        "pass" => Ok(ListStage::Passed),
+        "fail" => Ok(ListStage::Failed),
+        _      => Err(TrelloBSTError::Validation(format!("Error: Unknown section \"{}\" in the template \"{}\", valid sections are {{?pass}}..{{/pass}} and {{?fail}}..{{/fail}}.", name, template)))
+    }
+}
+
+fn condition_name(stage: ListStage) -> &'static str {
+    if stage == ListStage::Passed {"pass"} else {"fail"}
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeMap;
+    use std::env;
+
+    use status::BuildStatus;
+    use super::*;
+
+    fn render(template: &str, status: BuildStatus) -> String {
+        let mut values = BTreeMap::new();
+        values.insert("branch",       "master".to_string());
+        values.insert("build_number", "42".to_string());
+        values.insert("status",       status.title());
+        Template::parse(template).unwrap().render(status, &values)
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        assert_eq!(render("[{branch}]: #{build_number} {status}", BuildStatus::Passed), "[master]: #42 PASSED");
+    }
+
+    #[test]
+    fn known_placeholders_without_a_value_are_empty() {
+        assert_eq!(render("{os}|{compiler}", BuildStatus::Passed), "|");
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        match Template::parse("{branch} {nope}") {
+            Err(TrelloBSTError::Validation(message)) => assert!(message.contains("{nope}")),
+            _                                         => panic!("unknown placeholders must be validation errors")
+        }
+        assert!(Template::parse("{}").is_err());
+    }
+
+    #[test]
+    fn rejects_unclosed_placeholders() {
+        assert!(Template::parse("{branch").is_err());
+    }
+
+    #[test]
+    fn rejects_unclosed_and_mismatched_sections() {
+        assert!(Template::parse("{?pass}ok").is_err());
+        assert!(Template::parse("{?pass}ok{/fail}").is_err());
+        assert!(Template::parse("ok{/pass}").is_err());
+        assert!(Template::parse("{?pass}{?fail}{/pass}{/fail}").is_err());
+        assert!(Template::parse("{?maybe}ok{/maybe}").is_err());
+    }
+
+    #[test]
+    fn braces_are_escaped_by_doubling() {
+        assert_eq!(render("{{branch}} {{{branch}}}", BuildStatus::Passed), "{branch} {master}");
+        assert_eq!(render("a }} b", BuildStatus::Passed), "a } b");
+    }
+
+    #[test]
+    fn renders_environment_variables() {
+        env::set_var("TRELLOBST_TEMPLATE_TEST_VAR", "value");
+        assert_eq!(render("[{env:TRELLOBST_TEMPLATE_TEST_VAR}]", BuildStatus::Passed), "[value]");
+        assert_eq!(render("[{env:TRELLOBST_TEMPLATE_TEST_UNSET}]", BuildStatus::Passed), "[]");
+        assert!(Template::parse("{env:}").is_err());
+    }
+
+    #[test]
+    fn sections_follow_the_build_status() {
+        let template = "{status}{?pass} ok{/pass}{?fail} ko{/fail}";
+        assert_eq!(render(template, BuildStatus::Passed),    "PASSED ok");
+        assert_eq!(render(template, BuildStatus::Failed),    "FAILED ko");
+        assert_eq!(render(template, BuildStatus::Errored),   "ERRORED ko");
+        assert_eq!(render(template, BuildStatus::Cancelled), "CANCELLED ko");
+        assert_eq!(render(template, BuildStatus::Running),   "RUNNING");
+        assert_eq!(render(template, BuildStatus::Pending),   "PENDING");
+    }
+
+    #[test]
+    fn sections_can_nest_placeholders() {
+        assert_eq!(render("{?fail}[{branch}]{/fail}", BuildStatus::Failed), "[master]");
+        assert_eq!(render("{?fail}[{branch}]{/fail}", BuildStatus::Passed), "");
+    }
+}
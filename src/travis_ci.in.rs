@@ -43,8 +43,10 @@ extern crate openssl;
 use self::openssl::crypto::pkey::{EncryptionPadding, PKey};
 use self::openssl::ssl::error::SslError;
 
+use ci;
 use ci::CITrait;
 use config;
+use error::TrelloBSTError;
 use utils;
 
 
@@ -59,13 +61,6 @@ include!("utils_macros.rs");
 //                        Structs                         //
 ////////////////////////////////////////////////////////////
 
-pub struct TravisEncryptedVars {
-    pub trello_api_token: String,
-    pub list_id:          String,
-    pub build_pass_id:    String,
-    pub build_fail_id:    String
-}
-
 #[derive(Deserialize)]
 pub struct RepoResponse {
     key:  String,
@@ -99,24 +94,24 @@ impl ParsedHooksResponse {
         }
     }
 
-    pub fn from_json(json_data: &String) -> Result<ParsedHooksResponse, String> {
+    pub fn from_json(json_data: &String) -> Result<ParsedHooksResponse, TrelloBSTError> {
 
         let mut tmp_parsed_hooks_response = ParsedHooksResponse::new();
 
         //Parse
         let data: Value = match serde_json::from_str(&json_data){
             Ok(data) => data,
-            Err(_)   => {return Err("Error parsing the JSON data".to_string());}
+            Err(err) => {return Err(TrelloBSTError::parse("the Travis-CI hooks", err));}
         };
 
         //Get JSON object
-        let object = try!(data.as_object().ok_or("Error: JSON data does not describe an object.".to_string()));
+        let object = try!(data.as_object().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "JSON data does not describe an object.")));
 
         //Get "hooks" field
-        let hooks_value = try!(object.get("hooks").ok_or("Error: The \"hooks\" field has not been found in the JSON data.".to_string()));
+        let hooks_value = try!(object.get("hooks").ok_or(TrelloBSTError::parse("the Travis-CI hooks", "The \"hooks\" field has not been found in the JSON data.")));
 
         //Get "hooks" content
-        let hooks_array: Vec<Value> = match hooks_value.as_array().ok_or("Error: The \"hooks\" field does not describe an array.".to_string()) {
+        let hooks_array: Vec<Value> = match hooks_value.as_array().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "The \"hooks\" field does not describe an array.")) {
             Ok(hooks_array) => hooks_array.clone(),
             Err(err)        => {return Err(err);}
         };
@@ -125,28 +120,28 @@ impl ParsedHooksResponse {
         for hook in &hooks_array {
 
             //Get hook object
-            let hook_object = try!(hook.as_object().ok_or("Error: An entry in the \"hook\" field does not describe an object.".to_string())).clone();
+            let hook_object = try!(hook.as_object().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "An entry in the \"hook\" field does not describe an object."))).clone();
 
             //Get "id" value
-            let id_value = try!(hook_object.get("id").ok_or("Error: Failed to acquire the \"id\" field of a \"hook\" field.".to_string())).clone();
-            let id_u64   = try!(id_value.as_u64().ok_or("Error: Failed to convert the value of the \"id\" field to a u64.".to_string()));
+            let id_value = try!(hook_object.get("id").ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to acquire the \"id\" field of a \"hook\" field."))).clone();
+            let id_u64   = try!(id_value.as_u64().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to convert the value of the \"id\" field to a u64.")));
 
             //Get "name" field
-            let name_value  = try!(hook_object.get("name").ok_or("Error: Failed to acquire the \"name\" field of a \"hook\" field.".to_string())).clone();
-            let name_string = try!(name_value.as_str().ok_or("Error: Failed to convert the value of the \"name\" field to a string.".to_string())).to_string();
+            let name_value  = try!(hook_object.get("name").ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to acquire the \"name\" field of a \"hook\" field."))).clone();
+            let name_string = try!(name_value.as_str().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to convert the value of the \"name\" field to a string."))).to_string();
 
             //Get "owner_name" field
-            let owner_name_value  = try!(hook_object.get("owner_name").ok_or("Error: Failed to acquire the \"owner_name\" field of a \"hook\" field.".to_string())).clone();
-            let owner_name_string = try!(owner_name_value.as_str().ok_or("Error: Failed to convert the value of the \"owner_name\" field to a string.".to_string())).to_string();
+            let owner_name_value  = try!(hook_object.get("owner_name").ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to acquire the \"owner_name\" field of a \"hook\" field."))).clone();
+            let owner_name_string = try!(owner_name_value.as_str().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to convert the value of the \"owner_name\" field to a string."))).to_string();
 
             //Get "active" field and if null, assume false.
-            let active_value = try!(hook_object.get("active").ok_or("Error: Failed to acquire the \"active\" field of a \"hook\" field.".to_string())).clone();
+            let active_value = try!(hook_object.get("active").ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to acquire the \"active\" field of a \"hook\" field."))).clone();
 
             let active_bool: bool;
             if active_value.is_null() {
                 active_bool = false;
             } else {
-                active_bool = try!(active_value.as_bool().ok_or("Error: Failed to convert the value of the \"active\" field to a bool.".to_string()));
+                active_bool = try!(active_value.as_bool().ok_or(TrelloBSTError::parse("the Travis-CI hooks", "Failed to convert the value of the \"active\" field to a bool.")));
             }
 
             tmp_parsed_hooks_response.hooks.push(Hook{
@@ -173,7 +168,7 @@ impl CITrait for TravisCI{
 
     fn get_name(&mut self) -> String {return "Travis-CI".to_string();}
 
-    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), String> {
+    fn setup(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
 
         let travis_access_token = config.get("travis_access_token");
 
@@ -205,7 +200,7 @@ impl CITrait for TravisCI{
 
             let response_body = match utils::rest_api_call_post_with_header(&api_call, header) {
                 Ok(response_body) => response_body,
-                Err(err)          => {return Err(err.to_string())}
+                Err(err)          => {return Err(err.context("Error while getting a Travis-CI access token"))}
             };
 
             config.set("travis_access_token", &try!(utils::get_single_json_value_as_string(&response_body, "access_token"))[..]);
@@ -213,7 +208,7 @@ impl CITrait for TravisCI{
         Ok(())
     }
 
-    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), String> {
+    fn generate_ci_config(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig) -> Result<(String, String), TrelloBSTError> {
 
         //Get repo tag and public key
         let mut crypto_state = PKey::new();
@@ -230,10 +225,12 @@ impl CITrait for TravisCI{
         //Generate
         let mut file_data = include_str!("templates/travis-ci").to_string();
 
-        file_data = file_data.replace("<TRELLO_API_TOKEN>",         &encrypted_vars.trello_api_token[..]);
-        file_data = file_data.replace("<TRELLO_API_LIST_ID>",       &encrypted_vars.list_id[..]);
-        file_data = file_data.replace("<TRELLO_API_BUILD_PASS_ID>", &encrypted_vars.build_pass_id[..]);
-        file_data = file_data.replace("<TRELLO_API_BUILD_FAIL_ID>", &encrypted_vars.build_fail_id[..]);
+        let mut secure_env_vars = String::new();
+        for encrypted_var in &encrypted_vars {
+            secure_env_vars.push_str(&format!("    - secure: {}\n", encrypted_var));
+        }
+
+        file_data = file_data.replace("<TRELLO_SECURE_ENV_VARS>\n", &secure_env_vars[..]);
 
         return Ok((self.get_filename(), file_data));
     }
@@ -242,7 +239,7 @@ impl CITrait for TravisCI{
 impl TravisCI{
 
     #[allow(unused_assignments)]
-    pub fn get_repo_pub_key(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, crypto_state: &mut PKey) -> Result<(), String>{
+    pub fn get_repo_pub_key(&mut self, term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, crypto_state: &mut PKey) -> Result<(), TrelloBSTError>{
 
         //Get repos.
         let     status   = utils::StatusPrint::from_str(term, "Acquiring the repo list from Travis-CI.");
@@ -285,7 +282,7 @@ impl TravisCI{
 
             let hook: Hook;
             if option == 0 {
-                exit(0);
+                exit(TrelloBSTError::UserAbort.exit_code());
             } else {
                 hook = hooks.hooks[option - 1].clone();
             }
@@ -310,7 +307,7 @@ impl TravisCI{
                     }
                     Err(err)           => {
                         status.error(term);
-                        return Err(format!("There was an error linking the {} to travis-CI: {}", hook.name, err));
+                        return Err(err.context(format!("There was an error linking the {} to travis-CI", hook.name)));
                     }
                 }
             }
@@ -330,15 +327,15 @@ impl TravisCI{
                 Err(err)           => {
                     status.error(term);
                     is_api_call_success = false;
-                    return Err(format!("There was an error getting the public encryption key for {}: {}", repo_tag, err));
+                    return Err(err.context(format!("There was an error getting the public encryption key for {}", repo_tag)));
                 }
             };
 
             let mut repo_response: RepoResponse = match serde_json::from_str(&response_body) {
                 Ok(response) => response,
-                Err(_)       => {
+                Err(err)     => {
                     is_api_call_success = false;
-                    return Err("There was an error parsing the api response.".to_string());
+                    return Err(TrelloBSTError::parse("the repo's public key", err));
                 }
             };
 
@@ -356,7 +353,7 @@ impl TravisCI{
                         match err {
                             SslError::OpenSslErrors(err) => {
                                 for errs in err.iter() {
-                                    return Err(format!("There was an error parsing the encryption key: {:?}", errs));
+                                    return Err(TrelloBSTError::parse("the encryption key", format!("{:?}", errs)));
                                 }
                             }
                             _ => ()
@@ -368,30 +365,19 @@ impl TravisCI{
         }
     }
 
-    pub fn encrypt_vars(&mut self, config: &mut config::TrelloBSTConfig, crypto_state: &mut PKey) -> TravisEncryptedVars{
-
-        //Get config values
-        let trello_api_token = config.get("trello_api_token");
-        let list_id          = config.get("trello_list_id");
-        let build_pass_id    = config.get("trello_label_pass_id");
-        let build_fail_id    = config.get("trello_label_fail_id");
-
-        //Create environment variables
-        let trello_api_token_env_var = format!("TRELLO_API_TOKEN={}",         trello_api_token);
-        let list_id_env_var          = format!("TRELLO_API_LIST_ID={}",       list_id);
-        let build_pass_id_env_var    = format!("TRELLO_API_BUILD_PASS_ID={}", build_pass_id);
-        let build_fail_id_env_var    = format!("TRELLO_API_BUILD_FAIL_ID={}", build_fail_id);
+    pub fn encrypt_vars(&mut self, config: &mut config::TrelloBSTConfig, crypto_state: &mut PKey) -> Vec<String> {
 
         //Encrypt environment variables
-        TravisEncryptedVars {
-            trello_api_token: crypto_state.public_encrypt_with_padding(&trello_api_token_env_var.into_bytes(), EncryptionPadding::PKCS1v15).to_base64(base64::STANDARD),
-            list_id:          crypto_state.public_encrypt_with_padding(&list_id_env_var.into_bytes(),          EncryptionPadding::PKCS1v15).to_base64(base64::STANDARD),
-            build_pass_id:    crypto_state.public_encrypt_with_padding(&build_pass_id_env_var.into_bytes(),    EncryptionPadding::PKCS1v15).to_base64(base64::STANDARD),
-            build_fail_id:    crypto_state.public_encrypt_with_padding(&build_fail_id_env_var.into_bytes(),    EncryptionPadding::PKCS1v15).to_base64(base64::STANDARD)
+        let mut encrypted_vars = Vec::new();
+        for (name, value) in ci::trello_env_vars(config) {
+            let env_var = format!("{}={}", name, value);
+            encrypted_vars.push(crypto_state.public_encrypt_with_padding(&env_var.into_bytes(), EncryptionPadding::PKCS1v15).to_base64(base64::STANDARD));
         }
+
+        encrypted_vars
     }
 
-    pub fn acquire_hooks(&mut self, config: &mut config::TrelloBSTConfig) -> Result<ParsedHooksResponse, String> {
+    pub fn acquire_hooks(&mut self, config: &mut config::TrelloBSTConfig) -> Result<ParsedHooksResponse, TrelloBSTError> {
 
         let     api_call      = format!("https://api.travis-ci.org/hooks");
         let     auth          = format!("token {}", config.get("travis_access_token"));
@@ -23,19 +23,21 @@
     SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
-//TODO: put api calling in a function or macro
-
+use std::collections::BTreeMap;
 use std::io;
+use std::path::Path;
 
+use authorize;
 use config;
+use custom_fields::{self, BuildField};
+use error::TrelloBSTError;
+use members;
+use status::{BuildStatus, ListStage};
+use trello_client::{Board, Label, List, TrelloClient};
+use utils;
 
 extern crate term;
 
-use serde_json::Value;
-
-
-mod utils;
-
 
 ////////////////////////////////////////////////////////////
 //                         Macros                         //
@@ -45,53 +47,40 @@ include!("utils_macros.rs");
 
 
 ////////////////////////////////////////////////////////////
-//                        Structs                         //
+//                       Constants                        //
 ////////////////////////////////////////////////////////////
 
-#[derive(Deserialize)]
-pub struct BoardNameID {
-    pub name: String,
-    pub id:   String
-}
+//Label colours accepted by Trello, "none" makes a colourless label.
+pub const LABEL_COLORS: [&'static str; 11] = ["green", "yellow", "orange", "red", "pink", "purple", "blue", "sky", "lime", "black", "none"];
 
-//response to the /1/boards/[idBoard] api call.
-#[derive(Deserialize)]
-pub struct MembersMeBoardsResponse {
-    pub id:     String,
-    pub boards: Vec<BoardNameID>,
-}
 
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
 
-#[derive(Deserialize)]
-pub struct ListInfo {
-    pub id:   String,
-    pub name: String
-}
-
-//response to the /1/boards api call.
-#[derive(Deserialize)]
-pub struct BoardsResponse {
-    pub id:    String,
-    pub name:  String,
-    pub desc:  String,
-    pub lists: Vec<ListInfo>
-}
-
-pub struct LabelInfo {
-    pub id:    String,
-    pub name:  String,
-    pub color: String
+#[derive(Clone, Copy)]
+pub enum LabelSelection {
+    Existing(usize),
+    Create,
+    Skip
 }
 
-pub struct BoardsLabelsResponse {
-    pub id:     String,
-    pub labels: Vec<LabelInfo>
+#[derive(Clone, Copy)]
+pub enum FieldSelection {
+    Existing(usize),
+    Create,
+    Skip
 }
 
 pub struct Trello {
     is_board_created: bool
 }
 
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
 impl Trello {
 
     pub fn new() -> Trello {
@@ -100,7 +89,8 @@ impl Trello {
         }
     }
 
-    pub fn setup_api_token(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) {
+    //The token's expiration and scopes are asked unless token_request is given.
+    pub fn setup_api_token(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig, token_request: Option<authorize::TokenRequest>) {
 
         let trello_api_token_config_key = "trello_api_token";
         let trello_api_token            = config.get(&trello_api_token_config_key);
@@ -109,7 +99,35 @@ impl Trello {
         if trello_api_token.is_empty() {
             let mut api_token = String::new();
             println!("Setting up Trello API Token...");
-            get_input_string!(term, &mut api_token, "Log in to Trello.com and enter the app token from https://trello.com/1/authorize?response_type=token&key={}&scope=read%2Cwrite&expiration=never&name=TrelloBST : ", trello_api_key);
+
+            let token_request = match token_request {
+                Some(token_request) => token_request,
+                None                => Trello::select_token_request(term)
+            };
+
+            let mut login_select: utils::MenuBuilder<bool> = utils::MenuBuilder::new("How do you want to get the Trello API token?".to_string());
+            login_select.add_entry("Log in to Trello in the browser, TrelloBST gets the token by itself.".to_string(), true);
+            login_select.add_entry("Open the authorization page myself and paste the token.".to_string(), false);
+
+            if *login_select.select(term) {
+                match Trello::capture_api_token(term, trello_api_key, &token_request) {
+                    Ok(token) => api_token = token,
+                    Err(err)  => {
+                        writeln_red!(term, "{}", err);
+                        writeln_red!(term, "Falling back to pasting the token.");
+                    }
+                }
+            }
+
+            //Paste the token, copied tokens often come with stray spaces.
+            while api_token.is_empty() {
+                get_input_string!(term, &mut api_token, "Log in to Trello.com and enter the app token from {} : ", authorize::authorize_url(trello_api_key, &token_request, Option::None));
+                api_token = api_token.trim().to_string();
+                if !api_token.is_empty() && !Trello::validate_api_token(term, trello_api_key, &api_token) {
+                    api_token.clear();
+                }
+            }
+
             config.set(&trello_api_token_config_key, &api_token);
 
             //Save config
@@ -118,395 +136,439 @@ impl Trello {
                 Ok(())   => {status.success(term);},
                 Err(err) => {
                     status.error(term);
-                    writeln_red!(term, "Error: Failed to save the configuration file: {}, TrelloBST will continue without saving inputted values into the configuration file.", err);
+                    writeln_red!(term, "{}", err);
                 }
             }
         }
     }
 
-    #[allow(unused_assignments)]
-    pub fn setup_board(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), &'static str> {
+    //Asks how long the token lasts and what it can access.
+    pub fn select_token_request(term: &mut Box<term::StdoutTerminal>) -> authorize::TokenRequest {
 
-        //Get list of boards
-        let status                      = utils::StatusPrint::from_str(term, "Acquiring board list from Trello.");
-        let trello_api_token_config_key = "trello_api_token";
-        let api_call                    = format!("https://api.trello.com/1/members/me?fields=&boards=open&board_fields=name&key={}&token={}", trello_api_key, config.get(trello_api_token_config_key));
+        let mut expiration_select: utils::MenuBuilder<&'static str> = utils::MenuBuilder::new("When should the token expire?".to_string());
+        expiration_select.add_entry("Never, the CI keeps working until the token is revoked.".to_string(), "never");
+        expiration_select.add_entry("In 30 days.".to_string(), "30days");
+        expiration_select.add_entry("In 1 day.".to_string(), "1day");
+        let expiration = *expiration_select.select(term);
 
-        //  Do API call
-        let response_body = match utils::rest_api_call_get(&api_call) {
-            Ok(response_body) => response_body,
-            Err(err)          => {
+        let mut scope_select: utils::MenuBuilder<&'static str> = utils::MenuBuilder::new("What should the token give access to?".to_string());
+        scope_select.add_entry("Read and write the boards, needed to push build statuses.".to_string(), "read,write");
+        scope_select.add_entry("Read and write the boards and the account.".to_string(), "read,write,account");
+        let scopes = *scope_select.select(term);
+
+        //NOTE: The menu entries are all valid.
+        authorize::TokenRequest::parse(expiration, scopes).unwrap()
+    }
+
+    //Opens the authorize page in the browser and gets the token back through a listener on 127.0.0.1.
+    pub fn capture_api_token(term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, token_request: &authorize::TokenRequest) -> Result<String, TrelloBSTError> {
+
+        let listener      = try!(authorize::TokenListener::bind());
+        let authorize_url = authorize::authorize_url(trello_api_key, token_request, Option::Some(&listener.return_url()));
+
+        if !authorize::open_browser(&authorize_url) {
+            writeln_red!(term, "Failed to open the browser, please open this page yourself:");
+        }
+        println!("Waiting for the authorization from {}", authorize_url);
+
+        let token = try!(listener.wait_for_token(300));
+        if !Trello::validate_api_token(term, trello_api_key, &token) {
+            return Err(TrelloBSTError::Validation("Error: The token received from the browser was refused by Trello.".to_string()));
+        }
+
+        Ok(token)
+    }
+
+    //Checks the token with /1/members/me, a token that can't be checked (ex: no network) is accepted.
+    pub fn validate_api_token(term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, api_token: &str) -> bool {
+
+        let status = utils::StatusPrint::from_str(term, "Validating the token.");
+        match TrelloClient::new(trello_api_key, api_token).me() {
+            Ok(member) => {
+                status.success(term);
+                println!("Logged in as @{}.", member.username);
+                true
+            }
+            Err(err) => {
                 status.error(term);
-                return Err(err);
+                writeln_red!(term, "{}", err);
+                match *err.root() {
+                    TrelloBSTError::Api {..}                                                  => false,
+                    TrelloBSTError::HttpStatus {status, ..} if status == 400 || status == 401 => false,
+                    _                                                                         => true
+                }
             }
-        };
+        }
+    }
+
+    pub fn setup_board(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
+
+        let client = client(trello_api_key, config);
 
-        //  Parse JSON data
-        let board_list: MembersMeBoardsResponse = match serde_json::from_str(&response_body){
-            Ok(board_list) => board_list,
-            Err(_)         => {
+        //Get list of boards
+        let status = utils::StatusPrint::from_str(term, "Acquiring board list from Trello.");
+        let boards: Vec<Board> = match client.my_open_boards() {
+            Ok(boards) => boards,
+            Err(err)   => {
                 status.error(term);
-                return Err("Error parsing the response.");
+                return Err(err);
             }
         };
         status.success(term);
 
 
         //Select Board.
-        let mut board_select: utils::MenuBuilder<u64> = utils::MenuBuilder::new("Which board do you want to setup?".to_string());
-        let mut counter:      u64                     = 1;
-        let mut create_board: bool                    = false;
-
-        //  Create menu
-        for i in 0..board_list.boards.len() {
-            board_select.add_entry(board_list.boards[i].name.clone(), i as u64 + 1);
-            counter += 1;
+        let mut board_select: utils::MenuBuilder<Option<usize>> = utils::MenuBuilder::new("Which board do you want to setup?".to_string());
+        for i in 0..boards.len() {
+            board_select.add_entry(boards[i].name.clone(), Option::Some(i));
         }
-        board_select.add_entry_color(term::color::GREEN, "Create a new board.".to_string(), counter);
+        board_select.add_entry_color(term::color::GREEN, "Create a new board.".to_string(), Option::None);
 
-        //  Select
-        let board_selected = board_select.select(term);
-        if *board_selected == counter {
-            create_board = true;
-        } else {
-            let index = (*board_selected - 1) as usize;
-            create_board = false;
-            config.set("trello_board_id", &board_list.boards[index].id.clone()[..]);
+        if let Some(index) = *board_select.select(term) {
+            config.set("trello_board_id", &boards[index].id);
+            return Ok(());
         }
 
 
         //Create board if wanted.
-        if create_board {
-
-            //Get new board name
-            let mut board_name                  = String::new();
-            let mut is_input_success: bool      = false;
-            loop {
-                get_input_string_success!(term, &mut board_name, &mut is_input_success, "Please enter a name for the new board: ");
-                if is_input_success {break;}
-            }
-
-            //Create board
-            let     status        = utils::StatusPrint::from_str(term, "Creating new board.");
-            let     api_call      = format!("https://trello.com/1/boards?name={}&defaultLists=false&key={}&token={}", board_name, trello_api_key, config.get(trello_api_token_config_key));
-
-            //  Do API call
-            let response_body = match utils::rest_api_call_post(&api_call) {
-                Ok(response) => response,
-                Err(err)     => {
-                    status.error(term);
-                    return Err(err);
-                }
-            };
+        let mut board_name       = String::new();
+        let mut is_input_success = false;
+        loop {
+            get_input_string_success!(term, &mut board_name, &mut is_input_success, "Please enter a name for the new board: ");
+            if is_input_success {break;}
+        }
 
-            //Get ID of the new board
-            match utils::get_single_json_value_as_string(&response_body, "id") {
-                Ok(value) => {config.set("trello_board_id", &value[..]);},
-                Err(err)  => {
-                    status.error(term);
-                    return Err(err)
-                }
+        let status = utils::StatusPrint::from_str(term, "Creating new board.");
+        match client.create_board(&board_name, false, true) {
+            Ok(board) => {
+                config.set("trello_board_id", &board.id);
+                status.success(term);
+            }
+            Err(err) => {
+                status.error(term);
+                return Err(err);
             }
-            self.is_board_created = true;
-            status.success(term);
         }
+
+        self.is_board_created = true;
         Ok(())
     }
 
-    pub fn setup_list(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), &'static str> {
-
-        //Acquire board list and select a list if board wasnt just created
-        let     trello_api_token_config_key = "trello_api_token";
-        let mut create_list                 = false;
-        if !self.is_board_created {
-
-            //Acquire board list
-            let     status           = utils::StatusPrint::from_str(term, "Acquiring board's lists list from Trello.");
-            let     api_call         = format!("https://api.trello.com/1/boards/{}?lists=open&list_fields=name&fields=name,desc&key={}&token={}", config.get("trello_board_id"), trello_api_key, config.get(trello_api_token_config_key));
+    pub fn setup_list(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
 
-            let response_body = match utils::rest_api_call_get(&api_call) {
-                Ok(response_body) => response_body,
-                Err(err)           => {
-                    status.error(term);
-                    return Err(err)
-                }
-            };
+        let client = client(trello_api_key, config);
 
-            let board_lists_list: BoardsResponse = match serde_json::from_str(&response_body){
-                Ok(board_lists_list) => board_lists_list,
-                Err(_)               => {
+        //Acquire board list if board wasnt just created
+        let mut board_lists = Vec::new();
+        if !self.is_board_created {
+            let status = utils::StatusPrint::from_str(term, "Acquiring board's lists list from Trello.");
+            board_lists = match client.board_lists(&config.get("trello_board_id")) {
+                Ok(board_lists) => board_lists,
+                Err(err)        => {
                     status.error(term);
-                    return Err("Error parsing the response.");
+                    return Err(err);
                 }
             };
-
             status.success(term);
+        }
 
-            //Select board list
-            let mut board_list_select: utils::MenuBuilder<u64> = utils::MenuBuilder::new("Which board list do you want to use for the build statuses?".to_string());
-            let mut counter:           u64                     = 1;
-
-            for i in 0..board_lists_list.lists.len() {
-                board_list_select.add_entry(board_lists_list.lists[i].name.clone(), i as u64 + 1);
-                counter += 1;
-            }
-
-            board_list_select.add_entry_color(term::color::GREEN, "Create a new list.".to_string(), counter);
+        //Select a single list or a list per build stage
+        let mut layout_select: utils::MenuBuilder<bool> = utils::MenuBuilder::new("How do you want the build statuses to be organized?".to_string());
+        layout_select.add_entry("All build statuses in a single list.".to_string(), false);
+        layout_select.add_entry("A list per build stage, cards are moved from the running builds list to the passed or failed builds list.".to_string(), true);
 
-            let board_list_selected = board_list_select.select(term);
-            if *board_list_selected == counter {
-                create_list = true;
-            } else {
-                let index = (*board_list_selected - 1) as usize;
-                config.set("trello_list_id", &board_lists_list.lists[index].id.clone()[..]);
+        if *layout_select.select(term) {
+            for stage in ListStage::all() {
+                let list_id = try!(Trello::select_list(term, &client, config, &mut board_lists, &format!("Which board list do you want to use for the {} builds?", stage.name())));
+                config.set(&stage.config_key(), &list_id[..]);
             }
+            config.set("trello_list_id", "");
         } else {
-            create_list = true;
+            let list_id = try!(Trello::select_list(term, &client, config, &mut board_lists, "Which board list do you want to use for the build statuses?"));
+            config.set("trello_list_id", &list_id[..]);
+            for stage in ListStage::all() {
+                config.set(&stage.config_key(), "");
+            }
         }
 
+        Ok(())
+    }
 
-        //create board if wanted
-        if create_list {
-            let mut list_name        = String::new();
-            let mut is_input_success = false;
-            loop {
-                get_input_string_success!(term, &mut list_name, &mut is_input_success, "Please enter a name for the list which will contain the build statuses: ");
-                if is_input_success {break;}
-            }
-
-            let     trello_api_token_config_key = "trello_api_token";
-            let     api_call                    = format!("https://trello.com/1/lists?name={}&idBoard={}&defaultLists=false&key={}&token={}", list_name, config.get("trello_board_id"), trello_api_key, config.get(trello_api_token_config_key));
-            let     status                      = utils::StatusPrint::from_str(term, "Creating the list.");
+    //Selects a list or creates a new one, created lists are added to board_lists.
+    pub fn select_list(term: &mut Box<term::StdoutTerminal>, client: &TrelloClient, config: &mut config::TrelloBSTConfig, board_lists: &mut Vec<List>, message: &str) -> Result<String, TrelloBSTError> {
 
-            let response_body = match utils::rest_api_call_post(&api_call) {
-                Ok(response_body) => response_body,
-                Err(err)          => {
-                    status.error(term);
-                    return Err(err);
-                }
-            };
+        //Select board list
+        let mut board_list_select: utils::MenuBuilder<Option<usize>> = utils::MenuBuilder::new(message.to_string());
+        for i in 0..board_lists.len() {
+            board_list_select.add_entry(board_lists[i].name.clone(), Option::Some(i));
+        }
+        board_list_select.add_entry_color(term::color::GREEN, "Create a new list.".to_string(), Option::None);
 
-            match utils::get_single_json_value_as_string(&response_body, "id") {
-                Ok(value) => config.set("trello_list_id", &value[..]),
-                Err(err)  => {
-                    status.error(term);
-                    return Err(err);
-                }
-            }
-            status.success(term);
+        if let Some(index) = *board_list_select.select(term) {
+            return Ok(board_lists[index].id.clone());
         }
 
-        Ok(())
-    }
+        //create list
+        let mut list_name        = String::new();
+        let mut is_input_success = false;
+        loop {
+            get_input_string_success!(term, &mut list_name, &mut is_input_success, "Please enter a name for the new list: ");
+            if is_input_success {break;}
+        }
 
+        let status = utils::StatusPrint::from_str(term, "Creating the list.");
+        let list   = match client.create_list(&config.get("trello_board_id"), &list_name) {
+            Ok(list) => list,
+            Err(err) => {
+                status.error(term);
+                return Err(err);
+            }
+        };
+        status.success(term);
 
-    pub fn setup_labels(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), &'static str> {
+        let list_id = list.id.clone();
+        board_lists.push(list);
 
+        Ok(list_id)
+    }
 
-        //Acquire board labels and select ones to be used if board was not just created.
-        let     trello_api_token_config_key = "trello_api_token";
-        if !self.is_board_created {
 
-            //Acquire label list
-            let     api_call         = format!("https://api.trello.com/1/boards/{}?labels=all&label_fields=name,color&fields=none&key={}&token={}", config.get("trello_board_id"), trello_api_key, config.get(trello_api_token_config_key));
-            let     status           = utils::StatusPrint::from_str(term, "Acquiring board's labels from Trello.");
+    pub fn setup_labels(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
 
-            let response_body = match utils::rest_api_call_get(&api_call) {
-                Ok(response_body) => response_body,
-                Err(err)          => {
-                    status.error(term);
-                    return Err(err)
-                }
-            };
+        let client = client(trello_api_key, config);
 
-            let board_labels: BoardsLabelsResponse = match BoardsLabelsResponse::from_json(&response_body) {
-                Ok(labels) => labels,
-                Err(err)   => {
+        //Acquire board labels if board was not just created.
+        let mut board_labels = Vec::new();
+        if !self.is_board_created {
+            let status = utils::StatusPrint::from_str(term, "Acquiring board's labels from Trello.");
+            board_labels = match client.board_labels(&config.get("trello_board_id")) {
+                Ok(board_labels) => board_labels,
+                Err(err)         => {
                     status.error(term);
-                    return Err(err)
+                    return Err(err);
                 }
             };
-
             status.success(term);
+        }
 
-            //Select labels
-            let mut label_select: utils::MenuBuilder<u64> = utils::MenuBuilder::new(String::new());
-            let mut counter:      u64                     = 1;
-
-            for i in 0..board_labels.labels.len() {
-                label_select.add_entry(format!(" ({}) {}", board_labels.labels[i].color.clone().to_uppercase(), board_labels.labels[i].name.clone()), i as u64 + 1);
-                counter += 1;
-            }
-
-            label_select.add_entry_color(term::color::GREEN, "Create a new board.".to_string(), counter);
-
-            //  Select pass label and create if returned true
-            if Trello::select_label(term, config, &board_labels, &mut label_select, "pass", &counter) {
-                //create
-                match Trello::create_label(term, config, &trello_api_key, "pass") {
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
+        //Select or create a label for every build status
+        for build_status in BuildStatus::all() {
+            match Trello::select_label(term, &board_labels, build_status) {
+                LabelSelection::Existing(index) => {
+                    config.set(&build_status.config_key(), &board_labels[index].id.clone()[..]);
                 }
-            }
-
-            //  Select fail label and create if returned true
-            if Trello::select_label(term, config, &board_labels, &mut label_select, "fail", &counter) {
-                //create
-                match Trello::create_label(term, config, &trello_api_key, "fail") {
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
+                LabelSelection::Create => {
+                    try!(Trello::create_label(term, &client, config, build_status));
+                }
+                LabelSelection::Skip => {
+                    config.set(&build_status.config_key(), "");
                 }
-            }
-
-        } else {
-
-            //Create pass label
-            match Trello::create_label(term, config, &trello_api_key, "pass") {
-                Ok(()) => (),
-                Err(err) => return Err(err)
-            }
-
-            //Create fail label
-            match Trello::create_label(term, config, &trello_api_key, "fail") {
-                Ok(()) => (),
-                Err(err) => return Err(err)
             }
         }
+
         Ok(())
     }
 
-    pub fn select_label(term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, board_labels: &BoardsLabelsResponse, menu_builder: &mut utils::MenuBuilder<u64>, pass_fail_txt: &str, counter: &u64) -> bool {
+    pub fn select_label(term: &mut Box<term::StdoutTerminal>, board_labels: &Vec<Label>, build_status: BuildStatus) -> LabelSelection {
 
-        println!("\nWhich label do you want to use for the build {} status?", &pass_fail_txt);
+        let mut label_select: utils::MenuBuilder<LabelSelection> = utils::MenuBuilder::new(format!("\nWhich label do you want to use for the build {} status?", build_status.name()));
 
-        //Select label
-        let     label_selected = menu_builder.select(term);
-        let mut create_label   = false;
-        if *label_selected == *counter {
-            create_label = true;
-        } else {
-            let index = (*label_selected - 1) as usize;
-            config.set(&format!("trello_label_{}_id", &pass_fail_txt)[..], &board_labels.labels[index].id.clone()[..]);
+        for i in 0..board_labels.len() {
+            label_select.add_entry(format!(" ({}) {}", board_labels[i].color_name().to_uppercase(), board_labels[i].name.clone()), LabelSelection::Existing(i));
         }
-        return create_label;
+
+        label_select.add_entry_color(term::color::GREEN, "Create a new label.".to_string(), LabelSelection::Create);
+
+        if !build_status.is_required() {
+            label_select.add_entry("Don't use a label for this build status.".to_string(), LabelSelection::Skip);
+        }
+
+        *label_select.select(term)
     }
 
-    pub fn create_label(term: &mut Box<term::StdoutTerminal>, config: &mut config::TrelloBSTConfig, trello_api_key: &str, pass_fail_txt: &str) -> Result<(), &'static str> {
+    pub fn create_label(term: &mut Box<term::StdoutTerminal>, client: &TrelloClient, config: &mut config::TrelloBSTConfig, build_status: BuildStatus) -> Result<(), TrelloBSTError> {
 
         let mut label_name       = String::new();
         let mut label_color      = String::new();
-        let     valid_colors     = ["green", "yellow", "orange", "red", "pink", "purple", "blue", "sky", "lime", "black", "none"];
         let mut is_input_success = false;
 
         //Get label name and color
         loop {
-            get_input_string_success!(term, &mut label_name, &mut is_input_success, "Please enter a name for the label which will be in the build passed status: ");
+            get_input_string_success!(term, &mut label_name, &mut is_input_success, "Please enter a name for the label which will be in the build {} status: ", build_status.name());
             if is_input_success {break;}
         }
 
         loop {
-            get_input_string_success!(term, &mut label_color, &mut is_input_success, "Please enter the color for the label which will be in the build passed status (Options are: Green, Yellow, Orange, Red, Pink, Purple, Blue, Sky, Lime, Black): ");
+            get_input_string_success!(term, &mut label_color, &mut is_input_success, "Please enter the color for the label which will be in the build {} status (Options are: Green, Yellow, Orange, Red, Pink, Purple, Blue, Sky, Lime, Black, None): ", build_status.name());
             if is_input_success {
                 label_color = label_color.to_lowercase();
-                if valid_colors.contains(&&label_color[..]) {break;}
+                if LABEL_COLORS.contains(&&label_color[..]) {break;}
                 writeln_red!(term, "Please enter a valid color.");
             }
         }
 
         //Create label.
-        let trello_api_token_config_key = "trello_api_token";
-        let api_call                    = format!("https://trello.com/1/board/{}/labels?name={}&color={}&key={}&token={}", config.get("trello_board_id"), label_name, label_color, trello_api_key, config.get(trello_api_token_config_key));
-        let status                      = utils::StatusPrint::from_str(term, "Creating the label.");
-
-        let response_body = match utils::rest_api_call_post(&api_call) {
-            Ok(response_body) => response_body,
-            Err(err)          => {
-                status.error(term);
-                return Err(err);
+        let status = utils::StatusPrint::from_str(term, "Creating the label.");
+        match client.create_label(&config.get("trello_board_id"), &label_name, &label_color) {
+            Ok(label) => {
+                config.set(&build_status.config_key(), &label.id[..]);
+                status.success(term);
+                Ok(())
             }
-        };
-
-        match utils::get_single_json_value_as_string(&response_body, "id") {
-            Ok(value) => {config.set(&format!("trello_build_{}_id", &pass_fail_txt)[..], &value[..]);},
-            Err(err)  => {
+            Err(err) => {
                 status.error(term);
-                return Err(err);
+                Err(err)
             }
         }
-
-        status.success(term);
-        Ok(())
     }
-}
 
-////////////////////////////////////////////////////////////
-//                         Impls                          //
-////////////////////////////////////////////////////////////
 
-impl BoardsLabelsResponse {
+    //Selects or creates the custom fields the build metadata is written into.
+    pub fn setup_custom_fields(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> Result<(), TrelloBSTError> {
+
+        let mut fields_select: utils::MenuBuilder<bool> = utils::MenuBuilder::new("Do you want the build number, commit, duration and CI provider in custom fields? (Needs the Custom Fields Power-Up on the board)".to_string());
+        fields_select.add_entry("No.".to_string(), false);
+        fields_select.add_entry("Yes.".to_string(), true);
 
-    pub fn new() -> BoardsLabelsResponse {
-        BoardsLabelsResponse{
-            id:     String::new(),
-            labels: Vec::new(),
+        if !*fields_select.select(term) {
+            for field in BuildField::all() {
+                config.set(&field.config_key(), "");
+            }
+            return Ok(());
         }
-    }
 
-    pub fn from_json(json_data: &String) -> Result<BoardsLabelsResponse, &'static str> {
+        //Acquire board custom fields if board was not just created.
+        let     trello_api_token_config_key = "trello_api_token";
+        let     trello_api_token            = config.get(trello_api_token_config_key);
+        let     board_id                    = config.get("trello_board_id");
+        let mut board_fields                = Vec::new();
+        if !self.is_board_created {
+            let status = utils::StatusPrint::from_str(term, "Acquiring board's custom fields from Trello.");
+            board_fields = match custom_fields::board_fields(trello_api_key, &trello_api_token, &board_id) {
+                Ok(board_fields) => board_fields,
+                Err(err)         => {
+                    status.error(term);
+                    return Err(err);
+                }
+            };
+            status.success(term);
+        }
 
-        let mut tmp_boards_labels_response = BoardsLabelsResponse::new();
+        //Select or create a custom field for every build field
+        for field in BuildField::all() {
 
-        //Parse
-        let data: Value = match serde_json::from_str(&json_data){
-            Ok(data) => data,
-            Err(_)   => return Err("Error parsing the JSON data")
-        };
+            let mut field_select: utils::MenuBuilder<FieldSelection> = utils::MenuBuilder::new(format!("\nWhich custom field do you want to use for the {}?", field.name()));
+            for i in 0..board_fields.len() {
+                if board_fields[i].field_type == field.field_type() {
+                    field_select.add_entry(board_fields[i].name.clone(), FieldSelection::Existing(i));
+                }
+            }
+            field_select.add_entry_color(term::color::GREEN, format!("Create a new \"{}\" field.", field.name()), FieldSelection::Create);
+            field_select.add_entry("Don't use a custom field for this.".to_string(), FieldSelection::Skip);
+
+            match *field_select.select(term) {
+                FieldSelection::Existing(index) => {
+                    config.set(&field.config_key(), &board_fields[index].id.clone()[..]);
+                }
+                FieldSelection::Create => {
+                    let mut field_name = String::new();
+                    get_input_string!(term, &mut field_name, "Please enter a name for the new field (leave empty for \"{}\"): ", field.name());
+                    if field_name.trim().is_empty() {
+                        field_name = field.name().to_string();
+                    }
+
+                    let status = utils::StatusPrint::from_str(term, "Creating the custom field.");
+                    match custom_fields::create_field(trello_api_key, &trello_api_token, &board_id, field_name.trim(), field) {
+                        Ok(field_info) => {
+                            status.success(term);
+                            config.set(&field.config_key(), &field_info.id[..]);
+                            board_fields.push(field_info);
+                        }
+                        Err(err) => {
+                            status.error(term);
+                            return Err(err);
+                        }
+                    }
+                }
+                FieldSelection::Skip => {
+                    config.set(&field.config_key(), "");
+                }
+            }
+        }
 
-        //Get JSON object
-        let object = try!(data.as_object().ok_or("Error: JSON data does not describe an object.")).clone();
+        Ok(())
+    }
 
-        //Get "id" field
-        let id_value: Value = try!(object.get("id").ok_or("Error: The \"id\" field has not been found in the JSON data.")).clone();
 
-        //Get "id" value.
-        tmp_boards_labels_response.id = try!(id_value.as_str().ok_or("Error: The \"id\" field does not describe a string value.")).to_string().clone();
+    //Builds the git author email to board member mapping used by "push --assign-author".
+    pub fn setup_members(&mut self, term: &mut Box<term::StdoutTerminal>, trello_api_key: &str, config: &mut config::TrelloBSTConfig, members_file: &str) -> Result<(), TrelloBSTError> {
 
-        //Get "labels" field
-        let labels_value: Value = try!(object.get("labels").ok_or("Error: The \"labels\" field has not been found in the JSON data.")).clone();
+        let mut members_select: utils::MenuBuilder<bool> = utils::MenuBuilder::new("Do you want to map git authors to board members so failing builds can be assigned to their author?".to_string());
+        members_select.add_entry("No.".to_string(), false);
+        members_select.add_entry(format!("Yes, the mapping will be saved in {}.", members_file), true);
 
-        //Get "labels" content
-        let labels_array = try!(labels_value.as_array().ok_or("Error: The \"labels\" field does not describe an array.")).clone();
+        if !*members_select.select(term) {
+            return Ok(());
+        }
 
-        //For each label
-        for label in &labels_array {
+        //Acquire board members
+        let status        = utils::StatusPrint::from_str(term, "Acquiring board's members list from Trello.");
+        let board_members = match client(trello_api_key, config).board_members(&config.get("trello_board_id")) {
+            Ok(board_members) => board_members,
+            Err(err)          => {
+                status.error(term);
+                return Err(err);
+            }
+        };
+        status.success(term);
 
-            //Get label object
-            let label_object = try!(label.as_object().ok_or("Error: An entry in the \"labels\" field does not describe an object.")).clone();
+        //Start from the existing mapping if there is one
+        let mut mapping: BTreeMap<String, String> = if Path::new(members_file).is_file() {
+            try!(members::load_mapping(members_file))
+        } else {
+            BTreeMap::new()
+        };
 
-            //Get "id" field
-            let label_id_value: Value = try!(label_object.get("id").ok_or("Error: Failed to acquire the \"id\" field in a \"labels\" field.")).clone();
-            let label_id_string       = try!(label_id_value.as_str().ok_or("Error: Failed to convert the \"id\" field into a string.")).to_string().clone();
+        for member in &board_members {
+            let mut emails = String::new();
+            get_input_string!(term, &mut emails, "Git author emails of {} (@{}), separated by commas (leave empty to keep the current ones): ", member.full_name, member.username);
 
-            //Get "name" field
-            let label_name_value: Value = try!(label_object.get("name").ok_or("Error: Failed to acquire the \"name\" field in a \"labels\" field.")).clone();
-            let label_name_string       = try!(label_name_value.as_str().ok_or("Error: Failed to convert the value of the \"name\" field to a string.")).to_string().clone();
+            if emails.trim().is_empty() {
+                continue;
+            }
 
-            //Get "color" field, if null, color is none
-            let label_color_value: Value = try!(label_object.get("color").ok_or("Error: Failed to acquire the \"color\" field in a \"labels\" field.")).clone();
+            let emails: Vec<String> = emails.split(',').map(|email| email.trim().to_lowercase()).filter(|email| !email.is_empty()).collect();
 
-            let label_color_string: String;
-            if label_color_value.is_null() {
-                label_color_string = "none".to_string();
-            } else {
-                label_color_string = try!(label_color_value.as_str().ok_or("Error: Failed to convert the value of the \"color\" field to a string.")).to_string().clone();
+            //Replace the member's previous emails
+            let username = member.username.clone();
+            let previous: Vec<String> = mapping.iter().filter(|&(_, val)| *val == username || *val == member.id).map(|(key, _)| key.clone()).collect();
+            for email in previous {
+                mapping.remove(&email);
+            }
+            for email in emails {
+                mapping.insert(email, username.clone());
             }
+        }
 
-            tmp_boards_labels_response.labels.push(LabelInfo{
-                id:    label_id_string,
-                name:  label_name_string,
-                color: label_color_string
-            });
+        let status = utils::StatusPrint::from_string(term, format!("Saving the members mapping to {}.", members_file));
+        match members::save_mapping(members_file, &mapping) {
+            Ok(())   => status.success(term),
+            Err(err) => {
+                status.error(term);
+                return Err(err);
+            }
         }
-        Ok(tmp_boards_labels_response)
+
+        Ok(())
     }
 }
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+//Client for the token of the config, the token is only known once setup_api_token is done.
+fn client(trello_api_key: &str, config: &mut config::TrelloBSTConfig) -> TrelloClient {
+    TrelloClient::new(trello_api_key, &config.get("trello_api_token"))
+}
@@ -0,0 +1,584 @@
+/*
+    Copyright (c) 2015, Alex Frappier Lachapelle
+    All rights reserved.
+
+    Redistribution and use in source and binary forms, with or without
+    modification, are permitted provided that the following conditions are met:
+
+    1. Redistributions of source code must retain the above copyright notice, this
+       list of conditions and the following disclaimer.
+    2. Redistributions in binary form must reproduce the above copyright notice,
+       this list of conditions and the following disclaimer in the documentation
+       and/or other materials provided with the distribution.
+
+    THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+    ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+    WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+    DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE LIABLE FOR
+    ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+    (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+    LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+    ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+    (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+    SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+extern crate url;
+use self::url::form_urlencoded;
+
+use error::TrelloBSTError;
+use utils;
+
+
+////////////////////////////////////////////////////////////
+//                       Constants                        //
+////////////////////////////////////////////////////////////
+
+//The TRELLO_API_URL env var overrides it, ex: to point TrelloBST at a local mock server.
+const API_URL: &'static str = "https://api.trello.com/1";
+
+//Trello's largest page for the paged card listings.
+const PAGE_SIZE: usize = 1000;
+
+//Trello's largest limit for a board's labels, the default being 50.
+const MAX_LABELS: &'static str = "1000";
+
+
+////////////////////////////////////////////////////////////
+//                        Structs                         //
+////////////////////////////////////////////////////////////
+
+//Fields missing from a response (not asked for in "fields") are left to their default value.
+
+#[derive(Clone, Deserialize)]
+pub struct Board {
+    pub id:     String,
+    pub name:   String,
+    #[serde(default)]
+    pub closed: bool
+}
+
+#[derive(Clone, Deserialize)]
+pub struct List {
+    pub id:       String,
+    pub name:     String,
+    #[serde(default)]
+    pub closed:   bool,
+    #[serde(rename="idBoard", default)]
+    pub id_board: String
+}
+
+//Colourless labels have a null colour.
+#[derive(Clone, Deserialize)]
+pub struct Label {
+    pub id:       String,
+    #[serde(default)]
+    pub name:     String,
+    #[serde(default)]
+    pub color:    Option<String>,
+    #[serde(rename="idBoard", default)]
+    pub id_board: String
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Card {
+    pub id:                 String,
+    pub name:               String,
+    #[serde(default)]
+    pub desc:               String,
+    #[serde(rename="idList", default)]
+    pub id_list:            String,
+    #[serde(rename="idLabels", default)]
+    pub id_labels:          Vec<String>,
+    #[serde(default)]
+    pub closed:             bool,
+    //ex: "2016-10-18T05:36:28.123Z", these sort by date as strings.
+    #[serde(rename="dateLastActivity", default)]
+    pub date_last_activity: String,
+    //Position in the list, smaller is higher.
+    #[serde(default)]
+    pub pos:                f64
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Member {
+    pub id:        String,
+    #[serde(default)]
+    pub username:  String,
+    #[serde(rename="fullName", default)]
+    pub full_name: String
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Checklist {
+    pub id:      String,
+    pub name:    String,
+    #[serde(rename="idCard", default)]
+    pub id_card: String
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Attachment {
+    pub id:   String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub url:  String
+}
+
+//Null dateExpires for tokens that never expire.
+#[derive(Clone, Deserialize)]
+pub struct Token {
+    pub id:           String,
+    #[serde(default)]
+    pub identifier:   String,
+    #[serde(rename="idMember", default)]
+    pub id_member:    String,
+    #[serde(rename="dateCreated", default)]
+    pub date_created: String,
+    #[serde(rename="dateExpires", default)]
+    pub date_expires: Option<String>,
+    #[serde(default)]
+    pub permissions:  Vec<TokenPermission>
+}
+
+//ex: read and write access to every ("*") board.
+#[derive(Clone, Deserialize)]
+pub struct TokenPermission {
+    #[serde(rename="idModel", default)]
+    pub id_model:   String,
+    #[serde(rename="modelType", default)]
+    pub model_type: String,
+    #[serde(default)]
+    pub read:       bool,
+    #[serde(default)]
+    pub write:      bool
+}
+
+//What a created or updated card contains, see TrelloClient::create_card and TrelloClient::update_card.
+pub struct CardData {
+    pub list_id:    String,
+    pub name:       String,
+    pub desc:       String,
+    pub label_ids:  Vec<String>,
+    pub member_ids: Vec<String>
+}
+
+//Calls the Trello API with the developer key and a user token.
+//NOTE: Query values are percent-encoded here, callers pass names and urls as they are.
+#[derive(Clone)]
+pub struct TrelloClient {
+    api_key:  String,
+    token:    String,
+    base_url: String
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Impls                          //
+////////////////////////////////////////////////////////////
+
+impl Label {
+
+    //ex: "green", "none" for colourless labels as in the setup's colour choices.
+    pub fn color_name(&self) -> String {
+        match self.color {
+            Some(ref color) => color.clone(),
+            None            => "none".to_string()
+        }
+    }
+}
+
+impl TrelloClient {
+
+    //Calls https://api.trello.com/1 unless the TRELLO_API_URL env var is set.
+    pub fn new(api_key: &str, token: &str) -> TrelloClient {
+        let base_url = match env::var("TRELLO_API_URL") {
+            Ok(base_url) if !base_url.is_empty() => base_url,
+            _                                    => API_URL.to_string()
+        };
+        TrelloClient::with_base_url(api_key, token, &base_url)
+    }
+
+    //ex: "http://127.0.0.1:8080/1"
+    pub fn with_base_url(api_key: &str, token: &str, base_url: &str) -> TrelloClient {
+        TrelloClient {
+            api_key:  api_key.to_string(),
+            token:    token.to_string(),
+            base_url: base_url.trim_right_matches('/').to_string()
+        }
+    }
+
+
+    //Members
+
+    pub fn me(&self) -> Result<Member, TrelloBSTError> {
+        self.get("the member", "members/me", &[("fields", "username,fullName")])
+    }
+
+    pub fn my_open_boards(&self) -> Result<Vec<Board>, TrelloBSTError> {
+        self.get("the boards", "members/me/boards", &[("filter", "open"), ("fields", "name,closed")])
+    }
+
+
+    //Boards
+
+    pub fn board(&self, board_id: &str) -> Result<Board, TrelloBSTError> {
+        self.get("the board", &format!("boards/{}", encode(board_id)), &[("fields", "name,closed")])
+    }
+
+    //NOTE: New boards get Trello's default lists and labels unless they're turned off.
+    pub fn create_board(&self, name: &str, default_lists: bool, default_labels: bool) -> Result<Board, TrelloBSTError> {
+        self.post("the new board", "boards", &[("name", name), ("defaultLists", bool_str(default_lists)), ("defaultLabels", bool_str(default_labels))])
+    }
+
+    //Closed boards can be reopened from Trello.
+    pub fn close_board(&self, board_id: &str) -> Result<Board, TrelloBSTError> {
+        parse("the closed board", try!(utils::rest_api_call_put(&self.url(&format!("boards/{}", encode(board_id)), &[("closed", "true")]))))
+    }
+
+    //NOTE: Trello doesn't page a board's lists, labels or members, they come whole. Labels are the only ones with a
+    //      small default limit, hence MAX_LABELS.
+    pub fn board_lists(&self, board_id: &str) -> Result<Vec<List>, TrelloBSTError> {
+        self.get("the board's lists", &format!("boards/{}/lists", encode(board_id)), &[("filter", "open"), ("fields", "name,closed,idBoard")])
+    }
+
+    pub fn board_labels(&self, board_id: &str) -> Result<Vec<Label>, TrelloBSTError> {
+        self.get("the board's labels", &format!("boards/{}/labels", encode(board_id)), &[("fields", "name,color,idBoard"), ("limit", MAX_LABELS)])
+    }
+
+    pub fn board_members(&self, board_id: &str) -> Result<Vec<Member>, TrelloBSTError> {
+        self.get("the board's members", &format!("boards/{}/members", encode(board_id)), &[("fields", "username,fullName")])
+    }
+
+
+    //Lists
+
+    pub fn list(&self, list_id: &str) -> Result<List, TrelloBSTError> {
+        self.get("the list", &format!("lists/{}", encode(list_id)), &[("fields", "name,closed,idBoard")])
+    }
+
+    //The list is added at the bottom of the board.
+    pub fn create_list(&self, board_id: &str, name: &str) -> Result<List, TrelloBSTError> {
+        self.post("the new list", "lists", &[("name", name), ("idBoard", board_id), ("pos", "bottom")])
+    }
+
+    //Open cards of a list, in the list's order.
+    //NOTE: Lists of build cards can outgrow a page, the pages come by id so the cards are sorted back by position.
+    pub fn list_cards(&self, list_id: &str) -> Result<Vec<Card>, TrelloBSTError> {
+        let mut cards = try!(self.get_paginated("the list's cards", &format!("lists/{}/cards", encode(list_id)), &[("fields", "name,desc,idList,idLabels,closed,dateLastActivity,pos")], PAGE_SIZE, |card: &Card| card.id.clone()));
+        cards.sort_by(|a, b| a.pos.partial_cmp(&b.pos).unwrap_or(Ordering::Equal));
+        Ok(cards)
+    }
+
+
+    //Labels
+
+    pub fn label(&self, label_id: &str) -> Result<Label, TrelloBSTError> {
+        self.get("the label", &format!("labels/{}", encode(label_id)), &[("fields", "name,color,idBoard")])
+    }
+
+    pub fn create_label(&self, board_id: &str, name: &str, color: &str) -> Result<Label, TrelloBSTError> {
+        self.post("the new label", "labels", &[("name", name), ("color", color), ("idBoard", board_id)])
+    }
+
+
+    //Cards
+
+    pub fn card(&self, card_id: &str) -> Result<Card, TrelloBSTError> {
+        self.get("the card", &format!("cards/{}", encode(card_id)), &[("fields", "name,desc,idList,idLabels,closed,dateLastActivity")])
+    }
+
+    //Cards are added at the top of the list so the most recent builds come first.
+    pub fn create_card(&self, card_data: &CardData) -> Result<Card, TrelloBSTError> {
+        let json_body = try!(card_json(card_data));
+        parse("the new card", try!(utils::rest_api_call_post_json(&self.url("cards", &[]), &json_body)))
+    }
+
+    pub fn update_card(&self, card_id: &str, card_data: &CardData) -> Result<Card, TrelloBSTError> {
+        let json_body = try!(card_json(card_data));
+        parse("the updated card", try!(utils::rest_api_call_put_json(&self.url(&format!("cards/{}", encode(card_id)), &[]), &json_body)))
+    }
+
+    pub fn archive_card(&self, card_id: &str) -> Result<Card, TrelloBSTError> {
+        parse("the archived card", try!(utils::rest_api_call_put(&self.url(&format!("cards/{}", encode(card_id)), &[("closed", "true")]))))
+    }
+
+
+    //Attachments
+
+    pub fn attach_url(&self, card_id: &str, url: &str) -> Result<Attachment, TrelloBSTError> {
+        self.post("the attachment", &format!("cards/{}/attachments", encode(card_id)), &[("url", url)])
+    }
+
+    pub fn attach_file(&self, card_id: &str, file_name: &str, file_data: &[u8]) -> Result<Attachment, TrelloBSTError> {
+        parse("the attachment", try!(utils::rest_api_call_post_multipart(&self.url(&format!("cards/{}/attachments", encode(card_id)), &[]), "file", file_name, file_data)))
+    }
+
+    pub fn card_attachments(&self, card_id: &str) -> Result<Vec<Attachment>, TrelloBSTError> {
+        self.get("the card's attachments", &format!("cards/{}/attachments", encode(card_id)), &[("fields", "name,url")])
+    }
+
+    pub fn delete_attachment(&self, card_id: &str, attachment_id: &str) -> Result<(), TrelloBSTError> {
+        try!(utils::rest_api_call_delete(&self.url(&format!("cards/{}/attachments/{}", encode(card_id), encode(attachment_id)), &[])));
+        Ok(())
+    }
+
+
+    //Checklists
+
+    pub fn create_checklist(&self, card_id: &str, name: &str) -> Result<Checklist, TrelloBSTError> {
+        self.post("the new checklist", "checklists", &[("idCard", card_id), ("name", name)])
+    }
+
+    pub fn add_check_item(&self, checklist_id: &str, name: &str, checked: bool) -> Result<(), TrelloBSTError> {
+        try!(utils::rest_api_call_post(&self.url(&format!("checklists/{}/checkItems", encode(checklist_id)), &[("name", name), ("checked", bool_str(checked))])));
+        Ok(())
+    }
+
+
+    //Custom fields, kept as JSON since their value depends on the field type
+
+    pub fn board_custom_fields(&self, board_id: &str) -> Result<Value, TrelloBSTError> {
+        self.get("the custom fields", &format!("boards/{}/customFields", encode(board_id)), &[])
+    }
+
+    pub fn custom_field(&self, field_id: &str) -> Result<Value, TrelloBSTError> {
+        self.get("the custom field", &format!("customFields/{}", encode(field_id)), &[])
+    }
+
+    pub fn create_custom_field(&self, json_body: &str) -> Result<Value, TrelloBSTError> {
+        parse("the new custom field", try!(utils::rest_api_call_post_json(&self.url("customFields", &[]), json_body)))
+    }
+
+    pub fn add_custom_field_option(&self, field_id: &str, json_body: &str) -> Result<Value, TrelloBSTError> {
+        parse("the new custom field option", try!(utils::rest_api_call_post_json(&self.url(&format!("customFields/{}/options", encode(field_id)), &[]), json_body)))
+    }
+
+    pub fn set_card_custom_field(&self, card_id: &str, field_id: &str, json_body: &str) -> Result<(), TrelloBSTError> {
+        try!(utils::rest_api_call_put_json(&self.url(&format!("cards/{}/customField/{}/item", encode(card_id), encode(field_id)), &[]), json_body));
+        Ok(())
+    }
+
+
+    //Any object as JSON, ex: "doctor" checking lists, labels and custom fields alike.
+    //ex: get_json("the list", &format!("lists/{}", encode(list_id)), &[("fields", "name,idBoard")])
+    pub fn get_json(&self, what: &str, path: &str, params: &[(&str, &str)]) -> Result<Value, TrelloBSTError> {
+        self.get(what, path, params)
+    }
+
+
+    //Tokens, the client's own token
+
+    pub fn token_info(&self) -> Result<Token, TrelloBSTError> {
+        self.get("the token", &format!("tokens/{}", encode(&self.token)), &[])
+    }
+
+    pub fn revoke_token(&self) -> Result<(), TrelloBSTError> {
+        try!(utils::rest_api_call_delete(&self.url(&format!("tokens/{}", encode(&self.token)), &[])));
+        Ok(())
+    }
+
+
+    //Requests
+
+    //ex: https://api.trello.com/1/lists?key=...&token=...&name=Passed%20builds
+    fn url(&self, path: &str, params: &[(&str, &str)]) -> String {
+        let mut url = format!("{}/{}?key={}&token={}", self.base_url, path, encode(&self.api_key), encode(&self.token));
+        for &(name, value) in params {
+            url.push_str(&format!("&{}={}", name, encode(value)));
+        }
+        url
+    }
+
+    fn get<T: Deserialize>(&self, what: &str, path: &str, params: &[(&str, &str)]) -> Result<T, TrelloBSTError> {
+        parse(what, try!(utils::rest_api_call_get(&self.url(path, params))))
+    }
+
+    fn post<T: Deserialize>(&self, what: &str, path: &str, params: &[(&str, &str)]) -> Result<T, TrelloBSTError> {
+        parse(what, try!(utils::rest_api_call_post(&self.url(path, params))))
+    }
+
+    //Gets every page of a card or action listing, the only collections Trello pages.
+    //NOTE: Given "limit" and "before", Trello returns the items with a smaller id, newest first. Ids start with their
+    //      creation time so the smallest id seen is where the next page starts. Items already seen are skipped in case
+    //      an endpoint ignores "before".
+    //      A board's lists, labels, members and custom fields and a card's attachments are not paged by Trello, those
+    //      are fetched whole with get.
+    fn get_paginated<T: Deserialize, F: Fn(&T) -> String>(&self, what: &str, path: &str, params: &[(&str, &str)], page_size: usize, id_of: F) -> Result<Vec<T>, TrelloBSTError> {
+
+        let     limit    = page_size.to_string();
+        let mut items    = Vec::new();
+        let mut seen_ids = BTreeSet::new();
+        let mut before   = String::new();
+
+        loop {
+            let page: Vec<T> = {
+                let mut page_params = params.to_vec();
+                page_params.push(("limit", &limit[..]));
+                if !before.is_empty() {
+                    page_params.push(("before", &before[..]));
+                }
+                try!(self.get(what, path, &page_params))
+            };
+
+            let     is_last_page  = page.len() < page_size;
+            let mut has_new_items = false;
+            for item in page {
+                if seen_ids.insert(id_of(&item)) {
+                    items.push(item);
+                    has_new_items = true;
+                }
+            }
+
+            if is_last_page || !has_new_items {
+                return Ok(items);
+            }
+            before = seen_ids.iter().next().cloned().unwrap_or(String::new());
+        }
+    }
+}
+
+
+////////////////////////////////////////////////////////////
+//                       Functions                        //
+////////////////////////////////////////////////////////////
+
+fn parse<T: Deserialize>(what: &str, response_body: String) -> Result<T, TrelloBSTError> {
+    match serde_json::from_str(&response_body) {
+        Ok(data) => Ok(data),
+        Err(err) => Err(TrelloBSTError::parse(what, err))
+    }
+}
+
+fn card_json(card_data: &CardData) -> Result<String, TrelloBSTError> {
+
+    let mut body: BTreeMap<String, Value> = BTreeMap::new();
+    body.insert("idList".to_string(),   Value::String(card_data.list_id.clone()));
+    body.insert("name".to_string(),     Value::String(card_data.name.clone()));
+    body.insert("desc".to_string(),     Value::String(card_data.desc.clone()));
+    body.insert("idLabels".to_string(), Value::String(card_data.label_ids.join(",")));
+    body.insert("pos".to_string(),      Value::String("top".to_string()));
+
+    //Assigned members, if any
+    if !card_data.member_ids.is_empty() {
+        body.insert("idMembers".to_string(), Value::String(card_data.member_ids.join(",")));
+    }
+
+    match serde_json::to_string(&Value::Object(body)) {
+        Ok(json_body) => Ok(json_body),
+        Err(err)      => Err(TrelloBSTError::parse("the card", err))
+    }
+}
+
+//Percent-encodes a query value or a path segment.
+pub fn encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn bool_str(value: bool) -> &'static str {
+    if value {"true"} else {"false"}
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use utils::mock_server::{response, MockServer};
+
+    #[test]
+    fn calls_the_base_url_with_encoded_params() {
+        let server = MockServer::start(vec![response("200 OK", &[], "{\"id\": \"42\", \"username\": \"alex\"}")]);
+        let client = TrelloClient::with_base_url("key", "to ken", &format!("{}/1/", server.url));
+
+        let member = client.me().unwrap();
+        assert_eq!(member.id, "42");
+        assert_eq!(member.username, "alex");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /1/members/me?key=key&token=to+ken&fields=username%2CfullName "));
+    }
+
+    #[test]
+    fn deletes_card_attachments() {
+        let server = MockServer::start(vec![response("200 OK", &[], "[{\"id\": \"a1\", \"name\": \"description.txt\"}]"),
+                                            response("200 OK", &[], "{}")]);
+        let client = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+
+        let attachments = client.card_attachments("c1").unwrap();
+        assert_eq!(attachments[0].name, "description.txt");
+        client.delete_attachment("c1", &attachments[0].id).unwrap();
+
+        let requests = server.requests();
+        assert!(requests[0].starts_with("GET /1/cards/c1/attachments?"));
+        assert!(requests[1].starts_with("DELETE /1/cards/c1/attachments/a1?"));
+    }
+
+    #[test]
+    fn card_listings_are_paged_by_id() {
+        let server = MockServer::start(vec![response("200 OK", &[], "[{\"id\": \"c4\", \"name\": \"4\"}, {\"id\": \"c3\", \"name\": \"3\"}]"),
+                                            response("200 OK", &[], "[{\"id\": \"c2\", \"name\": \"2\"}, {\"id\": \"c1\", \"name\": \"1\"}]"),
+                                            response("200 OK", &[], "[]")]);
+        let client = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+
+        let cards: Vec<Card> = client.get_paginated("the cards", "lists/l1/cards", &[], 2, |card: &Card| card.id.clone()).unwrap();
+        assert_eq!(cards.iter().map(|card| &card.id[..]).collect::<Vec<&str>>(), vec!["c4", "c3", "c2", "c1"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].contains("limit=2") && !requests[0].contains("before="));
+        assert!(requests[1].contains("before=c3"));
+        assert!(requests[2].contains("before=c1"));
+    }
+
+    #[test]
+    fn paging_stops_when_before_is_ignored() {
+        let page   = "[{\"id\": \"c2\", \"name\": \"2\"}, {\"id\": \"c1\", \"name\": \"1\"}]";
+        let server = MockServer::start(vec![response("200 OK", &[], page), response("200 OK", &[], page)]);
+        let client = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+
+        let cards: Vec<Card> = client.get_paginated("the cards", "lists/l1/cards", &[], 2, |card: &Card| card.id.clone()).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn list_cards_are_in_list_order() {
+        let server = MockServer::start(vec![response("200 OK", &[], "[{\"id\": \"c2\", \"name\": \"2\", \"pos\": 2048}, {\"id\": \"c1\", \"name\": \"1\", \"pos\": 16}]")]);
+        let client = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+
+        let cards = client.list_cards("l1").unwrap();
+        assert_eq!(cards[0].id, "c1");
+        assert_eq!(cards[1].id, "c2");
+    }
+
+    #[test]
+    fn card_creation_is_not_retried_on_server_errors() {
+        let server    = MockServer::start(vec![response("500 Internal Server Error", &[], ""),
+                                               response("200 OK", &[], "{\"id\": \"1\"}")]);
+        let client    = TrelloClient::with_base_url("key", "token", &format!("{}/1", server.url));
+        let card_data = CardData {
+            list_id:    "list".to_string(),
+            name:       "Build #1".to_string(),
+            desc:       String::new(),
+            label_ids:  Vec::new(),
+            member_ids: Vec::new()
+        };
+
+        assert!(client.create_card(&card_data).is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+}
@@ -23,14 +23,21 @@
     SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
+use std::cmp::min;
 use std::collections::BTreeMap;
+use std::env;
 use std::io::{self, Read, Write};
 use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 extern crate hyper;
 use hyper::Client;
+use hyper::client::Body;
 use hyper::client::IntoUrl;
 use hyper::header::Headers;
+pub use hyper::method::Method;
 
 extern crate serde;
 extern crate serde_json;
@@ -38,6 +45,8 @@ use serde_json::Value;
 
 extern crate term;
 
+use error::{self, TrelloBSTError};
+
 include!("utils_macros.rs");
 
 
@@ -123,7 +132,6 @@ impl<T> MenuBuilder<T> {
         self.menu_items.insert(self.menu_item_counter, menu_item);
     }
 
-    #[allow(dead_code)] //Rustc is reporting dead code even though it is used in trello.in.rs
     pub fn add_entry_color(&mut self, text_color: u16, name: String, entry_object: T) {
         let menu_item = MenuBuilderItem {
             entry_name: name,
@@ -167,7 +175,7 @@ impl<T> MenuBuilder<T> {
 
         //Return object according to input
         if option == 0 {
-            exit(0);
+            exit(TrelloBSTError::UserAbort.exit_code());
         } else {
              match self.menu_items.get_mut(&option) {
                  Some(obj) => {
@@ -187,184 +195,424 @@ impl<T> MenuBuilder<T> {
 //                       Functions                        //
 ////////////////////////////////////////////////////////////
 
-#[allow(dead_code)]
-pub fn rest_api_call_get(api_call: &String) -> Result<String, &'static str> {
+//Number of attempts made for a retryable API call, see set_max_attempts.
+static MAX_ATTEMPTS: AtomicUsize = ATOMIC_USIZE_INIT;
 
-    let     http_client   = Client::new();
-    let mut response_body = String::new();
+const DEFAULT_MAX_ATTEMPTS: usize = 5;
+const BASE_RETRY_DELAY_MS:  u64   = 500;
+const MAX_RETRY_DELAY_MS:   u64   = 30000;
+const MAX_RETRY_AFTER_MS:   u64   = 120000;
 
-    let api_call_url = match api_call.into_url() {
-        Ok(url) => url,
-        Err(_)  => return Err("Error while parsing API call url.")
-    };
+//How a failed API call is retried.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts:  usize,
+    pub base_delay_ms: u64,
+    pub max_delay_ms:  u64
+}
 
-    let mut response = match http_client.get(api_call_url).send() {
-        Ok(res) => res,
-        Err(_)  => return Err("Error calling the API.")
-    };
+impl RetryPolicy {
 
-    match response.read_to_string(&mut response_body) {
-        Ok(_)  => (),
-        Err(_) => return Err("Error converting the API response to a string.")
+    //Policy used by the rest_api_call_* functions.
+    //The attempt limit comes from set_max_attempts, else from the TRELLOBST_MAX_ATTEMPTS env var.
+    pub fn current() -> RetryPolicy {
+        let mut max_attempts = MAX_ATTEMPTS.load(Ordering::SeqCst);
+        if max_attempts == 0 {
+            max_attempts = env::var("TRELLOBST_MAX_ATTEMPTS").ok().and_then(|val| val.parse::<usize>().ok()).unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        }
+        RetryPolicy {
+            max_attempts:  if max_attempts == 0 {1} else {max_attempts},
+            base_delay_ms: BASE_RETRY_DELAY_MS,
+            max_delay_ms:  MAX_RETRY_DELAY_MS
+        }
     }
 
-    if response_body == "invalid key" {
-        return Err("Error, the API key is invalid.");
+    //Exponential backoff with jitter, between half and all of base_delay * 2^(attempt - 1).
+    pub fn delay_ms(&self, attempt: usize) -> u64 {
+        let exponent = if attempt > 16 {16} else {attempt.saturating_sub(1) as u32};
+        let delay    = min(self.base_delay_ms.saturating_mul(1u64 << exponent), self.max_delay_ms);
+        delay / 2 + jitter(delay / 2 + 1)
     }
+}
 
-    if response_body == "invalid token" {
-        return Err("The api token is invalid.");
-    }
+//Sets the number of attempts made for retryable API calls, 0 restores the default.
+pub fn set_max_attempts(max_attempts: usize) {
+    MAX_ATTEMPTS.store(max_attempts, Ordering::SeqCst);
+}
 
-    Ok(response_body)
+//Calls the API with the current retry policy.
+//NOTE: Transport errors and 5xx responses are only retried for idempotent methods (GET, PUT, DELETE), 429 responses always are.
+pub fn rest_api_call(method: Method, api_call: &String, header: Headers, body: Option<&[u8]>) -> Result<String, TrelloBSTError> {
+    rest_api_call_with_policy(method, api_call, header, body, &RetryPolicy::current())
 }
 
-#[allow(dead_code)]
-pub fn rest_api_call_get_with_header(api_call: &String, header: Headers) -> Result<String, &'static str> {
+pub fn rest_api_call_with_policy(method: Method, api_call: &String, header: Headers, body: Option<&[u8]>, policy: &RetryPolicy) -> Result<String, TrelloBSTError> {
 
-    let     http_client   = Client::new();
-    let mut response_body = String::new();
+    let http_client   = Client::new();
+    let is_idempotent = method == Method::Get || method == Method::Put || method == Method::Delete;
+    let request_desc  = format!("{} {}", method, error::redact_url(api_call));
 
-    let api_call_url = match api_call.into_url() {
-        Ok(url) => url,
-        Err(_)  => return Err("Error while parsing API call url.")
-    };
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let is_last_attempt = attempt >= policy.max_attempts;
 
-    let mut response = match http_client.get(api_call_url)
-                     .headers(header)
-                     .send() {
-        Ok(res) => res,
-        Err(_)  => {
-            return Err("Error calling the API.");
+        let api_call_url = match api_call.into_url() {
+            Ok(url)  => url,
+            Err(err) => return Err(TrelloBSTError::parse(&format!("the API call url ({})", request_desc), err))
+        };
+
+        let mut request = http_client.request(method.clone(), api_call_url).headers(header.clone());
+        if let Some(body) = body {
+            request = request.body(Body::BufBody(body, body.len()));
         }
-    };
 
-    match response.read_to_string(&mut response_body) {
-        Ok(_)  => (),
-        Err(_) => return Err("Error converting the API response to a string.")
-    }
+        let mut response = match request.send() {
+            Ok(res)  => res,
+            Err(err) => {
+                if is_idempotent && !is_last_attempt {
+                    thread::sleep(Duration::from_millis(policy.delay_ms(attempt)));
+                    continue;
+                }
+                return Err(TrelloBSTError::Transport {request: request_desc, cause: err.to_string()});
+            }
+        };
 
-    if response_body == "invalid key" {
-        return Err("Error, the API key is invalid.");
-    }
+        let mut response_body = String::new();
+        match response.read_to_string(&mut response_body){
+            Ok(_)    => (),
+            Err(err) => {
+                if is_idempotent && !is_last_attempt {
+                    thread::sleep(Duration::from_millis(policy.delay_ms(attempt)));
+                    continue;
+                }
+                return Err(TrelloBSTError::Transport {request: request_desc, cause: format!("Error reading the response: {}", err)});
+            }
+        }
+
+        if response_body == "invalid key" || response_body == "invalid token" {
+            return Err(TrelloBSTError::Api {request: request_desc, message: response_body});
+        }
+
+        let status_code = response.status.to_u16();
+        if response.status.is_success() {
+            return Ok(response_body);
+        }
+
+        //Rate limited or server error, retry if we can
+        let is_retryable = status_code == 429 || (status_code >= 500 && is_idempotent);
+        if is_retryable && !is_last_attempt {
+            let delay_ms = match retry_after_ms(&response.headers) {
+                Some(retry_after) => min(retry_after, MAX_RETRY_AFTER_MS),
+                None              => policy.delay_ms(attempt)
+            };
+            thread::sleep(Duration::from_millis(delay_ms));
+            continue;
+        }
 
-    if response_body == "invalid token" {
-        return Err("The api token is invalid.");
+        return Err(TrelloBSTError::HttpStatus {request: request_desc, status: status_code, message: error::api_message(&response_body)});
     }
+}
 
-    Ok(response_body)
+#[allow(dead_code)]
+pub fn rest_api_call_get(api_call: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Get, api_call, Headers::new(), Option::None)
 }
 
 #[allow(dead_code)]
-pub fn rest_api_call_post(api_call: &String) -> Result<String, &'static str> {
+pub fn rest_api_call_get_with_header(api_call: &String, header: Headers) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Get, api_call, header, Option::None)
+}
 
-    let     http_client   = Client::new();
-    let mut response_body = String::new();
+#[allow(dead_code)]
+pub fn rest_api_call_post(api_call: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Post, api_call, Headers::new(), Option::None)
+}
 
-    let api_call_url = match api_call.into_url() {
-        Ok(url) => url,
-        Err(_)  => return Err("Error while parsing API call url.")
-    };
+#[allow(dead_code)]
+pub fn rest_api_call_post_with_header(api_call: &String, header: Headers) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Post, api_call, header, Option::None)
+}
 
-    let mut response = match http_client.post(api_call_url).send() {
-        Ok(res) => res,
-        Err(_)  => return Err("Error calling the API.")
-    };
+#[allow(dead_code)]
+pub fn rest_api_call_post_multipart(api_call: &String, field_name: &str, file_name: &str, file_data: &[u8]) -> Result<String, TrelloBSTError> {
 
-    match response.read_to_string(&mut response_body){
-        Ok(_)  => (),
-        Err(_) => return Err("Error converting the API response to a string.")
-    }
+    let mut header   = Headers::new();
+    let     boundary = "----TrelloBSTFormBoundary7MA4YWxkTrZu0gW";
 
-    if response_body == "invalid key" {
-        return Err("Error, the API key is invalid.");
-    }
+    //Build the multipart/form-data body.
+    let mut body: Vec<u8> = Vec::with_capacity(file_data.len() + 256);
+    body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+    body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", field_name, file_name.replace("\"", "")).as_bytes());
+    body.extend_from_slice(b"Content-Type: application/octet-stream\r\n\r\n");
+    body.extend_from_slice(file_data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
 
-    if response_body == "invalid token" {
-        return Err("The api token is invalid.");
-    }
+    header.set_raw("Content-Type", vec![format!("multipart/form-data; boundary={}", boundary).into_bytes()]);
 
-    Ok(response_body)
+    rest_api_call(Method::Post, api_call, header, Option::Some(&body[..]))
 }
 
 #[allow(dead_code)]
-pub fn rest_api_call_post_with_header(api_call: &String, header: Headers) -> Result<String, &'static str> {
+pub fn rest_api_call_post_json(api_call: &String, json_body: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Post, api_call, json_header(), Option::Some(json_body.as_bytes()))
+}
 
-    let     http_client   = Client::new();
-    let mut response_body = String::new();
+#[allow(dead_code)]
+pub fn rest_api_call_put(api_call: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Put, api_call, Headers::new(), Option::None)
+}
+
+#[allow(dead_code)]
+pub fn rest_api_call_put_with_header(api_call: &String, header: Headers) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Put, api_call, header, Option::None)
+}
+
+#[allow(dead_code)]
+pub fn rest_api_call_put_json(api_call: &String, json_body: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Put, api_call, json_header(), Option::Some(json_body.as_bytes()))
+}
+
+#[allow(dead_code)]
+pub fn rest_api_call_delete(api_call: &String) -> Result<String, TrelloBSTError> {
+    rest_api_call(Method::Delete, api_call, Headers::new(), Option::None)
+}
+
+fn json_header() -> Headers {
+    let mut header = Headers::new();
+    header.set_raw("Content-Type", vec![b"application/json".to_vec()]);
+    header
+}
+
+//Gets the delay asked by a Retry-After header, only the delay in seconds form is supported.
+fn retry_after_ms(headers: &Headers) -> Option<u64> {
+    let value = match headers.get_raw("Retry-After") {
+        Some(value) if !value.is_empty() => String::from_utf8_lossy(&value[0]).trim().to_string(),
+        _                                => return Option::None
+    };
+    value.parse::<u64>().ok().map(|seconds| seconds.saturating_mul(1000))
+}
 
-    let api_call_url = match api_call.into_url() {
-        Ok(url) => url,
-        Err(_)  => return Err("Error while parsing API call url.")
+//Pseudo random number in [0, range), seeded from the clock since this only spreads retries apart.
+fn jitter(range: u64) -> u64 {
+    let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.subsec_nanos() as u64,
+        Err(_)       => 0
     };
+    nanos.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407) % range
+}
+
+#[allow(dead_code)]
+pub fn get_single_json_value_as_string(json_string: &String, field: &str) -> Result<String, TrelloBSTError>{
 
-    let mut response = match http_client.post(api_call_url)
-            .headers(header)
-            .send() {
-        Ok(res) => res,
-        Err(_)  => return Err("Error calling the API.")
+    let data: Value = match serde_json::from_str(&json_string){
+        Ok(data) => data,
+        Err(err) => return Err(TrelloBSTError::parse("the JSON data", err))
     };
 
-    match response.read_to_string(&mut response_body){
-        Ok(_)  => (),
-        Err(_) => return Err("Error converting the API response to a string.")
+    let object            = try!(data.as_object().ok_or(TrelloBSTError::parse("the JSON data", "It does not describe an object."))).clone();
+    let json_value: Value = try!(object.get(field).ok_or(TrelloBSTError::parse("the JSON data", format!("The \"{}\" field has not been found.", field)))).clone();
+
+    Ok(try!(json_value.as_str().ok_or(TrelloBSTError::parse("the JSON data", format!("The \"{}\" field's value is not a string.", field)))).to_string())
+}
+
+
+////////////////////////////////////////////////////////////
+//                         Tests                          //
+////////////////////////////////////////////////////////////
+
+//HTTP server on 127.0.0.1 answering each request with the next scripted response, for the API call tests.
+#[cfg(test)]
+pub mod mock_server {
+
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    pub struct MockServer {
+        pub url:  String,
+        requests: Arc<Mutex<Vec<String>>>
     }
 
-    if response_body == "invalid key" {
-        return Err("Error, the API key is invalid.");
+    impl MockServer {
+
+        //The server stops once every response has been sent, later requests fail to connect.
+        pub fn start(responses: Vec<String>) -> MockServer {
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url      = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let server_requests = requests.clone();
+            thread::spawn(move || {
+                for response in responses {
+                    let mut stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_)          => return
+                    };
+                    let request_line = read_request(&mut stream);
+                    server_requests.lock().unwrap().push(request_line);
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            });
+
+            MockServer {
+                url:      url,
+                requests: requests
+            }
+        }
+
+        //Request lines received so far, ex: "GET /1/members/me?key=... HTTP/1.1"
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
     }
 
-    if response_body == "invalid token" {
-        return Err("The api token is invalid.");
+    //ex: response("429 Too Many Requests", &["Retry-After: 1"], "")
+    pub fn response(status: &str, headers: &[&str], body: &str) -> String {
+        let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for header in headers {
+            response.push_str(&format!("{}\r\n", header));
+        }
+        format!("{}\r\n{}", response, body)
     }
 
-    Ok(response_body)
+    //Reads the whole request so the client doesn't see a reset connection, returns its request line.
+    fn read_request(stream: &mut TcpStream) -> String {
+
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+        let mut request: Vec<u8> = Vec::new();
+        let mut buffer           = [0; 4096];
+        loop {
+            let head_end = request.windows(4).position(|window| window == b"\r\n\r\n");
+            if let Some(head_end) = head_end {
+                let head           = String::from_utf8_lossy(&request[..head_end]).to_string();
+                let content_length = head.lines()
+                                         .filter_map(|line| {
+                                             let mut parts = line.splitn(2, ':');
+                                             match (parts.next(), parts.next()) {
+                                                 (Some(name), Some(value)) if name.trim().to_lowercase() == "content-length" => value.trim().parse::<usize>().ok(),
+                                                 _                                                                           => None
+                                             }
+                                         })
+                                         .next()
+                                         .unwrap_or(0);
+                if request.len() >= head_end + 4 + content_length {
+                    return head.lines().next().unwrap_or("").to_string();
+                }
+            }
+
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => return String::from_utf8_lossy(&request).lines().next().unwrap_or("").to_string(),
+                Ok(read)       => request.extend_from_slice(&buffer[..read])
+            }
+        }
+    }
 }
 
-#[allow(dead_code)]
-pub fn rest_api_call_put_with_header(api_call: &String, header: Headers) -> Result<String, &'static str> {
+#[cfg(test)]
+mod tests {
 
-    let     http_client   = Client::new();
-    let mut response_body = String::new();
+    use std::time::Instant;
 
-    let api_call_url = match api_call.into_url() {
-        Ok(url) => url,
-        Err(_)  => return Err("Error while parsing API call url.")
-    };
+    use super::*;
+    use super::mock_server::{response, MockServer};
 
-    let mut response = match http_client.put(api_call_url)
-                     .headers(header)
-                     .send() {
-        Ok(res) => res,
-        Err(_)  => return Err("Error calling the API.")
-    };
+    fn policy(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts:  max_attempts,
+            base_delay_ms: 1,
+            max_delay_ms:  1
+        }
+    }
 
-    match response.read_to_string(&mut response_body){
-        Ok(_)  => (),
-        Err(_) => return Err("Error converting the API response to a string.")
+    fn call(method: Method, server: &MockServer, max_attempts: usize) -> Result<String, TrelloBSTError> {
+        rest_api_call_with_policy(method, &format!("{}/1/cards?token=secret", server.url), Headers::new(), Option::None, &policy(max_attempts))
     }
 
-    if response_body == "invalid key" {
-        return Err("Error, the API key is invalid.");
+    #[test]
+    fn rate_limit_waits_for_retry_after() {
+        let server = MockServer::start(vec![response("429 Too Many Requests", &["Retry-After: 1"], ""),
+                                            response("200 OK", &[], "{}")]);
+        let start  = Instant::now();
+
+        assert_eq!(call(Method::Get, &server, 5).unwrap(), "{}");
+        assert_eq!(server.requests().len(), 2);
+        assert!(start.elapsed().as_secs() >= 1);
     }
 
-    if response_body == "invalid token" {
-        return Err("The api token is invalid.");
+    #[test]
+    fn rate_limit_is_retried_for_posts() {
+        let server = MockServer::start(vec![response("429 Too Many Requests", &["Retry-After: 0"], ""),
+                                            response("200 OK", &[], "{}")]);
+
+        assert!(call(Method::Post, &server, 5).is_ok());
+        assert_eq!(server.requests().len(), 2);
     }
 
-    Ok(response_body)
-}
+    #[test]
+    fn server_errors_are_retried_up_to_max_attempts() {
+        let server = MockServer::start(vec![response("500 Internal Server Error", &[], "oops"),
+                                            response("502 Bad Gateway", &[], "oops"),
+                                            response("503 Service Unavailable", &[], "oops"),
+                                            response("200 OK", &[], "{}")]);
+
+        match call(Method::Get, &server, 3) {
+            Err(TrelloBSTError::HttpStatus {status, ref request, ..}) => {
+                assert_eq!(status, 503);
+                assert!(request.contains("token=<redacted>"));
+            }
+            other => panic!("expected an HTTP status error, got {:?}", other)
+        }
+        assert_eq!(server.requests().len(), 3);
+    }
 
-#[allow(dead_code)]
-pub fn get_single_json_value_as_string(json_string: &String, field: &str) -> Result<String, &'static str>{
+    #[test]
+    fn server_errors_then_success() {
+        let server = MockServer::start(vec![response("503 Service Unavailable", &[], ""),
+                                            response("200 OK", &[], "[]")]);
 
-    let data: Value = match serde_json::from_str(&json_string){
-        Ok(data) => data,
-        Err(_)   => return Err("Error parsing the JSON data")
-    };
+        assert_eq!(call(Method::Put, &server, 3).unwrap(), "[]");
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn server_errors_are_not_retried_for_posts() {
+        let server = MockServer::start(vec![response("500 Internal Server Error", &[], ""),
+                                            response("200 OK", &[], "{}")]);
+
+        match call(Method::Post, &server, 5) {
+            Err(TrelloBSTError::HttpStatus {status, ..}) => assert_eq!(status, 500),
+            other                                        => panic!("expected an HTTP status error, got {:?}", other)
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
 
-    let object            = try!(data.as_object().ok_or("Error: JSON data does not describe an object.")).clone();
-    let json_value: Value = try!(object.get(field).ok_or("Error: The field has not been found in the JSON data.")).clone();
+    #[test]
+    fn client_errors_are_not_retried() {
+        let server = MockServer::start(vec![response("404 Not Found", &[], "{\"message\": \"missing\"}"),
+                                            response("200 OK", &[], "{}")]);
 
-    Ok(try!(json_value.as_str().ok_or("Error: The field's value is not a string.")).to_string())
+        match call(Method::Get, &server, 5) {
+            Err(TrelloBSTError::HttpStatus {status, ref message, ..}) => {
+                assert_eq!(status, 404);
+                assert_eq!(message, "missing");
+            }
+            other => panic!("expected an HTTP status error, got {:?}", other)
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn invalid_token_is_an_api_error() {
+        let server = MockServer::start(vec![response("401 Unauthorized", &[], "invalid token")]);
+
+        match call(Method::Get, &server, 5) {
+            Err(TrelloBSTError::Api {ref message, ..}) => assert_eq!(message, "invalid token"),
+            other                                      => panic!("expected an API error, got {:?}", other)
+        }
+    }
 }